@@ -5,10 +5,11 @@
 
 ENDPOINT="http://o2.pool-toast.service.capi-prod-storage.kube.atla.twitter.biz"
 BUCKET="hjiang-benchmark-o2-nov-19-toast63"
-ACCESS_KEY="changeme"
-SECRET_KEY="changeme"
 REGION="atla"
 
+# Credentials come from the AWS provider chain: export AWS_ACCESS_KEY_ID and
+# AWS_SECRET_ACCESS_KEY, or set AWS_PROFILE to a profile in ~/.aws/credentials
+
 echo "Running quick S3 tests (10 seconds, 1GiB objects)..."
 echo "======================================================="
 
@@ -18,8 +19,6 @@ echo "Testing PUT (1GiB objects, 8MiB parts, 10 concurrent, 10 seconds)..."
 cargo run --release -- put \
   --endpoint "$ENDPOINT" \
   --bucket "$BUCKET" \
-  --access-key "$ACCESS_KEY" \
-  --secret-key "$SECRET_KEY" \
   --region "$REGION" \
   --duration-secs 10 \
   --concurrent 10 \
@@ -33,8 +32,6 @@ echo "Testing GET with range query (first 100 bytes, 10 concurrent, 10 seconds).
 cargo run --release -- get \
   --endpoint "$ENDPOINT" \
   --bucket "$BUCKET" \
-  --access-key "$ACCESS_KEY" \
-  --secret-key "$SECRET_KEY" \
   --region "$REGION" \
   --duration-secs 10 \
   --concurrent 10 \
@@ -47,8 +44,6 @@ echo "Testing LIST (10 concurrent, 10 seconds)..."
 cargo run --release -- list \
   --endpoint "$ENDPOINT" \
   --bucket "$BUCKET" \
-  --access-key "$ACCESS_KEY" \
-  --secret-key "$SECRET_KEY" \
   --region "$REGION" \
   --duration-secs 10 \
   --concurrent 10 \
@@ -3,10 +3,11 @@
 # S3 Configuration (based on your warp config)
 ENDPOINT="http://o2.pool-toast.service.capi-prod-storage.kube.atla.twitter.biz"
 BUCKET="hjiang-benchmark-o2-nov-19-toast63"
-ACCESS_KEY="changeme"
-SECRET_KEY="changeme"
 REGION="atla"
 
+# Credentials come from the AWS provider chain: export AWS_ACCESS_KEY_ID and
+# AWS_SECRET_ACCESS_KEY, or set AWS_PROFILE to a profile in ~/.aws/credentials
+
 # Benchmark Configuration
 DURATION=60
 CONCURRENT=200
@@ -22,8 +23,6 @@ echo "1. Running PUT benchmark (1 GiB objects, 200 concurrent)..."
 cargo run --release -- put \
   --endpoint "$ENDPOINT" \
   --bucket "$BUCKET" \
-  --access-key "$ACCESS_KEY" \
-  --secret-key "$SECRET_KEY" \
   --region "$REGION" \
   --duration-secs $DURATION \
   --concurrent $CONCURRENT \
@@ -37,8 +36,6 @@ echo "2. Running GET benchmark with range query (first 100 bytes, 200 concurrent
 cargo run --release -- get \
   --endpoint "$ENDPOINT" \
   --bucket "$BUCKET" \
-  --access-key "$ACCESS_KEY" \
-  --secret-key "$SECRET_KEY" \
   --region "$REGION" \
   --duration-secs $DURATION \
   --concurrent $CONCURRENT \
@@ -51,8 +48,6 @@ echo "3. Running LIST benchmark (10 concurrent)..."
 cargo run --release -- list \
   --endpoint "$ENDPOINT" \
   --bucket "$BUCKET" \
-  --access-key "$ACCESS_KEY" \
-  --secret-key "$SECRET_KEY" \
   --region "$REGION" \
   --duration-secs $DURATION \
   --concurrent 10 \
@@ -0,0 +1,228 @@
+//! Latency-SLO autotuning: run a workload in short control intervals and adjust
+//! concurrency between them until the highest level that keeps the chosen latency
+//! percentile and error rate within bounds is found.
+
+use super::{RunArgs, Workload};
+use anyhow::Result;
+use clap::{Args, ValueEnum};
+use std::time::Duration;
+
+/// Feedback controller used to pick the next concurrency level
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub(crate) enum Strategy {
+    /// Slow start, then additive increase / multiplicative decrease; tracks the SLO continuously
+    Aimd,
+    /// Double until the SLO is violated, then bisect between the last passing and first failing level
+    Binary,
+}
+
+#[derive(Args)]
+pub(crate) struct AutotuneArgs {
+    /// Latency objective for the chosen percentile (e.g. 200ms)
+    #[arg(long, value_parser = humantime::parse_duration)]
+    target_latency: Duration,
+    /// Latency percentile compared against --target-latency
+    #[arg(long, default_value = "99")]
+    percentile: f64,
+    /// Highest error rate (fraction) an interval may have and still pass
+    #[arg(long, default_value = "0.01")]
+    max_error_rate: f64,
+    #[arg(long, value_enum, default_value = "binary")]
+    strategy: Strategy,
+    /// Initial concurrency
+    #[arg(long, default_value = "1")]
+    start: usize,
+    /// Upper bound on concurrency
+    #[arg(long, default_value = "1024")]
+    max_concurrency: usize,
+    /// Length of each control interval in seconds
+    #[arg(long, default_value = "10", value_parser = clap::value_parser!(u64).range(1..))]
+    interval_secs: u64,
+    /// Maximum number of control intervals
+    #[arg(long, default_value = "20")]
+    max_steps: usize,
+    /// Additive increase per passing interval (AIMD)
+    #[arg(long, default_value = "1")]
+    increase: usize,
+    /// Multiplicative decrease factor after a failing interval (AIMD)
+    #[arg(long, default_value = "0.5")]
+    decrease: f64,
+    /// Workload to tune; its --warmup/--cooldown apply to each interval and --concurrent is ignored
+    #[command(subcommand)]
+    workload: Workload,
+}
+
+/// Picks the next concurrency from the outcome of the last interval
+struct Controller {
+    strategy: Strategy,
+    max: usize,
+    increase: usize,
+    decrease: f64,
+    slow_start: bool,
+    /// Highest passing and lowest failing level seen so far (binary search bounds)
+    passing: Option<usize>,
+    failing: Option<usize>,
+}
+
+impl Controller {
+    /// Next concurrency to try after running `current`, or `None` once the search has converged
+    fn next(&mut self, current: usize, passed: bool) -> Option<usize> {
+        match self.strategy {
+            Strategy::Aimd => {
+                let next = if passed && self.slow_start {
+                    current * 2
+                } else if passed {
+                    current + self.increase.max(1)
+                } else {
+                    self.slow_start = false;
+                    ((current as f64 * self.decrease) as usize).max(1)
+                };
+                Some(next.min(self.max))
+            }
+            Strategy::Binary => {
+                if passed {
+                    self.passing = Some(self.passing.map_or(current, |p| p.max(current)));
+                } else {
+                    self.failing = Some(self.failing.map_or(current, |f| f.min(current)));
+                }
+                let low = self.passing.unwrap_or(0);
+                match self.failing {
+                    None if current >= self.max => None,
+                    None => Some((current * 2).min(self.max)),
+                    Some(high) if high <= low + 1 => None,
+                    Some(high) => Some(low + (high - low) / 2),
+                }
+            }
+        }
+    }
+}
+
+/// Measurements of one control interval
+struct Interval {
+    concurrency: usize,
+    ops_per_sec: f64,
+    mb_per_sec: f64,
+    latency_ms: f64,
+    error_rate: f64,
+    passed: bool,
+}
+
+pub(crate) async fn run_autotune(args: AutotuneArgs) -> Result<()> {
+    tune(args).await?;
+    Ok(())
+}
+
+/// Run the search and print its intervals; returns the concurrency of the sustained operating point, if any
+pub(crate) async fn tune(args: AutotuneArgs) -> Result<Option<usize>> {
+    if !(0.0..=100.0).contains(&args.percentile) {
+        anyhow::bail!("--percentile must be between 0 and 100");
+    }
+    if !(0.0..=1.0).contains(&args.max_error_rate) {
+        anyhow::bail!("--max-error-rate must be between 0 and 1");
+    }
+    if !(0.0..1.0).contains(&args.decrease) {
+        anyhow::bail!("--decrease must be in [0, 1)");
+    }
+    let target_ms = args.target_latency.as_secs_f64() * 1000.0;
+    let quantile = args.percentile / 100.0;
+
+    println!("Starting latency-SLO autotune...");
+    println!("Endpoint: {}", args.workload.endpoint());
+    println!("Objective: p{} <= {:.2} ms, error rate <= {:.2}%", args.percentile, target_ms, args.max_error_rate * 100.0);
+    println!("Strategy: {:?}, interval: {}s, max steps: {}", args.strategy, args.interval_secs, args.max_steps);
+
+    let mut workload = args.workload.prepare().await?;
+    let label = workload.label();
+    workload.print_config();
+
+    // Every interval is a fixed-length run; keep the workload's warmup/cooldown
+    let run = workload.run_mut();
+    *run = RunArgs {
+        duration_secs: Some(args.interval_secs),
+        ops: None,
+        total_bytes: None,
+        ..*run
+    };
+
+    let mut controller = Controller {
+        strategy: args.strategy,
+        max: args.max_concurrency.max(1),
+        increase: args.increase,
+        decrease: args.decrease,
+        slow_start: true,
+        passing: None,
+        failing: None,
+    };
+    let mut concurrency = args.start.clamp(1, controller.max);
+    let mut intervals: Vec<Interval> = Vec::new();
+
+    for step in 1..=args.max_steps {
+        println!("\n[AUTOTUNE] Step {} of {}: {} concurrent {} operations", step, args.max_steps, concurrency, label);
+        let stats = workload.execute(concurrency).await?;
+
+        let latency_ms = stats.percentile_ms(quantile);
+        let error_rate = stats.error_rate();
+        let passed = stats.operations > stats.errors && latency_ms <= target_ms && error_rate <= args.max_error_rate;
+        println!(
+            "[AUTOTUNE] concurrency {}: {:.2} ops/sec, {:.2} MB/s, p{} {:.2} ms, errors {:.2}% -> {}",
+            concurrency,
+            stats.ops_per_sec(),
+            stats.mb_per_sec(),
+            args.percentile,
+            latency_ms,
+            error_rate * 100.0,
+            if passed { "within SLO" } else { "violates SLO" }
+        );
+        intervals.push(Interval {
+            concurrency,
+            ops_per_sec: stats.ops_per_sec(),
+            mb_per_sec: stats.mb_per_sec(),
+            latency_ms,
+            error_rate,
+            passed,
+        });
+
+        match controller.next(concurrency, passed) {
+            Some(next) => concurrency = next,
+            None => {
+                println!("[AUTOTUNE] Search converged");
+                break;
+            }
+        }
+    }
+
+    println!("\n=== {} Autotune Results ===", label);
+    println!(
+        "{:>5} {:>11} {:>10} {:>10} {:>10} {:>9} {:>8}",
+        "step", "concurrency", "ops/sec", "MB/s", format!("p{} ms", args.percentile), "err rate", "SLO"
+    );
+    for (idx, interval) in intervals.iter().enumerate() {
+        println!(
+            "{:>5} {:>11} {:>10.2} {:>10.2} {:>10.2} {:>8.2}% {:>8}",
+            idx + 1,
+            interval.concurrency,
+            interval.ops_per_sec,
+            interval.mb_per_sec,
+            interval.latency_ms,
+            interval.error_rate * 100.0,
+            if interval.passed { "pass" } else { "fail" }
+        );
+    }
+
+    let best = intervals
+        .iter()
+        .filter(|interval| interval.passed)
+        .max_by(|a, b| a.ops_per_sec.total_cmp(&b.ops_per_sec));
+    match best {
+        Some(best) => println!(
+            "Sustained operating point: {:.2} ops/sec ({:.2} MB/s) at concurrency {} with p{} {:.2} ms",
+            best.ops_per_sec, best.mb_per_sec, best.concurrency, args.percentile, best.latency_ms
+        ),
+        None => {
+            let lowest = intervals.iter().map(|interval| interval.concurrency).min().unwrap_or(concurrency);
+            println!("No interval met the objective; even concurrency {} violates it", lowest);
+        }
+    }
+
+    Ok(best.map(|best| best.concurrency))
+}
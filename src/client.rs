@@ -0,0 +1,312 @@
+//! S3 client behaviour shared by every command: the SDK retry policy and
+//! timeouts, and per-operation accounting of the retries, timeouts and SigV4
+//! signing time that would otherwise be hidden inside the measured latency.
+
+use aws_sdk_s3::config::interceptors::{
+    BeforeSerializationInterceptorContextRef, BeforeTransmitInterceptorContextMut, BeforeTransmitInterceptorContextRef,
+    FinalizerInterceptorContextRef,
+};
+use aws_sdk_s3::config::retry::RetryConfig;
+use aws_sdk_s3::config::timeout::TimeoutConfig;
+use aws_sdk_s3::config::{ConfigBag, Intercept, RuntimeComponents};
+use aws_smithy_runtime_api::box_error::BoxError;
+use aws_smithy_runtime_api::client::orchestrator::Metadata;
+use aws_smithy_runtime_api::client::retries::RequestAttempts;
+use aws_smithy_types::config_bag::{Storable, StoreReplace};
+use clap::{Args, ValueEnum};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// How failed requests are retried by the SDK
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub(crate) enum RetryMode {
+    /// Exponential backoff with jitter, limited by a retry quota
+    Standard,
+    /// Standard retries plus client-side rate limiting after throttling responses
+    Adaptive,
+    /// Every request is attempted exactly once
+    None,
+}
+
+/// Retry policy and timeouts of the S3 client
+#[derive(Args, Clone)]
+pub(crate) struct ClientArgs {
+    /// Maximum attempts per request, including the first one
+    #[arg(long, default_value = "3")]
+    max_attempts: u32,
+    #[arg(long, value_enum, default_value = "standard")]
+    retry_mode: RetryMode,
+    /// Limit on establishing a connection (e.g. 500ms; SDK default 3.1s)
+    #[arg(long, value_parser = humantime::parse_duration)]
+    connect_timeout: Option<Duration>,
+    /// Limit on waiting for the response headers after the request was sent, and
+    /// then for each chunk of the body (e.g. 2s)
+    #[arg(long, value_parser = humantime::parse_duration)]
+    read_timeout: Option<Duration>,
+    /// Limit on a whole request including all retries (e.g. 30s)
+    #[arg(long, value_parser = humantime::parse_duration)]
+    operation_timeout: Option<Duration>,
+}
+
+impl ClientArgs {
+    pub(crate) fn retry_config(&self) -> RetryConfig {
+        match self.retry_mode {
+            RetryMode::Standard => RetryConfig::standard().with_max_attempts(self.max_attempts.max(1)),
+            RetryMode::Adaptive => RetryConfig::adaptive().with_max_attempts(self.max_attempts.max(1)),
+            RetryMode::None => RetryConfig::disabled(),
+        }
+    }
+
+    /// Timeouts to apply; unset ones keep the SDK defaults
+    pub(crate) fn timeout_config(&self) -> TimeoutConfig {
+        let mut builder = TimeoutConfig::builder();
+        if let Some(timeout) = self.connect_timeout {
+            builder = builder.connect_timeout(timeout);
+        }
+        if let Some(timeout) = self.read_timeout {
+            builder = builder.read_timeout(timeout);
+        }
+        if let Some(timeout) = self.operation_timeout {
+            builder = builder.operation_timeout(timeout);
+        }
+        builder.build()
+    }
+}
+
+impl fmt::Display for ClientArgs {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.retry_mode {
+            RetryMode::None => write!(f, "no retries")?,
+            mode => write!(f, "{:?} retries, max {} attempts", mode, self.max_attempts.max(1))?,
+        }
+        let timeouts = [
+            ("connect", self.connect_timeout),
+            ("read", self.read_timeout),
+            ("operation", self.operation_timeout),
+        ];
+        for (name, timeout) in timeouts {
+            if let Some(timeout) = timeout {
+                write!(f, ", {} timeout {}", name, humantime::format_duration(timeout))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Requests of one S3 operation type (PutObject, UploadPart, ...)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct RequestCounts {
+    pub(crate) requests: u64,
+    pub(crate) retries: u64,
+    pub(crate) timeouts: u64,
+    /// Time spent signing, over all attempts. Covers hashing an in-memory payload,
+    /// and the chunk signatures and checksum of aws-chunked bodies as they are sent
+    #[serde(default)]
+    pub(crate) signing: Duration,
+}
+
+impl RequestCounts {
+    pub(crate) fn add(&mut self, other: &RequestCounts) {
+        self.requests += other.requests;
+        self.retries += other.retries;
+        self.timeouts += other.timeouts;
+        self.signing += other.signing;
+    }
+
+    /// Average signing time of an attempt
+    pub(crate) fn signing_per_attempt(&self) -> Duration {
+        let attempts = self.requests + self.retries;
+        if attempts == 0 {
+            return Duration::ZERO;
+        }
+        self.signing / attempts as u32
+    }
+}
+
+/// Raw interceptor events for one operation type
+#[derive(Clone, Copy, Debug, Default)]
+struct Tally {
+    started: u64,
+    finished: u64,
+    retries: u64,
+    attempt_timeouts: u64,
+    signing: Duration,
+}
+
+/// When the current attempt started signing, kept in the request's config bag
+#[derive(Debug)]
+struct SigningStarted(Instant);
+
+impl Storable for SigningStarted {
+    type Storer = StoreReplace<Self>;
+}
+
+/// Requests, retries and timeouts per operation type, fed by [`CountingInterceptor`].
+/// An operation timeout cancels the request before its final interceptors run,
+/// so requests that started but never finished are counted as timed out; the
+/// counts are only exact while no request is in flight.
+#[derive(Debug, Default)]
+pub(crate) struct RequestCounters {
+    tallies: Mutex<BTreeMap<String, Tally>>,
+}
+
+impl RequestCounters {
+    fn update(&self, cfg: &ConfigBag, apply: impl FnOnce(&mut Tally)) {
+        self.update_operation(operation_name(cfg), apply);
+    }
+
+    fn update_operation(&self, operation: &str, apply: impl FnOnce(&mut Tally)) {
+        let mut tallies = self.tallies.lock().unwrap();
+        apply(tallies.entry(operation.to_string()).or_default());
+    }
+
+    pub(crate) fn snapshot(&self) -> BTreeMap<String, RequestCounts> {
+        let tallies = self.tallies.lock().unwrap();
+        tallies
+            .iter()
+            .map(|(operation, tally)| {
+                let counts = RequestCounts {
+                    requests: tally.started,
+                    retries: tally.retries,
+                    timeouts: tally.attempt_timeouts + (tally.started - tally.finished),
+                    signing: tally.signing,
+                };
+                (operation.clone(), counts)
+            })
+            .collect()
+    }
+
+    /// Counts accumulated since `earlier` was taken, leaving out operation types without requests
+    pub(crate) fn since(&self, earlier: &BTreeMap<String, RequestCounts>) -> BTreeMap<String, RequestCounts> {
+        self.snapshot()
+            .into_iter()
+            .map(|(operation, now)| {
+                let before = earlier.get(&operation).copied().unwrap_or_default();
+                let counts = RequestCounts {
+                    requests: now.requests - before.requests,
+                    retries: now.retries - before.retries,
+                    timeouts: now.timeouts - before.timeouts,
+                    signing: now.signing - before.signing,
+                };
+                (operation, counts)
+            })
+            .filter(|(_, counts)| counts.requests > 0)
+            .collect()
+    }
+}
+
+fn operation_name(cfg: &ConfigBag) -> &str {
+    cfg.load::<Metadata>().map_or("Unknown", |metadata| metadata.name())
+}
+
+/// Request extension through which the HTTP client charges the signing work done
+/// while a body is sent (aws-chunked chunk signatures and checksums) to its operation
+#[derive(Clone, Debug)]
+pub(crate) struct BodySigning {
+    counters: Arc<RequestCounters>,
+    operation: String,
+}
+
+impl BodySigning {
+    pub(crate) fn charge(&self, elapsed: Duration) {
+        self.counters.update_operation(&self.operation, |tally| tally.signing += elapsed);
+    }
+}
+
+/// SDK interceptor feeding the counters shared with the workload
+#[derive(Debug)]
+pub(crate) struct CountingInterceptor(pub(crate) Arc<RequestCounters>);
+
+impl Intercept for CountingInterceptor {
+    fn name(&self) -> &'static str {
+        "CountingInterceptor"
+    }
+
+    fn read_before_serialization(
+        &self,
+        _context: &BeforeSerializationInterceptorContextRef<'_>,
+        _runtime_components: &RuntimeComponents,
+        cfg: &mut ConfigBag,
+    ) -> Result<(), BoxError> {
+        self.0.update(cfg, |tally| tally.started += 1);
+        Ok(())
+    }
+
+    fn read_before_attempt(
+        &self,
+        _context: &BeforeTransmitInterceptorContextRef<'_>,
+        _runtime_components: &RuntimeComponents,
+        cfg: &mut ConfigBag,
+    ) -> Result<(), BoxError> {
+        if cfg.load::<RequestAttempts>().is_some_and(|attempts| attempts.attempts() > 1) {
+            self.0.update(cfg, |tally| tally.retries += 1);
+        }
+        Ok(())
+    }
+
+    fn read_before_signing(
+        &self,
+        _context: &BeforeTransmitInterceptorContextRef<'_>,
+        _runtime_components: &RuntimeComponents,
+        cfg: &mut ConfigBag,
+    ) -> Result<(), BoxError> {
+        cfg.interceptor_state().store_put(SigningStarted(Instant::now()));
+        Ok(())
+    }
+
+    fn read_after_signing(
+        &self,
+        _context: &BeforeTransmitInterceptorContextRef<'_>,
+        _runtime_components: &RuntimeComponents,
+        cfg: &mut ConfigBag,
+    ) -> Result<(), BoxError> {
+        if let Some(SigningStarted(started)) = cfg.load::<SigningStarted>() {
+            let elapsed = started.elapsed();
+            self.0.update(cfg, |tally| tally.signing += elapsed);
+        }
+        Ok(())
+    }
+
+    fn modify_before_transmit(
+        &self,
+        context: &mut BeforeTransmitInterceptorContextMut<'_>,
+        _runtime_components: &RuntimeComponents,
+        cfg: &mut ConfigBag,
+    ) -> Result<(), BoxError> {
+        let signing = BodySigning {
+            counters: self.0.clone(),
+            operation: operation_name(cfg).to_string(),
+        };
+        context.request_mut().add_extension(signing);
+        Ok(())
+    }
+
+    fn read_after_attempt(
+        &self,
+        context: &FinalizerInterceptorContextRef<'_>,
+        _runtime_components: &RuntimeComponents,
+        cfg: &mut ConfigBag,
+    ) -> Result<(), BoxError> {
+        let timed_out = match context.output_or_error() {
+            Some(Err(e)) => e.is_timeout_error() || e.as_connector_error().is_some_and(|e| e.is_timeout()),
+            _ => false,
+        };
+        if timed_out {
+            self.0.update(cfg, |tally| tally.attempt_timeouts += 1);
+        }
+        Ok(())
+    }
+
+    fn read_after_execution(
+        &self,
+        _context: &FinalizerInterceptorContextRef<'_>,
+        _runtime_components: &RuntimeComponents,
+        cfg: &mut ConfigBag,
+    ) -> Result<(), BoxError> {
+        self.0.update(cfg, |tally| tally.finished += 1);
+        Ok(())
+    }
+}
@@ -0,0 +1,363 @@
+//! Conditional request workloads. In `create` mode concurrent writers race to
+//! create the same keys with `If-None-Match: *`; in `cas` mode they read a key
+//! and write the next generation back with `If-Match` on the ETag they read, a
+//! compare-and-swap; in `get` mode GETs carry a condition the objects are known
+//! to meet or fail, as cache revalidations do. Outcomes are counted by kind:
+//! success, 304 Not Modified, 412 Precondition Failed and 409 Conflict (a
+//! concurrent conditional write in progress); only other failures are errors.
+//! Afterwards the keys are checked for duplicate creates and lost updates.
+
+use super::{connect, drive, ConnectionArgs, OpUnit, RunArgs, Stats};
+use crate::endpoints::EndpointPool;
+use anyhow::{Context, Result};
+use aws_sdk_s3::error::SdkError;
+use aws_sdk_s3::primitives::{ByteStream, DateTime};
+use aws_sdk_s3::Client as S3Client;
+use aws_smithy_runtime_api::http::Response as HttpResponse;
+use clap::{Args, ValueEnum};
+use rand::Rng;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub(crate) enum ConditionalMode {
+    /// PUT with If-None-Match: * to keys that start out absent; one writer per key can win
+    Create,
+    /// GET a key, then PUT its next generation with If-Match on the ETag read
+    Cas,
+    /// GET with --get-condition against unchanging objects
+    Get,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub(crate) enum GetCondition {
+    /// If-None-Match with the current ETag, answered 304 Not Modified
+    #[value(name = "if-none-match")]
+    NoneMatch,
+    /// If-Match with the current ETag, answered with the object
+    #[value(name = "if-match")]
+    Match,
+    /// If-Match with a stale ETag, answered 412 Precondition Failed
+    #[value(name = "if-match-stale")]
+    MatchStale,
+    /// If-Modified-Since the object's Last-Modified, answered 304 Not Modified
+    #[value(name = "if-modified-since")]
+    ModifiedSince,
+    /// If-Unmodified-Since a day before the object's Last-Modified, answered 412 Precondition Failed
+    #[value(name = "if-unmodified-since")]
+    UnmodifiedSince,
+}
+
+#[derive(Args)]
+pub(crate) struct ConditionalArgs {
+    #[command(flatten)]
+    conn: ConnectionArgs,
+    #[command(flatten)]
+    run: RunArgs,
+    #[arg(long, default_value = "10")]
+    concurrent: usize,
+    #[arg(long, value_enum, default_value = "create")]
+    mode: ConditionalMode,
+    /// Keys the writers contend on; fewer keys mean more contention
+    #[arg(long, default_value = "10")]
+    keys: usize,
+    /// Condition of every GET in get mode
+    #[arg(long, value_enum, default_value = "if-none-match")]
+    get_condition: GetCondition,
+    /// Prefix of the keys; objects already there are deleted first
+    #[arg(long, default_value = "conditional/")]
+    prefix: String,
+}
+
+/// How a conditional request was answered, when not with an error
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Outcome {
+    Success,
+    NotModified,
+    PreconditionFailed,
+    Conflict,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Outcomes {
+    pub(crate) success: AtomicU64,
+    pub(crate) not_modified: AtomicU64,
+    pub(crate) precondition_failed: AtomicU64,
+    pub(crate) conflict: AtomicU64,
+}
+
+impl Outcomes {
+    fn record(&self, outcome: Outcome) {
+        let counter = match outcome {
+            Outcome::Success => &self.success,
+            Outcome::NotModified => &self.not_modified,
+            Outcome::PreconditionFailed => &self.precondition_failed,
+            Outcome::Conflict => &self.conflict,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Split a response into its outcome, passing on failures that are not an answer to the condition
+fn outcome<T, E>(result: Result<T, SdkError<E, HttpResponse>>, what: &'static str) -> Result<(Outcome, Option<T>)>
+where
+    E: std::error::Error + Send + Sync + 'static,
+{
+    let err = match result {
+        Ok(output) => return Ok((Outcome::Success, Some(output))),
+        Err(err) => err,
+    };
+    match err.raw_response().map(|response| response.status().as_u16()) {
+        Some(304) => Ok((Outcome::NotModified, None)),
+        Some(412) => Ok((Outcome::PreconditionFailed, None)),
+        Some(409) => Ok((Outcome::Conflict, None)),
+        _ => Err(err).context(what),
+    }
+}
+
+/// Object body of one generation of a key
+fn generation_body(generation: u64) -> String {
+    format!("generation {}\n", generation)
+}
+
+/// A key and, unless it starts out absent, the ETag and Last-Modified it was written with
+struct KeyState {
+    key: String,
+    etag: String,
+    last_modified: DateTime,
+}
+
+pub(crate) struct ConditionalWorkload {
+    endpoints: Arc<EndpointPool>,
+    bucket: String,
+    run: RunArgs,
+    mode: ConditionalMode,
+    get_condition: GetCondition,
+    keys: Vec<KeyState>,
+    pub(crate) outcomes: Arc<Outcomes>,
+    /// Successful writes by key
+    writes: Arc<Vec<AtomicU64>>,
+}
+
+impl ConditionalWorkload {
+    /// Delete the keys, then in cas and get mode write generation 0 of each
+    pub(crate) async fn prepare(args: ConditionalArgs) -> Result<Self> {
+        if args.keys == 0 {
+            anyhow::bail!("--keys must be positive");
+        }
+        // Generation bodies are a few bytes and GETs may be answered 304, so operations plan no bytes
+        if args.run.total_bytes.is_some() {
+            anyhow::bail!("--total-bytes is not supported for conditional requests; use --ops or --duration-secs");
+        }
+        let endpoints = connect(&args.conn).await?;
+        let bucket = args.conn.bucket;
+        let client = endpoints.primary();
+
+        println!("Resetting {} keys under prefix '{}'...", args.keys, args.prefix);
+        let names: Vec<String> = (0..args.keys).map(|i| format!("{}key-{:05}", args.prefix, i)).collect();
+        let deletes = names.iter().map(|key| client.delete_object().bucket(&bucket).key(key).send());
+        futures::future::try_join_all(deletes).await.context("Failed to delete key")?;
+
+        let mut keys = Vec::new();
+        for key in names {
+            let mut state = KeyState {
+                key,
+                etag: String::new(),
+                last_modified: DateTime::from_secs(0),
+            };
+            if args.mode != ConditionalMode::Create {
+                let body = ByteStream::from(generation_body(0).into_bytes());
+                client.put_object().bucket(&bucket).key(&state.key).body(body).send().await.context("Failed to put object")?;
+                let head = client.head_object().bucket(&bucket).key(&state.key).send().await.context("Failed to head object")?;
+                state.etag = head.e_tag().context("No ETag")?.to_string();
+                state.last_modified = *head.last_modified().context("No Last-Modified")?;
+            }
+            keys.push(state);
+        }
+
+        Ok(ConditionalWorkload {
+            endpoints,
+            bucket,
+            run: args.run,
+            mode: args.mode,
+            get_condition: args.get_condition,
+            writes: Arc::new((0..keys.len()).map(|_| AtomicU64::new(0)).collect()),
+            keys,
+            outcomes: Arc::default(),
+        })
+    }
+
+    fn label(&self) -> &'static str {
+        match self.mode {
+            ConditionalMode::Create => "CREATE-IF-ABSENT",
+            ConditionalMode::Cas => "COMPARE-AND-SWAP",
+            ConditionalMode::Get => "CONDITIONAL-GET",
+        }
+    }
+
+    pub(crate) fn print_config(&self) {
+        println!("Mode: {}", self.label());
+        println!("Keys: {}", self.keys.len());
+        if self.mode == ConditionalMode::Get {
+            let condition = self.get_condition.to_possible_value().expect("no skipped variants");
+            println!("Condition: {}", condition.get_name());
+        }
+    }
+
+    /// Every operation goes to a random key
+    pub(crate) async fn execute(&self, concurrent: usize) -> Result<Stats> {
+        drive(self.label(), concurrent, self.run, Duration::from_millis(10), OpUnit::Bytes, &self.endpoints, |operation_count, client| {
+            let bucket = self.bucket.clone();
+            let index = rand::thread_rng().gen_range(0..self.keys.len());
+            let state = &self.keys[index];
+            let key = state.key.clone();
+            let etag = state.etag.clone();
+            let last_modified = state.last_modified;
+            let mode = self.mode;
+            let condition = self.get_condition;
+            let outcomes = self.outcomes.clone();
+            let writes = self.writes.clone();
+
+            println!("[BENCH] Spawning {} task {} for key: {}", self.label(), operation_count, key);
+            let op = async move {
+                let (outcome, bytes) = match mode {
+                    ConditionalMode::Create => create_if_absent(&client, &bucket, &key).await?,
+                    ConditionalMode::Cas => compare_and_swap(&client, &bucket, &key).await?,
+                    ConditionalMode::Get => conditional_get(&client, &bucket, &key, condition, &etag, last_modified).await?,
+                };
+                outcomes.record(outcome);
+                if outcome == Outcome::Success && mode != ConditionalMode::Get {
+                    writes[index].fetch_add(1, Ordering::Relaxed);
+                }
+                Ok(bytes)
+            };
+            (0, op)
+        })
+        .await
+    }
+
+    /// Duplicate creates (create mode) or lost updates (cas mode): each key may be
+    /// created once, and its generation must have advanced once per successful swap
+    pub(crate) async fn verify(&self) -> Result<u64> {
+        let client = self.endpoints.primary();
+        let mut anomalies = 0;
+        for (state, writes) in self.keys.iter().zip(self.writes.iter()) {
+            let writes = writes.load(Ordering::Relaxed);
+            anomalies += match self.mode {
+                ConditionalMode::Create => writes.saturating_sub(1),
+                ConditionalMode::Cas => {
+                    let (generation, _) = read_generation(client, &self.bucket, &state.key).await?;
+                    writes.saturating_sub(generation)
+                }
+                ConditionalMode::Get => 0,
+            };
+        }
+        Ok(anomalies)
+    }
+
+    pub(crate) fn print_summary(&self, anomalies: u64) {
+        println!("\n=== {} Outcomes ===", self.label());
+        println!("Success: {}", self.outcomes.success.load(Ordering::Relaxed));
+        println!("Not modified (304): {}", self.outcomes.not_modified.load(Ordering::Relaxed));
+        println!("Precondition failed (412): {}", self.outcomes.precondition_failed.load(Ordering::Relaxed));
+        println!("Conflict (409): {}", self.outcomes.conflict.load(Ordering::Relaxed));
+        match self.mode {
+            ConditionalMode::Create => println!("Duplicate creates: {}", anomalies),
+            ConditionalMode::Cas => println!("Lost updates: {}", anomalies),
+            ConditionalMode::Get => {}
+        }
+    }
+}
+
+async fn create_if_absent(client: &S3Client, bucket: &str, key: &str) -> Result<(Outcome, usize)> {
+    let body = generation_body(0);
+    let len = body.len();
+    let result = client
+        .put_object()
+        .bucket(bucket)
+        .key(key)
+        .if_none_match("*")
+        .body(ByteStream::from(body.into_bytes()))
+        .send()
+        .await;
+    let (outcome, _) = outcome(result, "Failed to put object")?;
+    Ok((outcome, if outcome == Outcome::Success { len } else { 0 }))
+}
+
+/// Current generation of a key and the ETag it was read with
+async fn read_generation(client: &S3Client, bucket: &str, key: &str) -> Result<(u64, String)> {
+    let resp = client.get_object().bucket(bucket).key(key).send().await.context("Failed to get object")?;
+    let etag = resp.e_tag().context("No ETag")?.to_string();
+    let data = resp.body.collect().await.context("Failed to read object body")?.into_bytes();
+    let generation = std::str::from_utf8(&data)
+        .ok()
+        .and_then(|text| text.trim().strip_prefix("generation "))
+        .and_then(|n| n.parse().ok())
+        .with_context(|| format!("Unexpected contents of {}", key))?;
+    Ok((generation, etag))
+}
+
+async fn compare_and_swap(client: &S3Client, bucket: &str, key: &str) -> Result<(Outcome, usize)> {
+    let (generation, etag) = read_generation(client, bucket, key).await?;
+    let body = generation_body(generation + 1);
+    let len = body.len();
+    let result = client
+        .put_object()
+        .bucket(bucket)
+        .key(key)
+        .if_match(etag)
+        .body(ByteStream::from(body.into_bytes()))
+        .send()
+        .await;
+    let (outcome, _) = outcome(result, "Failed to put object")?;
+    Ok((outcome, if outcome == Outcome::Success { len } else { 0 }))
+}
+
+async fn conditional_get(
+    client: &S3Client,
+    bucket: &str,
+    key: &str,
+    condition: GetCondition,
+    etag: &str,
+    last_modified: DateTime,
+) -> Result<(Outcome, usize)> {
+    let request = client.get_object().bucket(bucket).key(key);
+    let request = match condition {
+        GetCondition::NoneMatch => request.if_none_match(etag),
+        GetCondition::Match => request.if_match(etag),
+        GetCondition::MatchStale => request.if_match("\"00000000000000000000000000000000\""),
+        GetCondition::ModifiedSince => request.if_modified_since(last_modified),
+        GetCondition::UnmodifiedSince => request.if_unmodified_since(DateTime::from_secs(last_modified.secs() - 86_400)),
+    };
+    match outcome(request.send().await, "Failed to get object")? {
+        (outcome, Some(resp)) => {
+            let data = resp.body.collect().await.context("Failed to read object body")?;
+            Ok((outcome, data.into_bytes().len()))
+        }
+        (outcome, None) => Ok((outcome, 0)),
+    }
+}
+
+pub(crate) async fn run_conditional_benchmark(args: ConditionalArgs) -> Result<()> {
+    let concurrent = args.concurrent;
+
+    println!("Starting CONDITIONAL benchmark...");
+    println!("Endpoint: {}", args.conn.describe_endpoints());
+    println!("Client: {}", args.conn.client);
+    println!("HTTP: {}", args.conn.http);
+    println!("Credentials: {}", args.conn.credentials);
+    println!("Bucket: {}", args.conn.bucket);
+    println!("Stop after: {}", args.run.resolve());
+    println!("Concurrent operations: {}", concurrent);
+
+    let workload = ConditionalWorkload::prepare(args).await?;
+    workload.print_config();
+
+    let stats = workload.execute(concurrent).await?;
+    stats.print(workload.label());
+    let anomalies = workload.verify().await?;
+    workload.print_summary(anomalies);
+
+    Ok(())
+}
@@ -0,0 +1,543 @@
+//! Read-after-write and list-after-write consistency checker. Writers PUT,
+//! overwrite and DELETE a fixed set of keys while readers GET, HEAD and LIST
+//! them. Writes to one key are serialized and numbered, and every object
+//! carries the number of the PUT that wrote it. A read may return any write at
+//! least as new as the last one acknowledged before it started, so a strongly
+//! consistent store never shows:
+//!
+//! - a stale read: an older write, or no object, after a PUT was acknowledged
+//! - a resurrected object: an object written before an acknowledged DELETE
+//! - a missing listing entry: a key absent from a listing after its PUT was
+//!   acknowledged, or present after its DELETE was
+//!
+//! Writes that fail may or may not have taken effect, so reads that reflect
+//! them are accepted too.
+
+use super::{connect, discover_objects, drive, ConnectionArgs, OpUnit, RunArgs, Stats, MAX_TRACKED_LATENCY_US};
+use crate::endpoints::EndpointPool;
+use anyhow::{Context, Result};
+use aws_sdk_s3::error::SdkError;
+use aws_sdk_s3::primitives::ByteStream;
+use aws_sdk_s3::Client as S3Client;
+use aws_smithy_runtime_api::http::Response as HttpResponse;
+use bytes::{Bytes, BytesMut};
+use clap::Args;
+use hdrhistogram::Histogram;
+use rand::Rng;
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Violations printed in full; the rest are only counted
+const MAX_REPORTED_VIOLATIONS: usize = 20;
+
+/// User metadata entry carrying the write number, for HEAD
+const SEQUENCE_METADATA: &str = "sequence";
+
+#[derive(Args)]
+pub(crate) struct ConsistencyArgs {
+    #[command(flatten)]
+    conn: ConnectionArgs,
+    /// Stop condition, applied to writers and readers each
+    #[command(flatten)]
+    run: RunArgs,
+    /// Concurrent write operations
+    #[arg(long, default_value = "4")]
+    writers: usize,
+    /// Concurrent read operations
+    #[arg(long, default_value = "16")]
+    readers: usize,
+    /// Keys written and read; fewer keys mean more reads racing writes to the same key
+    #[arg(long, default_value = "20")]
+    keys: usize,
+    /// Fraction of writes that are DELETEs
+    #[arg(long, default_value = "0.2")]
+    delete_fraction: f64,
+    /// Fraction of reads that LIST the prefix; the rest are split evenly between GET and HEAD
+    #[arg(long, default_value = "0.1")]
+    list_fraction: f64,
+    #[arg(long, default_value = "1024")]
+    object_size: usize,
+    /// Prefix of the keys; objects already there under the checked keys are deleted first
+    #[arg(long, default_value = "consistency/")]
+    prefix: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum ViolationKind {
+    StaleRead,
+    ResurrectedObject,
+    MissingFromListing,
+    ResurrectedInListing,
+}
+
+impl fmt::Display for ViolationKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ViolationKind::StaleRead => "stale read",
+            ViolationKind::ResurrectedObject => "resurrected object",
+            ViolationKind::MissingFromListing => "missing from listing",
+            ViolationKind::ResurrectedInListing => "resurrected in listing",
+        })
+    }
+}
+
+/// Writes to one key. Write numbers start at 1; number 0 is the DELETE that
+/// cleared the key before the run.
+#[derive(Clone, Copy, Debug, Default)]
+struct KeyState {
+    /// Last write started
+    issued: u64,
+    last_put: u64,
+    last_delete: u64,
+    /// Last write acknowledged, and whether it was a DELETE
+    acked: u64,
+    acked_delete: bool,
+    acked_at: Option<Instant>,
+}
+
+impl KeyState {
+    fn cleared() -> Self {
+        KeyState {
+            acked_delete: true,
+            ..KeyState::default()
+        }
+    }
+}
+
+/// Check a GET or HEAD against the key's state before it started (`before`) and
+/// after it finished (`after`); `observed` is the write number read, `None` if the key was absent
+fn check_read(before: &KeyState, after: &KeyState, observed: Option<u64>) -> Option<ViolationKind> {
+    match observed {
+        Some(sequence) if sequence < before.acked && before.acked_delete => Some(ViolationKind::ResurrectedObject),
+        Some(sequence) if sequence < before.acked => Some(ViolationKind::StaleRead),
+        // Absent after an acknowledged PUT, with no DELETE started since
+        None if !before.acked_delete && after.last_delete < before.acked => Some(ViolationKind::StaleRead),
+        _ => None,
+    }
+}
+
+/// Check whether a key's presence in a listing agrees with its state before and after the LIST
+fn check_listing(before: &KeyState, after: &KeyState, listed: bool) -> Option<ViolationKind> {
+    match (listed, before.acked_delete) {
+        (false, false) if after.last_delete < before.acked => Some(ViolationKind::MissingFromListing),
+        (true, true) if after.last_put < before.acked => Some(ViolationKind::ResurrectedInListing),
+        // Deleted before the run and not written since
+        (true, true) if after.last_put == 0 => Some(ViolationKind::ResurrectedInListing),
+        _ => None,
+    }
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct Violation {
+    pub(crate) kind: ViolationKind,
+    operation: &'static str,
+    key: String,
+    /// Write acknowledged before the read started
+    expected: u64,
+    expected_delete: bool,
+    /// Write the read returned, `None` for an absent key
+    observed: Option<u64>,
+    /// Time from the acknowledgement to the start of the read
+    since_ack: Option<Duration>,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let expected = if self.expected_delete { "DELETE" } else { "PUT" };
+        write!(f, "{} by {} of {}: expected {} #{} or newer, got ", self.kind, self.operation, self.key, expected, self.expected)?;
+        match self.observed {
+            Some(sequence) => write!(f, "PUT #{}", sequence)?,
+            None => write!(f, "no object")?,
+        }
+        match self.since_ack {
+            Some(since) => write!(f, ", {:.1} ms after acknowledgement", since.as_secs_f64() * 1000.0),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Request latency of successful operations by kind, in microseconds; unlike
+/// the WRITE stats it excludes waiting for earlier writes to the same key
+#[derive(Default)]
+struct Timings(Mutex<BTreeMap<&'static str, Histogram<u64>>>);
+
+impl Timings {
+    fn record(&self, operation: &'static str, latency: Duration) {
+        let mut timings = self.0.lock().unwrap();
+        let histogram = timings
+            .entry(operation)
+            .or_insert_with(|| Histogram::new_with_bounds(1, MAX_TRACKED_LATENCY_US, 3).expect("valid histogram bounds"));
+        histogram.saturating_record(latency.as_micros() as u64);
+    }
+
+    fn print(&self) {
+        let ms = |micros: u64| micros as f64 / 1000.0;
+        println!("{:<8} {:>8} {:>10} {:>10} {:>10}", "Op", "Count", "p50 ms", "p99 ms", "max ms");
+        for (operation, histogram) in self.0.lock().unwrap().iter() {
+            println!(
+                "{:<8} {:>8} {:>10.2} {:>10.2} {:>10.2}",
+                operation,
+                histogram.len(),
+                ms(histogram.value_at_quantile(0.5)),
+                ms(histogram.value_at_quantile(0.99)),
+                ms(histogram.max())
+            );
+        }
+    }
+}
+
+/// Shared between the operations of a run
+struct Checker {
+    keys: Vec<String>,
+    states: Mutex<Vec<KeyState>>,
+    /// Held for the duration of each write, so a key's writes are applied in the order they are numbered
+    write_locks: Vec<tokio::sync::Mutex<()>>,
+    violations: Mutex<Vec<Violation>>,
+    timings: Timings,
+}
+
+impl Checker {
+    fn state(&self, index: usize) -> KeyState {
+        self.states.lock().unwrap()[index]
+    }
+
+    fn report(&self, kind: ViolationKind, operation: &'static str, index: usize, before: &KeyState, observed: Option<u64>, started: Instant) {
+        let violation = Violation {
+            kind,
+            operation,
+            key: self.keys[index].clone(),
+            expected: before.acked,
+            expected_delete: before.acked_delete,
+            observed,
+            since_ack: before.acked_at.map(|acked| started.saturating_duration_since(acked)),
+        };
+        println!("[CONSISTENCY] Violation: {}", violation);
+        self.violations.lock().unwrap().push(violation);
+    }
+
+    async fn write(&self, client: &S3Client, bucket: &str, index: usize, delete: bool, object_size: usize) -> Result<usize> {
+        let key = &self.keys[index];
+        let _serialized = self.write_locks[index].lock().await;
+        let sequence = {
+            let mut states = self.states.lock().unwrap();
+            let state = &mut states[index];
+            state.issued += 1;
+            if delete {
+                state.last_delete = state.issued;
+            } else {
+                state.last_put = state.issued;
+            }
+            state.issued
+        };
+
+        let start = Instant::now();
+        let (operation, written) = if delete {
+            client.delete_object().bucket(bucket).key(key).send().await.context("Failed to delete object")?;
+            ("DELETE", 0)
+        } else {
+            client
+                .put_object()
+                .bucket(bucket)
+                .key(key)
+                .metadata(SEQUENCE_METADATA, sequence.to_string())
+                .body(ByteStream::from(object_data(sequence, object_size)))
+                .send()
+                .await
+                .context("Failed to put object")?;
+            ("PUT", object_size)
+        };
+        self.timings.record(operation, start.elapsed());
+
+        let mut states = self.states.lock().unwrap();
+        let state = &mut states[index];
+        state.acked = sequence;
+        state.acked_delete = delete;
+        state.acked_at = Some(Instant::now());
+        Ok(written)
+    }
+
+    async fn read(&self, client: &S3Client, bucket: &str, index: usize, head: bool) -> Result<usize> {
+        let key = &self.keys[index];
+        let before = self.state(index);
+        let start = Instant::now();
+        let (operation, observed, bytes) = if head {
+            let result = client.head_object().bucket(bucket).key(key).send().await;
+            match not_found(result, "Failed to head object")? {
+                Some(resp) => {
+                    let sequence = resp.metadata().and_then(|metadata| metadata.get(SEQUENCE_METADATA)).and_then(|n| n.parse().ok());
+                    ("HEAD", Some(sequence.with_context(|| format!("No write number in the metadata of {}", key))?), 0)
+                }
+                None => ("HEAD", None, 0),
+            }
+        } else {
+            let result = client.get_object().bucket(bucket).key(key).send().await;
+            match not_found(result, "Failed to get object")? {
+                Some(resp) => {
+                    let data = resp.body.collect().await.context("Failed to read object body")?.into_bytes();
+                    let sequence = parse_sequence(&data).with_context(|| format!("Unexpected contents of {}", key))?;
+                    ("GET", Some(sequence), data.len())
+                }
+                None => ("GET", None, 0),
+            }
+        };
+        self.timings.record(operation, start.elapsed());
+
+        if let Some(kind) = check_read(&before, &self.state(index), observed) {
+            self.report(kind, operation, index, &before, observed, start);
+        }
+        Ok(bytes)
+    }
+
+    async fn list(&self, client: &S3Client, bucket: &str, prefix: &str) -> Result<usize> {
+        let before = self.states.lock().unwrap().clone();
+        let start = Instant::now();
+        let listed: HashSet<String> = discover_objects(client, bucket, prefix).await?.into_iter().map(|object| object.key).collect();
+        self.timings.record("LIST", start.elapsed());
+
+        let after = self.states.lock().unwrap().clone();
+        for (index, key) in self.keys.iter().enumerate() {
+            let present = listed.contains(key);
+            if let Some(kind) = check_listing(&before[index], &after[index], present) {
+                self.report(kind, "LIST", index, &before[index], None, start);
+            }
+        }
+        Ok(0)
+    }
+}
+
+/// Object data of write `sequence`: its number on the first line, padded to `size`
+fn object_data(sequence: u64, size: usize) -> Bytes {
+    let mut data = BytesMut::from(format!("sequence {}\n", sequence).as_bytes());
+    if data.len() < size {
+        data.resize(size, b'.');
+    }
+    data.freeze()
+}
+
+fn parse_sequence(data: &[u8]) -> Option<u64> {
+    let line = data.split(|&byte| byte == b'\n').next()?;
+    std::str::from_utf8(line).ok()?.strip_prefix("sequence ")?.parse().ok()
+}
+
+/// The response, or `None` if the key does not exist
+fn not_found<T, E>(result: Result<T, SdkError<E, HttpResponse>>, context: &'static str) -> Result<Option<T>>
+where
+    E: std::error::Error + Send + Sync + 'static,
+{
+    match result {
+        Ok(output) => Ok(Some(output)),
+        Err(err) if err.raw_response().is_some_and(|response| response.status().as_u16() == 404) => Ok(None),
+        Err(err) => Err(err).context(context),
+    }
+}
+
+pub(crate) struct ConsistencyWorkload {
+    endpoints: Arc<EndpointPool>,
+    bucket: String,
+    run: RunArgs,
+    writers: usize,
+    readers: usize,
+    delete_fraction: f64,
+    list_fraction: f64,
+    object_size: usize,
+    prefix: String,
+    checker: Arc<Checker>,
+}
+
+impl ConsistencyWorkload {
+    /// Delete the checked keys so every key starts out absent
+    pub(crate) async fn prepare(args: ConsistencyArgs) -> Result<Self> {
+        if args.keys == 0 || args.writers == 0 || args.readers == 0 {
+            anyhow::bail!("--keys, --writers and --readers must be positive");
+        }
+        if !(0.0..=1.0).contains(&args.delete_fraction) || !(0.0..=1.0).contains(&args.list_fraction) {
+            anyhow::bail!("--delete-fraction and --list-fraction must be between 0 and 1");
+        }
+        if args.run.total_bytes.is_some() {
+            anyhow::bail!("--total-bytes is not supported for the consistency check; use --ops or --duration-secs");
+        }
+        let endpoints = connect(&args.conn).await?;
+        let bucket = args.conn.bucket;
+
+        println!("Clearing {} keys under prefix '{}'...", args.keys, args.prefix);
+        let keys: Vec<String> = (0..args.keys).map(|i| format!("{}key-{:05}", args.prefix, i)).collect();
+        let client = endpoints.primary();
+        let deletes = keys.iter().map(|key| client.delete_object().bucket(&bucket).key(key).send());
+        futures::future::try_join_all(deletes).await.context("Failed to delete key")?;
+
+        let checker = Checker {
+            states: Mutex::new(vec![KeyState::cleared(); keys.len()]),
+            write_locks: keys.iter().map(|_| tokio::sync::Mutex::new(())).collect(),
+            keys,
+            violations: Mutex::default(),
+            timings: Timings::default(),
+        };
+        Ok(ConsistencyWorkload {
+            endpoints,
+            bucket,
+            run: args.run,
+            writers: args.writers,
+            readers: args.readers,
+            delete_fraction: args.delete_fraction,
+            list_fraction: args.list_fraction,
+            object_size: args.object_size,
+            prefix: args.prefix,
+            checker: Arc::new(checker),
+        })
+    }
+
+    pub(crate) fn print_config(&self) {
+        println!("Keys: {}", self.checker.keys.len());
+        println!("Writers: {} ({:.0}% DELETE)", self.writers, self.delete_fraction * 100.0);
+        println!("Readers: {} ({:.0}% LIST, the rest GET and HEAD)", self.readers, self.list_fraction * 100.0);
+        println!("Object size: {} bytes", self.object_size);
+    }
+
+    /// Run writers and readers side by side; returns their stats in that order
+    pub(crate) async fn execute(&self) -> Result<(Stats, Stats)> {
+        let keys = self.checker.keys.len();
+        let writes = drive("WRITE", self.writers, self.run, Duration::from_millis(10), OpUnit::Bytes, &self.endpoints, |operation_count, client| {
+            let checker = self.checker.clone();
+            let bucket = self.bucket.clone();
+            let object_size = self.object_size;
+            let mut rng = rand::thread_rng();
+            let index = rng.gen_range(0..keys);
+            let delete = rng.gen_bool(self.delete_fraction);
+
+            println!("[BENCH] Spawning {} task {} for key: {}", if delete { "DELETE" } else { "PUT" }, operation_count, checker.keys[index]);
+            let op = async move { checker.write(&client, &bucket, index, delete, object_size).await };
+            (if delete { 0 } else { object_size as u64 }, op)
+        });
+        let reads = drive("READ", self.readers, self.run, Duration::from_millis(10), OpUnit::Bytes, &self.endpoints, |operation_count, client| {
+            let checker = self.checker.clone();
+            let bucket = self.bucket.clone();
+            let prefix = self.prefix.clone();
+            let mut rng = rand::thread_rng();
+            let list = rng.gen_bool(self.list_fraction);
+            let index = rng.gen_range(0..keys);
+            let head = rng.gen_bool(0.5);
+
+            println!("[BENCH] Spawning READ task {}", operation_count);
+            let op = async move {
+                if list {
+                    checker.list(&client, &bucket, &prefix).await
+                } else {
+                    checker.read(&client, &bucket, index, head).await
+                }
+            };
+            (0, op)
+        });
+        let (writes, reads) = tokio::join!(writes, reads);
+        Ok((writes?, reads?))
+    }
+
+    pub(crate) fn violations(&self) -> Vec<Violation> {
+        self.checker.violations.lock().unwrap().clone()
+    }
+
+    pub(crate) fn print_summary(&self) {
+        println!("\n=== CONSISTENCY Summary ===");
+        self.checker.timings.print();
+
+        let violations = self.violations();
+        let mut by_kind: BTreeMap<ViolationKind, usize> = BTreeMap::new();
+        for violation in &violations {
+            *by_kind.entry(violation.kind).or_default() += 1;
+        }
+        println!("Violations: {}", violations.len());
+        for (kind, count) in &by_kind {
+            println!("  {}: {}", kind, count);
+        }
+        if let Some(latest) = violations.iter().filter_map(|violation| violation.since_ack).max() {
+            println!("Longest time after acknowledgement with a violation: {:.1} ms", latest.as_secs_f64() * 1000.0);
+        }
+        for violation in violations.iter().take(MAX_REPORTED_VIOLATIONS) {
+            println!("  {}", violation);
+        }
+        if violations.len() > MAX_REPORTED_VIOLATIONS {
+            println!("  ... and {} more", violations.len() - MAX_REPORTED_VIOLATIONS);
+        }
+    }
+}
+
+pub(crate) async fn run_consistency_check(args: ConsistencyArgs) -> Result<()> {
+    println!("Starting CONSISTENCY check...");
+    println!("Endpoint: {}", args.conn.describe_endpoints());
+    println!("Client: {}", args.conn.client);
+    println!("HTTP: {}", args.conn.http);
+    println!("Credentials: {}", args.conn.credentials);
+    println!("Bucket: {}", args.conn.bucket);
+    println!("Stop writers and readers each after: {}", args.run.resolve());
+
+    let workload = ConsistencyWorkload::prepare(args).await?;
+    workload.print_config();
+
+    let (writes, reads) = workload.execute().await?;
+    writes.print("WRITE");
+    println!("WRITE latency includes waiting for earlier writes to the same key; the summary has request latency only");
+    reads.print("READ");
+    workload.print_summary();
+
+    let violations = workload.violations().len();
+    if violations > 0 {
+        anyhow::bail!("{} consistency violations", violations);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn acked(sequence: u64, delete: bool) -> KeyState {
+        KeyState {
+            issued: sequence,
+            last_put: if delete { 0 } else { sequence },
+            last_delete: if delete { sequence } else { 0 },
+            acked: sequence,
+            acked_delete: delete,
+            acked_at: None,
+        }
+    }
+
+    #[test]
+    fn flags_reads_older_than_the_acknowledged_write() {
+        let put = acked(3, false);
+        assert_eq!(check_read(&put, &put, Some(3)), None);
+        assert_eq!(check_read(&put, &put, Some(2)), Some(ViolationKind::StaleRead));
+        assert_eq!(check_read(&put, &put, None), Some(ViolationKind::StaleRead));
+        // A DELETE started during the read may already have been applied
+        let deleting = KeyState { issued: 4, last_delete: 4, ..put };
+        assert_eq!(check_read(&put, &deleting, None), None);
+        // So may a PUT that was started but not yet acknowledged
+        assert_eq!(check_read(&put, &KeyState { issued: 4, last_put: 4, ..put }, Some(4)), None);
+
+        let delete = acked(5, true);
+        assert_eq!(check_read(&delete, &delete, None), None);
+        assert_eq!(check_read(&delete, &delete, Some(3)), Some(ViolationKind::ResurrectedObject));
+    }
+
+    #[test]
+    fn flags_listings_that_disagree_with_acknowledged_writes() {
+        let put = acked(2, false);
+        assert_eq!(check_listing(&put, &put, true), None);
+        assert_eq!(check_listing(&put, &put, false), Some(ViolationKind::MissingFromListing));
+        assert_eq!(check_listing(&put, &KeyState { issued: 3, last_delete: 3, ..put }, false), None);
+
+        let delete = acked(4, true);
+        assert_eq!(check_listing(&delete, &delete, false), None);
+        assert_eq!(check_listing(&delete, &delete, true), Some(ViolationKind::ResurrectedInListing));
+        assert_eq!(check_listing(&delete, &KeyState { issued: 5, last_put: 5, ..delete }, true), None);
+        assert_eq!(check_listing(&KeyState::cleared(), &KeyState::cleared(), true), Some(ViolationKind::ResurrectedInListing));
+    }
+
+    #[test]
+    fn object_data_carries_its_write_number() {
+        let data = object_data(42, 100);
+        assert_eq!(data.len(), 100);
+        assert_eq!(parse_sequence(&data), Some(42));
+        assert_eq!(parse_sequence(b"garbage"), None);
+    }
+}
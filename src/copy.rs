@@ -0,0 +1,241 @@
+//! Server-side copy benchmark. Objects discovered under a source prefix are
+//! copied to a destination prefix without their data passing through the
+//! client: objects smaller than the part size with one CopyObject, larger ones
+//! with a multipart upload whose parts are UploadPartCopy byte ranges. The
+//! throughput reported is logical bytes copied.
+
+use super::{connect, discover_objects, drive, ConnectionArgs, ObjectInfo, OpUnit, RunArgs, Stats};
+use crate::endpoints::EndpointPool;
+use anyhow::{Context, Result};
+use aws_sdk_s3::types::{CompletedMultipartUpload, CompletedPart};
+use aws_sdk_s3::Client as S3Client;
+use clap::Args;
+use std::sync::Arc;
+use std::time::Duration;
+
+#[derive(Args)]
+pub(crate) struct CopyArgs {
+    #[command(flatten)]
+    pub(crate) conn: ConnectionArgs,
+    #[command(flatten)]
+    pub(crate) run: RunArgs,
+    #[arg(long, default_value = "10")]
+    pub(crate) concurrent: usize,
+    /// Prefix of the objects to copy
+    #[arg(long, default_value = "test-object/")]
+    source_prefix: String,
+    /// Prefix the copies are written under, followed by the key relative to --source-prefix
+    #[arg(long, default_value = "copy/")]
+    dest_prefix: String,
+    /// Objects of at least this size are copied with UploadPartCopy in ranges of this size (S3 needs 5 MiB or more)
+    #[arg(long, default_value = "8388608")] // 8MB default
+    part_size: u64,
+}
+
+/// COPY benchmark configuration with the source objects discovered up front
+pub(crate) struct CopyWorkload {
+    endpoints: Arc<EndpointPool>,
+    bucket: String,
+    pub(crate) run: RunArgs,
+    source_prefix: String,
+    dest_prefix: String,
+    part_size: u64,
+    objects: Vec<ObjectInfo>,
+}
+
+impl CopyWorkload {
+    pub(crate) async fn prepare(args: CopyArgs) -> Result<Self> {
+        if args.dest_prefix == args.source_prefix {
+            anyhow::bail!("--dest-prefix must differ from --source-prefix");
+        }
+        if args.part_size == 0 {
+            anyhow::bail!("--part-size must be positive");
+        }
+        let endpoints = connect(&args.conn).await?;
+        let bucket = args.conn.bucket;
+
+        println!("Listing objects with prefix '{}'...", args.source_prefix);
+        let objects = discover_objects(endpoints.primary(), &bucket, &args.source_prefix).await?;
+        if objects.is_empty() {
+            anyhow::bail!("No objects found with prefix '{}'. Please run PUT benchmark first.", args.source_prefix);
+        }
+        println!("Found {} objects to copy", objects.len());
+
+        Ok(CopyWorkload {
+            endpoints,
+            bucket,
+            run: args.run,
+            source_prefix: args.source_prefix,
+            dest_prefix: args.dest_prefix,
+            part_size: args.part_size,
+            objects,
+        })
+    }
+
+    pub(crate) fn print_config(&self) {
+        let multipart = self.objects.iter().filter(|obj| obj.size >= self.part_size).count();
+        println!("Copying '{}' -> '{}'", self.source_prefix, self.dest_prefix);
+        println!("Part size: {} bytes ({:.2} MB)", self.part_size, self.part_size as f64 / 1_048_576.0);
+        println!("Objects copied with UploadPartCopy: {} of {}", multipart, self.objects.len());
+    }
+
+    pub(crate) async fn execute(&self, concurrent: usize) -> Result<Stats> {
+        let objects = &self.objects;
+        let part_size = self.part_size;
+
+        drive("COPY", concurrent, self.run, Duration::from_millis(10), OpUnit::Bytes, &self.endpoints, |operation_count, client| {
+            let bucket = self.bucket.clone();
+            let object = &objects[operation_count as usize % objects.len()];
+            let source_key = object.key.clone();
+            let relative = object.key.strip_prefix(&self.source_prefix).unwrap_or(&object.key);
+            let dest_key = format!("{}{}", self.dest_prefix, relative);
+            let size = object.size;
+
+            println!("[BENCH] Spawning COPY task {} for key: {} -> {}", operation_count, source_key, dest_key);
+            let op = async move {
+                if size < part_size {
+                    copy_object(&client, &bucket, &source_key, &dest_key, size).await
+                } else {
+                    copy_object_multipart(&client, &bucket, &source_key, &dest_key, size, part_size).await
+                }
+            };
+            (size, op)
+        })
+        .await
+    }
+}
+
+/// `x-amz-copy-source` value for a key in `bucket`, percent-encoded except for `/`
+fn copy_source(bucket: &str, key: &str) -> String {
+    let mut encoded = format!("{}/", bucket);
+    for byte in key.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => encoded.push(byte as char),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+async fn copy_object(client: &S3Client, bucket: &str, source_key: &str, dest_key: &str, size: u64) -> Result<usize> {
+    println!("[COPY] Starting copy of key: {} -> {} (size: {} bytes)", source_key, dest_key, size);
+    client
+        .copy_object()
+        .bucket(bucket)
+        .key(dest_key)
+        .copy_source(copy_source(bucket, source_key))
+        .send()
+        .await
+        .context("Failed to copy object")?;
+
+    println!("[COPY] Completed copy of key: {}", dest_key);
+    Ok(size as usize)
+}
+
+async fn copy_object_multipart(
+    client: &S3Client,
+    bucket: &str,
+    source_key: &str,
+    dest_key: &str,
+    size: u64,
+    part_size: u64,
+) -> Result<usize> {
+    let num_parts = size.div_ceil(part_size);
+    println!("[COPY-MP] Starting multipart copy of key: {} -> {} (size: {} bytes, {} parts)", source_key, dest_key, size, num_parts);
+
+    let multipart = client
+        .create_multipart_upload()
+        .bucket(bucket)
+        .key(dest_key)
+        .send()
+        .await
+        .context("Failed to create multipart upload")?;
+    let upload_id = multipart.upload_id().context("No upload ID")?;
+
+    // Copy all ranges in parallel
+    let mut copy_tasks = Vec::new();
+    for part_index in 0..num_parts {
+        let client = client.clone();
+        let bucket = bucket.to_string();
+        let dest_key = dest_key.to_string();
+        let upload_id = upload_id.to_string();
+        let source = copy_source(&bucket, source_key);
+        let start = part_index * part_size;
+        let end = (start + part_size).min(size) - 1;
+        let current_part = part_index as i32 + 1;
+
+        copy_tasks.push(tokio::spawn(async move {
+            println!("[COPY-MP] Copying part {} (bytes {}-{}) for key: {}", current_part, start, end, dest_key);
+            let result = client
+                .upload_part_copy()
+                .bucket(bucket)
+                .key(&dest_key)
+                .upload_id(upload_id)
+                .part_number(current_part)
+                .copy_source(source)
+                .copy_source_range(format!("bytes={}-{}", start, end))
+                .send()
+                .await;
+            if let Err(e) = &result {
+                println!("[COPY-MP] Failed part {} for key: {} - {:?}", current_part, dest_key, e);
+            }
+            result.map(|resp| (current_part, resp))
+        }));
+    }
+
+    let mut completed_parts = Vec::new();
+    for task in copy_tasks {
+        let (part_num, copy_result) = task
+            .await
+            .context("Copy part task panicked")?
+            .context("Failed to copy part")?;
+        let e_tag = copy_result.copy_part_result().and_then(|result| result.e_tag()).unwrap_or_default();
+        completed_parts.push(CompletedPart::builder().part_number(part_num).e_tag(e_tag).build());
+    }
+
+    client
+        .complete_multipart_upload()
+        .bucket(bucket)
+        .key(dest_key)
+        .upload_id(upload_id)
+        .multipart_upload(CompletedMultipartUpload::builder().set_parts(Some(completed_parts)).build())
+        .send()
+        .await
+        .context("Failed to complete multipart copy")?;
+
+    println!("[COPY-MP] Successfully completed multipart copy of key: {}", dest_key);
+    Ok(size as usize)
+}
+
+pub(crate) async fn run_copy_benchmark(args: CopyArgs) -> Result<()> {
+    let concurrent = args.concurrent;
+
+    println!("Starting COPY benchmark...");
+    println!("Endpoint: {}", args.conn.describe_endpoints());
+    println!("Client: {}", args.conn.client);
+    println!("HTTP: {}", args.conn.http);
+    println!("Credentials: {}", args.conn.credentials);
+    println!("Bucket: {}", args.conn.bucket);
+    println!("Stop after: {}", args.run.resolve());
+    println!("Concurrent operations: {}", concurrent);
+
+    let workload = CopyWorkload::prepare(args).await?;
+    workload.print_config();
+
+    let stats = workload.execute(concurrent).await?;
+    stats.print("COPY");
+    println!("Data and throughput above are logical bytes copied server-side; no object data passed through the client.");
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_copy_sources() {
+        assert_eq!(copy_source("bench", "test-object/1-2"), "bench/test-object/1-2");
+        assert_eq!(copy_source("bench", "a b/ü?.txt"), "bench/a%20b/%C3%BC%3F.txt");
+    }
+}
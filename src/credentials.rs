@@ -0,0 +1,129 @@
+//! Where request credentials come from. Without explicit keys the standard AWS
+//! provider chain is used: environment variables, the shared config and
+//! credentials files, web identity tokens, SSO, and container/instance metadata.
+
+use anyhow::{Context, Result};
+use aws_config::provider_config::ProviderConfig;
+use aws_config::sts::AssumeRoleProvider;
+use aws_config::web_identity_token::{StaticConfiguration, WebIdentityTokenCredentialsProvider};
+use aws_config::{BehaviorVersion, Region, SdkConfig};
+use aws_credential_types::provider::{ProvideCredentials, SharedCredentialsProvider};
+use aws_credential_types::Credentials;
+use clap::Args;
+use std::fmt;
+use std::path::PathBuf;
+
+/// Placeholder value older scripts pass for the keys; never sent to a server
+const PLACEHOLDER_KEY: &str = "changeme";
+
+#[derive(Args, Clone)]
+pub(crate) struct CredentialArgs {
+    /// Access key ID; prefer AWS_ACCESS_KEY_ID or --profile to keep keys out of shell history
+    #[arg(long, requires = "secret_key")]
+    access_key: Option<String>,
+    #[arg(long, requires = "access_key")]
+    secret_key: Option<String>,
+    /// Session token for temporary --access-key/--secret-key credentials
+    #[arg(long, requires = "access_key")]
+    session_token: Option<String>,
+    /// Profile from the shared AWS config and credentials files
+    #[arg(long, conflicts_with = "access_key")]
+    profile: Option<String>,
+    /// Send unsigned requests
+    #[arg(long, conflicts_with_all = ["access_key", "profile", "role_arn"])]
+    anonymous: bool,
+    /// Assume this IAM role, using the other credentials (or --web-identity-token-file) as the source
+    #[arg(long)]
+    role_arn: Option<String>,
+    /// Exchange this OIDC token file for --role-arn credentials via AssumeRoleWithWebIdentity
+    #[arg(long, requires = "role_arn", conflicts_with_all = ["access_key", "profile"])]
+    web_identity_token_file: Option<PathBuf>,
+    #[arg(long, default_value = "s3-load-gen")]
+    role_session_name: String,
+    /// External ID required by the role's trust policy
+    #[arg(long, requires = "role_arn")]
+    external_id: Option<String>,
+}
+
+impl CredentialArgs {
+    /// Resolve the credential source into an SDK config. Credentials are fetched
+    /// once up front so a missing or placeholder source fails before the run starts.
+    pub(crate) async fn load(&self, region: &str) -> Result<SdkConfig> {
+        let region = Region::new(region.to_string());
+        let mut loader = aws_config::defaults(BehaviorVersion::latest()).region(region.clone());
+        if self.anonymous {
+            return Ok(loader.no_credentials().load().await);
+        }
+        if let Some(profile) = &self.profile {
+            loader = loader.profile_name(profile);
+        }
+        if let (Some(access_key), Some(secret_key)) = (&self.access_key, &self.secret_key) {
+            if access_key == PLACEHOLDER_KEY || secret_key == PLACEHOLDER_KEY {
+                anyhow::bail!(
+                    "Refusing to use the placeholder credentials '{}'; pass real keys, set AWS_ACCESS_KEY_ID/AWS_SECRET_ACCESS_KEY, use --profile, or --anonymous",
+                    PLACEHOLDER_KEY
+                );
+            }
+            let credentials = Credentials::new(access_key, secret_key, self.session_token.clone(), None, "command-line");
+            loader = loader.credentials_provider(credentials);
+        }
+        let mut config = loader.load().await;
+
+        if let Some(role_arn) = &self.role_arn {
+            let provider = match &self.web_identity_token_file {
+                Some(token_file) => SharedCredentialsProvider::new(
+                    WebIdentityTokenCredentialsProvider::builder()
+                        .static_configuration(StaticConfiguration {
+                            web_identity_token_file: token_file.clone(),
+                            role_arn: role_arn.clone(),
+                            session_name: self.role_session_name.clone(),
+                        })
+                        .configure(&ProviderConfig::without_region().with_region(Some(region)))
+                        .build(),
+                ),
+                None => {
+                    let mut builder = AssumeRoleProvider::builder(role_arn)
+                        .session_name(&self.role_session_name)
+                        .configure(&config);
+                    if let Some(external_id) = &self.external_id {
+                        builder = builder.external_id(external_id);
+                    }
+                    SharedCredentialsProvider::new(builder.build().await)
+                }
+            };
+            config = config.into_builder().credentials_provider(provider).build();
+        }
+
+        let provider = config
+            .credentials_provider()
+            .context("No credentials provider configured")?;
+        let credentials = provider
+            .provide_credentials()
+            .await
+            .context("Failed to resolve credentials; set AWS_ACCESS_KEY_ID/AWS_SECRET_ACCESS_KEY, use --profile, or pass --anonymous")?;
+        if credentials.access_key_id() == PLACEHOLDER_KEY || credentials.secret_access_key() == PLACEHOLDER_KEY {
+            anyhow::bail!("Refusing to use the placeholder credentials '{}' from {}", PLACEHOLDER_KEY, self);
+        }
+        Ok(config)
+    }
+}
+
+impl fmt::Display for CredentialArgs {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.anonymous {
+            return write!(f, "anonymous");
+        }
+        match (&self.access_key, &self.profile) {
+            (Some(_), _) => write!(f, "command-line keys")?,
+            (None, Some(profile)) => write!(f, "profile '{}'", profile)?,
+            (None, None) => write!(f, "default provider chain")?,
+        }
+        if let Some(role_arn) = &self.role_arn {
+            match self.web_identity_token_file {
+                Some(_) => write!(f, ", web identity for role {}", role_arn)?,
+                None => write!(f, ", assuming role {}", role_arn)?,
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,537 @@
+//! Distributed load generation. Agents listen on a TCP port; the coordinator
+//! pushes the workload (as the same command-line arguments the standalone
+//! benchmark takes) to every agent, waits until all of them have prepared it,
+//! schedules a common wall-clock start, and merges the counters and latency
+//! histograms they report into one result.
+//!
+//! The protocol is one JSON message per line:
+//! coordinator `job` -> agent `ready` -> coordinator `start` -> agent `report`.
+//! Agents compare the coordinator's clock with their own and warn about skew;
+//! the synchronized start assumes the hosts are NTP-synced. Every job carries a
+//! shared token the agent checks before running anything. The connection is not
+//! encrypted, so static keys are never forwarded: agents resolve credentials from
+//! their own environment.
+
+use super::client::RequestCounts;
+use super::endpoints::EndpointStats;
+use super::transport::ConnectionCounts;
+use super::{Stats, Workload};
+use anyhow::{Context, Result};
+use clap::{Args, Parser};
+use hdrhistogram::Histogram;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::{TcpListener, TcpStream};
+
+/// Clock differences above this are reported by agents
+const MAX_CLOCK_SKEW: Duration = Duration::from_millis(250);
+
+/// Longest message either side accepts; latency histograms in reports stay far below this
+const MAX_MESSAGE_BYTES: u64 = 8 * 1024 * 1024;
+
+/// Workload options whose values would cross the unencrypted connection in the clear
+const SECRET_OPTIONS: [&str; 2] = ["--secret-key", "--session-token"];
+
+#[derive(Args)]
+pub(crate) struct AgentArgs {
+    /// Address to accept coordinator connections on; bind a routable address to
+    /// accept remote coordinators
+    #[arg(long, default_value = "127.0.0.1:7878")]
+    listen: String,
+    /// Shared secret coordinators must present
+    #[arg(long, env = "S3_LOAD_GEN_TOKEN", hide_env_values = true)]
+    token: String,
+    /// Drop a coordinator that has not sent its job within this time
+    #[arg(long, default_value = "10")]
+    job_timeout_secs: u64,
+    /// Abandon a prepared job the coordinator has not started within this time
+    #[arg(long, default_value = "3600")]
+    start_timeout_secs: u64,
+    /// Exit after serving a single job
+    #[arg(long)]
+    once: bool,
+}
+
+#[derive(Args)]
+pub(crate) struct CoordinateArgs {
+    /// Agent addresses (host:port), comma-separated or repeated
+    #[arg(long, value_delimiter = ',', required = true)]
+    agents: Vec<String>,
+    /// Delay between the last agent reporting ready and the synchronized start
+    #[arg(long, default_value = "3")]
+    start_delay_secs: u64,
+    /// Shared secret the agents were started with
+    #[arg(long, env = "S3_LOAD_GEN_TOKEN", hide_env_values = true)]
+    token: String,
+    /// Give up on an agent that does not accept the connection within this time
+    #[arg(long, default_value = "10")]
+    connect_timeout_secs: u64,
+    /// Give up on an agent that has not reported ready, or its results after the
+    /// start, within this time; raise it for long runs
+    #[arg(long, default_value = "3600")]
+    report_timeout_secs: u64,
+    /// Workload subcommand and its options (e.g. `put --endpoint ... --concurrent 50`),
+    /// forwarded verbatim to every agent; --concurrent is per agent
+    #[arg(trailing_var_arg = true, allow_hyphen_values = true, required = true)]
+    workload: Vec<String>,
+}
+
+/// Parses the forwarded workload arguments on both sides of the connection
+#[derive(Parser)]
+#[command(name = "workload", no_binary_name = true)]
+struct WorkloadCli {
+    #[command(subcommand)]
+    workload: Workload,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Message {
+    Job { token: String, workload: Vec<String> },
+    Ready,
+    Start { at_unix_ms: u64, coordinator_unix_ms: u64 },
+    Report(AgentReport),
+    Error { message: String },
+}
+
+/// Counters and latency distribution of one agent's run
+#[derive(Serialize, Deserialize)]
+struct AgentReport {
+    operations: u64,
+    errors: u64,
+    excluded: u64,
+    bytes_transferred: u64,
+    objects_listed: u64,
+    duration_secs: f64,
+    total_latency_ms: f64,
+    /// Recorded latency histogram as (microseconds, count) pairs
+    latency_us: Vec<(u64, u64)>,
+    /// Requests, retries and timeouts by S3 operation
+    requests: BTreeMap<String, RequestCounts>,
+    connections: ConnectionCounts,
+    /// Breakdown by endpoint, when the workload spreads over several
+    endpoints: Vec<EndpointReport>,
+}
+
+/// Counters and latency distribution of one agent's operations on one endpoint
+#[derive(Serialize, Deserialize)]
+struct EndpointReport {
+    endpoint: String,
+    operations: u64,
+    errors: u64,
+    bytes_transferred: u64,
+    latency_us: Vec<(u64, u64)>,
+}
+
+impl From<&EndpointStats> for EndpointReport {
+    fn from(stats: &EndpointStats) -> Self {
+        EndpointReport {
+            endpoint: stats.endpoint.clone(),
+            operations: stats.operations,
+            errors: stats.errors,
+            bytes_transferred: stats.bytes_transferred,
+            latency_us: recorded(&stats.latency),
+        }
+    }
+}
+
+impl EndpointReport {
+    fn merge_into(&self, endpoints: &mut Vec<EndpointStats>) {
+        let index = match endpoints.iter().position(|stats| stats.endpoint == self.endpoint) {
+            Some(index) => index,
+            None => {
+                endpoints.push(EndpointStats::new(&self.endpoint));
+                endpoints.len() - 1
+            }
+        };
+        let stats = &mut endpoints[index];
+        stats.operations += self.operations;
+        stats.errors += self.errors;
+        stats.bytes_transferred += self.bytes_transferred;
+        for &(value, count) in &self.latency_us {
+            stats.latency.saturating_record_n(value, count);
+        }
+    }
+}
+
+fn recorded(latency: &Histogram<u64>) -> Vec<(u64, u64)> {
+    latency.iter_recorded().map(|v| (v.value_iterated_to(), v.count_at_value())).collect()
+}
+
+impl From<&Stats> for AgentReport {
+    fn from(stats: &Stats) -> Self {
+        AgentReport {
+            operations: stats.operations,
+            errors: stats.errors,
+            excluded: stats.excluded,
+            bytes_transferred: stats.bytes_transferred,
+            objects_listed: stats.objects_listed,
+            duration_secs: stats.duration.as_secs_f64(),
+            total_latency_ms: stats.total_latency_ms,
+            latency_us: recorded(&stats.latency),
+            requests: stats.requests.clone(),
+            connections: stats.connections,
+            endpoints: stats.endpoints.iter().map(EndpointReport::from).collect(),
+        }
+    }
+}
+
+impl AgentReport {
+    /// Add this agent's results to `stats`. Agents start together, so the merged
+    /// duration is the longest agent run rather than the sum.
+    fn merge_into(&self, stats: &mut Stats) {
+        stats.operations += self.operations;
+        stats.errors += self.errors;
+        stats.excluded += self.excluded;
+        stats.bytes_transferred += self.bytes_transferred;
+        stats.objects_listed += self.objects_listed;
+        stats.total_latency_ms += self.total_latency_ms;
+        stats.duration = stats.duration.max(Duration::from_secs_f64(self.duration_secs));
+        for &(value, count) in &self.latency_us {
+            stats.latency.saturating_record_n(value, count);
+        }
+        for (operation, counts) in &self.requests {
+            stats.requests.entry(operation.clone()).or_default().add(counts);
+        }
+        stats.connections.add(&self.connections);
+        for endpoint in &self.endpoints {
+            endpoint.merge_into(&mut stats.endpoints);
+        }
+    }
+}
+
+/// Line-delimited JSON framing over one TCP connection
+struct Connection {
+    reader: BufReader<OwnedReadHalf>,
+    writer: OwnedWriteHalf,
+}
+
+impl Connection {
+    fn new(stream: TcpStream) -> Self {
+        let (reader, writer) = stream.into_split();
+        Connection {
+            reader: BufReader::new(reader),
+            writer,
+        }
+    }
+
+    async fn send(&mut self, message: &Message) -> Result<()> {
+        let mut line = serde_json::to_vec(message)?;
+        line.push(b'\n');
+        self.writer.write_all(&line).await.context("Failed to send message")?;
+        Ok(())
+    }
+
+    async fn recv(&mut self) -> Result<Message> {
+        let mut line = String::new();
+        let read = (&mut self.reader)
+            .take(MAX_MESSAGE_BYTES + 1)
+            .read_line(&mut line)
+            .await
+            .context("Failed to read message")?;
+        if read == 0 {
+            anyhow::bail!("Connection closed by peer");
+        }
+        if read as u64 > MAX_MESSAGE_BYTES {
+            anyhow::bail!("Message exceeds {} bytes", MAX_MESSAGE_BYTES);
+        }
+        serde_json::from_str(&line).context("Malformed message")
+    }
+}
+
+fn unix_ms() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64
+}
+
+fn parse_workload(args: &[String]) -> Result<Workload> {
+    WorkloadCli::try_parse_from(args)
+        .map(|cli| cli.workload)
+        .map_err(|e| anyhow::anyhow!("Invalid workload arguments: {}", e))
+}
+
+/// Compare without returning early, so the time taken does not reveal how much of
+/// a guess matched
+fn token_matches(expected: &str, presented: &str) -> bool {
+    expected.len() == presented.len()
+        && expected.bytes().zip(presented.bytes()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+/// Bound a step of the exchange with one agent
+async fn within<T>(limit: Duration, step: impl std::future::Future<Output = Result<T>>) -> Result<T> {
+    tokio::time::timeout(limit, step)
+        .await
+        .map_err(|_| anyhow::anyhow!("Timed out after {:?}", limit))?
+}
+
+pub(crate) async fn run_agent(args: AgentArgs) -> Result<()> {
+    if args.token.is_empty() {
+        anyhow::bail!("--token must not be empty");
+    }
+    let listener = TcpListener::bind(&args.listen)
+        .await
+        .with_context(|| format!("Failed to listen on {}", args.listen))?;
+    println!("[AGENT] Listening on {}", listener.local_addr()?);
+    let timeouts = AgentTimeouts {
+        job: Duration::from_secs(args.job_timeout_secs),
+        start: Duration::from_secs(args.start_timeout_secs),
+    };
+    serve(listener, &args.token, timeouts, args.once).await
+}
+
+/// How long an agent waits for each message from the coordinator; it serves one
+/// connection at a time, so a silent peer must not hold it forever
+#[derive(Clone, Copy)]
+struct AgentTimeouts {
+    job: Duration,
+    start: Duration,
+}
+
+async fn serve(listener: TcpListener, token: &str, timeouts: AgentTimeouts, once: bool) -> Result<()> {
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        println!("[AGENT] Coordinator connected from {}", peer);
+        let mut conn = Connection::new(stream);
+
+        if let Err(e) = serve_job(&mut conn, token, timeouts).await {
+            println!("[AGENT] Job failed: {:?}", e);
+            // Best effort: the coordinator may already be gone
+            let _ = conn.send(&Message::Error { message: format!("{:#}", e) }).await;
+        }
+
+        if once {
+            return Ok(());
+        }
+    }
+}
+
+async fn serve_job(conn: &mut Connection, token: &str, timeouts: AgentTimeouts) -> Result<()> {
+    let job = within(timeouts.job, conn.recv()).await.context("No job from the coordinator")?;
+    let Message::Job { token: presented, workload } = job else {
+        anyhow::bail!("Expected a job from the coordinator");
+    };
+    if !token_matches(token, &presented) {
+        anyhow::bail!("Rejected job: invalid token");
+    }
+    println!("[AGENT] Received workload: {}", workload.join(" "));
+    let workload = parse_workload(&workload)?;
+    let concurrent = workload.concurrent();
+    let workload = workload.prepare().await?;
+    workload.print_config();
+    conn.send(&Message::Ready).await?;
+
+    let start = within(timeouts.start, conn.recv()).await.context("No start time from the coordinator")?;
+    let Message::Start { at_unix_ms, coordinator_unix_ms } = start else {
+        anyhow::bail!("Expected a start time from the coordinator");
+    };
+    let now = unix_ms();
+    if now.abs_diff(coordinator_unix_ms) > MAX_CLOCK_SKEW.as_millis() as u64 {
+        println!(
+            "[AGENT] Warning: local clock differs from the coordinator by {} ms (including network delay)",
+            now as i64 - coordinator_unix_ms as i64
+        );
+    }
+    if at_unix_ms > now {
+        println!("[AGENT] Starting in {} ms", at_unix_ms - now);
+        tokio::time::sleep(Duration::from_millis(at_unix_ms - now)).await;
+    } else {
+        println!("[AGENT] Warning: start time already passed by {} ms, starting now", now - at_unix_ms);
+    }
+
+    let stats = workload.execute(concurrent).await?;
+    stats.print(workload.label());
+    conn.send(&Message::Report(AgentReport::from(&stats))).await?;
+    Ok(())
+}
+
+pub(crate) async fn run_coordinator(args: CoordinateArgs) -> Result<()> {
+    coordinate(args).await.map(|_| ())
+}
+
+/// Run the workload on every agent and return the merged results
+async fn coordinate(args: CoordinateArgs) -> Result<Stats> {
+    // Validate locally so typos fail here rather than on every agent
+    let workload = parse_workload(&args.workload)?;
+    if let Some(option) = args
+        .workload
+        .iter()
+        .find(|arg| SECRET_OPTIONS.iter().any(|secret| arg.split('=').next() == Some(*secret)))
+    {
+        anyhow::bail!(
+            "Refusing to forward {} over the unencrypted agent connection; let each agent resolve credentials from its environment, --profile or instance role",
+            option.split('=').next().unwrap_or_default()
+        );
+    }
+    let connect_timeout = Duration::from_secs(args.connect_timeout_secs);
+    let report_timeout = Duration::from_secs(args.report_timeout_secs);
+    let label = match workload {
+        Workload::Put(_) => "PUT",
+        Workload::Get(_) => "GET",
+        Workload::List(_) => "LIST",
+        Workload::Copy(_) => "COPY",
+    };
+
+    println!("Starting distributed {} benchmark...", label);
+    println!("Endpoint: {}", workload.endpoint());
+    println!("Agents: {}", args.agents.join(", "));
+    println!("Concurrent operations per agent: {}", workload.concurrent());
+
+    let mut conns = Vec::with_capacity(args.agents.len());
+    for agent in &args.agents {
+        let stream = within(connect_timeout, async { Ok(TcpStream::connect(agent).await?) })
+            .await
+            .with_context(|| format!("Failed to connect to agent {}", agent))?;
+        let mut conn = Connection::new(stream);
+        conn.send(&Message::Job { token: args.token.clone(), workload: args.workload.clone() }).await?;
+        conns.push(conn);
+    }
+
+    // Agents prepare in parallel (GET lists the bucket), wait for all of them
+    for (agent, conn) in args.agents.iter().zip(conns.iter_mut()) {
+        match within(report_timeout, conn.recv()).await.with_context(|| format!("Agent {} did not become ready", agent))? {
+            Message::Ready => println!("[COORD] Agent {} ready", agent),
+            Message::Error { message } => anyhow::bail!("Agent {} failed to prepare: {}", agent, message),
+            _ => anyhow::bail!("Unexpected message from agent {}", agent),
+        }
+    }
+
+    let start_at = unix_ms() + args.start_delay_secs * 1000;
+    println!("[COORD] All agents ready, starting in {}s", args.start_delay_secs);
+    for conn in conns.iter_mut() {
+        conn.send(&Message::Start { at_unix_ms: start_at, coordinator_unix_ms: unix_ms() }).await?;
+    }
+
+    // The wait for results starts before the delayed start
+    let report_timeout = report_timeout + Duration::from_secs(args.start_delay_secs);
+    let reports = futures::future::join_all(conns.iter_mut().map(|conn| within(report_timeout, conn.recv()))).await;
+
+    let mut merged = Stats::new();
+    let mut failed = 0;
+    println!("\n=== Per-Agent Results ===");
+    for (agent, report) in args.agents.iter().zip(reports) {
+        match report {
+            Ok(Message::Report(report)) => {
+                println!(
+                    "{}: {} ops, {} errors, {:.2}s, {:.2} MB",
+                    agent,
+                    report.operations,
+                    report.errors,
+                    report.duration_secs,
+                    report.bytes_transferred as f64 / 1_048_576.0
+                );
+                report.merge_into(&mut merged);
+            }
+            Ok(Message::Error { message }) => {
+                println!("{}: failed: {}", agent, message);
+                failed += 1;
+            }
+            Ok(_) => {
+                println!("{}: unexpected message", agent);
+                failed += 1;
+            }
+            Err(e) => {
+                println!("{}: no report: {:#}", agent, e);
+                failed += 1;
+            }
+        }
+    }
+
+    merged.print(&format!("Distributed {} ({} agents)", label, args.agents.len() - failed));
+    if matches!(workload, Workload::List(_)) {
+        super::ListWorkload::print_summary(&merged);
+    }
+    if failed > 0 {
+        anyhow::bail!("{} of {} agents failed", failed, args.agents.len());
+    }
+
+    Ok(merged)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_server::MockServer;
+    use crate::{Cli, Commands};
+
+    const TOKEN: &str = "test-token";
+    const TIMEOUTS: AgentTimeouts = AgentTimeouts {
+        job: Duration::from_millis(200),
+        start: Duration::from_secs(10),
+    };
+
+    fn coordinate_args(agents: &[String], token: &str, workload: &[&str]) -> CoordinateArgs {
+        let agents = agents.join(",");
+        let args = ["s3-load-gen", "coordinate", "--agents", &agents, "--token", token, "--start-delay-secs", "0"];
+        match Cli::try_parse_from(args.iter().chain(workload)).unwrap().command {
+            Commands::Coordinate(args) => args,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Agents on ephemeral localhost ports, each serving a single job
+    async fn start_agents(count: usize) -> Vec<String> {
+        let mut agents = Vec::with_capacity(count);
+        for _ in 0..count {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            agents.push(listener.local_addr().unwrap().to_string());
+            tokio::spawn(async move { serve(listener, TOKEN, TIMEOUTS, true).await });
+        }
+        agents
+    }
+
+    #[tokio::test]
+    async fn merges_results_from_several_agents() {
+        let server = MockServer::start("127.0.0.1:0").await.unwrap();
+        let agents = start_agents(3).await;
+        let workload = ["put", "--endpoint", &server.endpoint, "--bucket", "bench", "--anonymous", "--ops", "4", "--object-size", "100"];
+        let stats = coordinate(coordinate_args(&agents, TOKEN, &workload)).await.unwrap();
+
+        assert_eq!((stats.operations, stats.errors), (12, 0));
+        assert_eq!(stats.bytes_transferred, 1200);
+        assert_eq!(stats.latency.len(), 12);
+        assert_eq!(stats.endpoints.len(), 1);
+        assert_eq!(stats.endpoints[0].endpoint, server.endpoint);
+        assert_eq!(stats.endpoints[0].operations, 12);
+    }
+
+    #[tokio::test]
+    async fn agents_reject_a_wrong_token() {
+        let server = MockServer::start("127.0.0.1:0").await.unwrap();
+        let agents = start_agents(2).await;
+        let workload = ["put", "--endpoint", &server.endpoint, "--bucket", "bench", "--anonymous", "--ops", "1"];
+        let err = coordinate(coordinate_args(&agents, "wrong", &workload)).await.err().unwrap();
+        assert!(format!("{:#}", err).contains("invalid token"), "{:#}", err);
+    }
+
+    #[tokio::test]
+    async fn agents_drop_silent_and_oversized_peers() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let agent = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move { serve(listener, TOKEN, TIMEOUTS, false).await });
+
+        // Stays connected without sending; the agent gives up on it and takes the next peer
+        let mut silent = Connection::new(TcpStream::connect(&agent).await.unwrap());
+        let mut flooding = Connection::new(TcpStream::connect(&agent).await.unwrap());
+        let Message::Error { message } = silent.recv().await.unwrap() else { panic!("expected an error") };
+        assert!(message.contains("No job"), "{}", message);
+
+        let flood = vec![b'x'; MAX_MESSAGE_BYTES as usize + 1];
+        let _ = flooding.writer.write_all(&flood).await;
+        let Message::Error { message } = flooding.recv().await.unwrap() else { panic!("expected an error") };
+        assert!(message.contains("exceeds"), "{}", message);
+
+        let server = MockServer::start("127.0.0.1:0").await.unwrap();
+        let workload = ["put", "--endpoint", &server.endpoint, "--bucket", "bench", "--anonymous", "--ops", "1"];
+        let stats = coordinate(coordinate_args(&[agent], TOKEN, &workload)).await.unwrap();
+        assert_eq!((stats.operations, stats.errors), (1, 0));
+    }
+
+    #[tokio::test]
+    async fn static_secrets_are_not_forwarded() {
+        let workload = ["put", "--endpoint", "http://localhost:9000", "--bucket", "bench", "--access-key", "a", "--secret-key=b"];
+        let err = coordinate(coordinate_args(&["127.0.0.1:1".to_string()], TOKEN, &workload)).await.err().unwrap();
+        assert!(err.to_string().contains("--secret-key"), "{}", err);
+    }
+}
@@ -0,0 +1,157 @@
+//! Client-side load balancing across several endpoints, e.g. storage gateway
+//! nodes behind a VIP. Every endpoint gets its own S3 client; each operation is
+//! sent to one endpoint picked by the selection policy, and results are also
+//! broken down per endpoint so an unhealthy node stands out.
+
+use super::client::RequestCounters;
+use super::transport::ConnectionCounters;
+use anyhow::{Context, Result};
+use aws_sdk_s3::Client as S3Client;
+use clap::ValueEnum;
+use hdrhistogram::Histogram;
+use rand::Rng;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// How the endpoint for the next operation is chosen
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub(crate) enum Selection {
+    /// Cycle through the endpoints in order
+    RoundRobin,
+    /// Pick an endpoint uniformly at random
+    Random,
+    /// Pick the endpoint with the fewest operations in flight
+    LeastOutstanding,
+}
+
+/// Read endpoint URLs from a file, one per line; blank lines and `#` comments are skipped
+pub(crate) fn read_endpoints_file(path: &Path) -> Result<Vec<String>> {
+    let text = std::fs::read_to_string(path).with_context(|| format!("Failed to read endpoints file {}", path.display()))?;
+    Ok(text
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or("").trim())
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect())
+}
+
+struct Endpoint {
+    url: String,
+    client: S3Client,
+    outstanding: AtomicUsize,
+}
+
+/// The endpoints of one benchmark and the request and connection counters their clients share
+pub(crate) struct EndpointPool {
+    endpoints: Vec<Endpoint>,
+    selection: Selection,
+    next: AtomicUsize,
+    pub(crate) requests: Arc<RequestCounters>,
+    pub(crate) connections: Arc<ConnectionCounters>,
+}
+
+impl EndpointPool {
+    pub(crate) fn new(
+        clients: Vec<(String, S3Client)>,
+        selection: Selection,
+        requests: Arc<RequestCounters>,
+        connections: Arc<ConnectionCounters>,
+    ) -> Self {
+        assert!(!clients.is_empty(), "endpoint pool needs at least one endpoint");
+        let endpoints = clients
+            .into_iter()
+            .map(|(url, client)| Endpoint {
+                url,
+                client,
+                outstanding: AtomicUsize::new(0),
+            })
+            .collect();
+        EndpointPool {
+            endpoints,
+            selection,
+            next: AtomicUsize::new(0),
+            requests,
+            connections,
+        }
+    }
+
+    pub(crate) fn urls(&self) -> impl Iterator<Item = &str> {
+        self.endpoints.iter().map(|endpoint| endpoint.url.as_str())
+    }
+
+    /// Client of the first endpoint, for setup work such as listing objects before a run
+    pub(crate) fn primary(&self) -> &S3Client {
+        &self.endpoints[0].client
+    }
+
+    /// Choose the endpoint for one operation; it counts as outstanding until the lease is dropped
+    pub(crate) fn pick(self: &Arc<Self>) -> Lease {
+        let count = self.endpoints.len();
+        let index = match self.selection {
+            Selection::RoundRobin => self.next.fetch_add(1, Ordering::Relaxed) % count,
+            Selection::Random => rand::thread_rng().gen_range(0..count),
+            Selection::LeastOutstanding => {
+                // Rotate the starting point so ties are spread evenly
+                let start = self.next.fetch_add(1, Ordering::Relaxed);
+                (0..count)
+                    .map(|offset| (start + offset) % count)
+                    .min_by_key(|&index| self.endpoints[index].outstanding.load(Ordering::Relaxed))
+                    .unwrap_or(0)
+            }
+        };
+        self.endpoints[index].outstanding.fetch_add(1, Ordering::Relaxed);
+        Lease {
+            pool: self.clone(),
+            index,
+        }
+    }
+}
+
+/// One in-flight operation's claim on an endpoint
+pub(crate) struct Lease {
+    pool: Arc<EndpointPool>,
+    index: usize,
+}
+
+impl Lease {
+    pub(crate) fn index(&self) -> usize {
+        self.index
+    }
+
+    pub(crate) fn client(&self) -> &S3Client {
+        &self.pool.endpoints[self.index].client
+    }
+}
+
+impl Drop for Lease {
+    fn drop(&mut self) {
+        self.pool.endpoints[self.index].outstanding.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Measured operations sent to one endpoint
+pub(crate) struct EndpointStats {
+    pub(crate) endpoint: String,
+    pub(crate) operations: u64,
+    pub(crate) errors: u64,
+    pub(crate) bytes_transferred: u64,
+    /// Latency of successful operations in microseconds
+    pub(crate) latency: Histogram<u64>,
+}
+
+impl EndpointStats {
+    pub(crate) fn new(endpoint: &str) -> Self {
+        EndpointStats {
+            endpoint: endpoint.to_string(),
+            operations: 0,
+            errors: 0,
+            bytes_transferred: 0,
+            latency: Histogram::new_with_bounds(1, super::MAX_TRACKED_LATENCY_US, 3).expect("valid histogram bounds"),
+        }
+    }
+
+    pub(crate) fn percentile_ms(&self, quantile: f64) -> f64 {
+        self.latency.value_at_quantile(quantile) as f64 / 1000.0
+    }
+}
@@ -0,0 +1,247 @@
+//! LIST variants. An operation lists either every page under the prefix or a
+//! single page, through ListObjectsV2 or the legacy ListObjects (v1) API,
+//! optionally rolling keys up into common prefixes at a delimiter and starting
+//! after a random existing key. Every page fetched is timed on its own, so the
+//! per-request cost stays visible when an operation spans many pages.
+
+use super::{MeasurementWindow, MAX_TRACKED_LATENCY_US};
+use anyhow::{Context, Result};
+use aws_sdk_s3::Client as S3Client;
+use clap::{Args, ValueEnum};
+use hdrhistogram::Histogram;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// S3's limit on keys per LIST page
+const MAX_PAGE_SIZE: i32 = 1000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub(crate) enum ListApi {
+    /// ListObjectsV2, paginated with continuation tokens
+    V2,
+    /// The legacy ListObjects, paginated with markers
+    V1,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub(crate) enum ListScope {
+    /// Follow pagination to the end of the listing
+    Full,
+    /// Fetch a single page
+    Page,
+}
+
+/// How each LIST operation lists
+#[derive(Args, Clone, Debug)]
+pub(crate) struct ListOptions {
+    /// LIST API to call
+    #[arg(long, value_enum, default_value = "v2")]
+    api: ListApi,
+    /// Roll keys up into common prefixes at this delimiter (e.g. /); each common prefix counts as one entry
+    #[arg(long)]
+    delimiter: Option<String>,
+    /// Keys per page (at most 1000)
+    #[arg(long, default_value = "1000")]
+    max_keys: i32,
+    /// Start each operation after a random existing key under the prefix
+    #[arg(long)]
+    pub(crate) random_start_after: bool,
+    /// Whether an operation lists every page or one
+    #[arg(long, value_enum, default_value = "full")]
+    scope: ListScope,
+}
+
+impl ListOptions {
+    pub(crate) fn validate(&self) -> Result<()> {
+        if !(1..=MAX_PAGE_SIZE).contains(&self.max_keys) {
+            anyhow::bail!("--max-keys must be between 1 and {}", MAX_PAGE_SIZE);
+        }
+        if self.delimiter.as_deref() == Some("") {
+            anyhow::bail!("--delimiter must not be empty");
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for ListOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.api {
+            ListApi::V2 => write!(f, "ListObjectsV2")?,
+            ListApi::V1 => write!(f, "ListObjects (v1)")?,
+        }
+        write!(f, ", max-keys {}", self.max_keys)?;
+        if let Some(delimiter) = &self.delimiter {
+            write!(f, ", delimiter '{}'", delimiter)?;
+        }
+        match self.scope {
+            ListScope::Full => write!(f, ", full listing per operation")?,
+            ListScope::Page => write!(f, ", one page per operation")?,
+        }
+        if self.random_start_after {
+            write!(f, ", random start-after")?;
+        }
+        Ok(())
+    }
+}
+
+/// One page of either API: entries listed and where the next page starts
+struct Page {
+    entries: usize,
+    next: Option<String>,
+}
+
+/// Successful page requests of the current run
+struct PageLog {
+    start: Instant,
+    /// When each page completed, as an offset from `start`, and its latency in microseconds
+    pages: Vec<(Duration, u64)>,
+}
+
+/// Runs LIST operations and collects the latency of every page they fetch; cheap to clone into operations
+#[derive(Clone)]
+pub(crate) struct Lister {
+    options: ListOptions,
+    log: Arc<Mutex<PageLog>>,
+}
+
+impl Lister {
+    pub(crate) fn new(options: ListOptions) -> Self {
+        Lister {
+            options,
+            log: Arc::new(Mutex::new(PageLog { start: Instant::now(), pages: Vec::new() })),
+        }
+    }
+
+    /// Forget the pages of earlier runs, e.g. the previous level of a sweep; the
+    /// measurement window of the next run is relative to this call
+    pub(crate) fn begin_run(&self) {
+        let mut log = self.log.lock().unwrap();
+        log.start = Instant::now();
+        log.pages.clear();
+    }
+
+    /// Latency of the pages of this run that completed inside `window`
+    pub(crate) fn page_latency(&self, window: Option<MeasurementWindow>) -> Histogram<u64> {
+        let mut histogram = Histogram::new_with_bounds(1, MAX_TRACKED_LATENCY_US, 3).expect("valid histogram bounds");
+        let log = self.log.lock().unwrap();
+        for &(completed_at, latency) in &log.pages {
+            if window.is_none_or(|w| w.contains(completed_at)) {
+                histogram.saturating_record(latency);
+            }
+        }
+        histogram
+    }
+
+    pub(crate) fn options(&self) -> &ListOptions {
+        &self.options
+    }
+
+    /// One LIST operation; returns the keys and common prefixes listed
+    pub(crate) async fn list(&self, client: &S3Client, bucket: &str, prefix: &str, start_after: Option<String>) -> Result<usize> {
+        println!("[LIST] Starting list operation with prefix: '{}'", prefix);
+        let mut count = 0;
+        let mut next = start_after;
+        let mut page = 1;
+
+        loop {
+            println!("[LIST] Fetching page {} for prefix: '{}'", page, prefix);
+            let start = Instant::now();
+            let result = match self.options.api {
+                ListApi::V2 => self.page_v2(client, bucket, prefix, next, page == 1).await?,
+                ListApi::V1 => self.page_v1(client, bucket, prefix, next).await?,
+            };
+            self.record_page(start.elapsed());
+
+            count += result.entries;
+            println!("[LIST] Page {} returned {} entries (total so far: {})", page, result.entries, count);
+
+            match result.next {
+                Some(marker) if self.options.scope == ListScope::Full => {
+                    next = Some(marker);
+                    page += 1;
+                }
+                _ => break,
+            }
+        }
+
+        println!("[LIST] Completed list operation with prefix: '{}' (total: {} entries)", prefix, count);
+        Ok(count)
+    }
+
+    fn record_page(&self, latency: Duration) {
+        let mut log = self.log.lock().unwrap();
+        let completed_at = log.start.elapsed();
+        log.pages.push((completed_at, latency.as_micros() as u64));
+    }
+
+    /// `after` is the start-after key on the first page and the continuation token on later ones
+    async fn page_v2(&self, client: &S3Client, bucket: &str, prefix: &str, after: Option<String>, first: bool) -> Result<Page> {
+        let mut request = client
+            .list_objects_v2()
+            .bucket(bucket)
+            .max_keys(self.options.max_keys)
+            .set_delimiter(self.options.delimiter.clone());
+        if !prefix.is_empty() {
+            request = request.prefix(prefix);
+        }
+        request = if first {
+            request.set_start_after(after)
+        } else {
+            request.set_continuation_token(after)
+        };
+
+        let resp = request.send().await.context("Failed to list objects")?;
+        let next = match resp.is_truncated() {
+            Some(true) => resp.next_continuation_token().map(String::from),
+            _ => None,
+        };
+        Ok(Page {
+            entries: resp.contents().len() + resp.common_prefixes().len(),
+            next,
+        })
+    }
+
+    /// S3 only returns NextMarker when a delimiter is given; otherwise the next page
+    /// starts after the last key (or common prefix) of this one
+    async fn page_v1(&self, client: &S3Client, bucket: &str, prefix: &str, marker: Option<String>) -> Result<Page> {
+        let mut request = client
+            .list_objects()
+            .bucket(bucket)
+            .max_keys(self.options.max_keys)
+            .set_delimiter(self.options.delimiter.clone())
+            .set_marker(marker);
+        if !prefix.is_empty() {
+            request = request.prefix(prefix);
+        }
+
+        let resp = request.send().await.context("Failed to list objects")?;
+        let next = match resp.is_truncated() {
+            Some(true) => resp.next_marker().map(String::from).or_else(|| {
+                let last_key = resp.contents().last().and_then(|object| object.key());
+                let last_prefix = resp.common_prefixes().last().and_then(|common| common.prefix());
+                last_key.max(last_prefix).map(String::from)
+            }),
+            _ => None,
+        };
+        Ok(Page {
+            entries: resp.contents().len() + resp.common_prefixes().len(),
+            next,
+        })
+    }
+
+    pub(crate) fn print_pages(&self, window: Option<MeasurementWindow>) {
+        let pages = self.page_latency(window);
+        let ms = |micros: u64| Duration::from_micros(micros).as_secs_f64() * 1000.0;
+        println!("Pages fetched: {}", pages.len());
+        if !pages.is_empty() {
+            println!(
+                "Page latency p50/p90/p99/max: {:.2} / {:.2} / {:.2} / {:.2} ms",
+                ms(pages.value_at_quantile(0.5)),
+                ms(pages.value_at_quantile(0.9)),
+                ms(pages.value_at_quantile(0.99)),
+                ms(pages.max())
+            );
+        }
+    }
+}
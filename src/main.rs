@@ -1,17 +1,47 @@
 use anyhow::{Context, Result};
-use aws_config::BehaviorVersion;
-use aws_credential_types::Credentials;
-use aws_sdk_s3::config::{Builder as S3ConfigBuilder, Region};
-use aws_sdk_s3::primitives::ByteStream;
+use aws_sdk_s3::config::Builder as S3ConfigBuilder;
 use aws_sdk_s3::types::{CompletedMultipartUpload, CompletedPart};
 use aws_sdk_s3::Client as S3Client;
-use bytes::Bytes;
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use hdrhistogram::Histogram;
 use indicatif::{ProgressBar, ProgressStyle};
-use rand::RngCore;
+use client::{ClientArgs, CountingInterceptor, RequestCounters, RequestCounts};
+use credentials::CredentialArgs;
+use endpoints::{EndpointPool, EndpointStats};
+use listing::{ListOptions, Lister};
+use metadata::{MetadataArgs, ObjectAttributes};
+use namespace::{Namespace, NamespaceArgs};
+use rand::{Rng, RngCore};
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::path::PathBuf;
+use std::str::FromStr;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
+use throttle::{BandwidthArgs, Throttle};
 use tokio::sync::Semaphore;
+use transport::{ConnectionCounters, ConnectionCounts, HttpArgs, TlsArgs};
+use upload::{Payload, UploadArgs, Uploader};
+
+mod autotune;
+mod client;
+mod conditional;
+mod consistency;
+mod copy;
+mod credentials;
+mod distributed;
+mod endpoints;
+mod listing;
+mod metadata;
+mod mock_server;
+mod multipart;
+mod namespace;
+mod presigned;
+mod sweep;
+mod throttle;
+mod transport;
+mod upload;
+mod versions;
 
 #[derive(Parser)]
 #[command(name = "s3-load-gen")]
@@ -24,84 +54,497 @@ struct Cli {
 #[derive(Subcommand)]
 enum Commands {
     /// Run PUT benchmark
-    Put {
-        #[arg(long, default_value = "changeme")]
-        access_key: String,
-        #[arg(long, default_value = "changeme")]
-        secret_key: String,
-        #[arg(long, default_value = "us-east-1")]
-        region: String,
-        #[arg(long)]
-        endpoint: String,
-        #[arg(long)]
-        bucket: String,
-        #[arg(long, default_value = "60")]
-        duration_secs: u64,
-        #[arg(long, default_value = "10")]
-        concurrent: usize,
-        #[arg(long, default_value = "1048576")] // 1MB default
-        object_size: usize,
-        #[arg(long, default_value = "8388608")] // 8MB default
-        part_size: usize,
-        #[arg(long)]
-        disable_multipart: bool,
-        #[arg(long, default_value = "test-object/")]
-        prefix: String,
-    },
+    Put(PutArgs),
     /// Run GET benchmark
-    Get {
-        #[arg(long, default_value = "changeme")]
-        access_key: String,
-        #[arg(long, default_value = "changeme")]
-        secret_key: String,
-        #[arg(long, default_value = "us-east-1")]
-        region: String,
-        #[arg(long)]
-        endpoint: String,
-        #[arg(long)]
-        bucket: String,
-        #[arg(long, default_value = "60")]
-        duration_secs: u64,
-        #[arg(long, default_value = "10")]
-        concurrent: usize,
-        #[arg(long, default_value = "test-object/")]
-        prefix: String,
-        #[arg(long)]
-        range_bytes: Option<usize>,
-    },
+    Get(GetArgs),
     /// Run LIST benchmark
-    List {
-        #[arg(long, default_value = "changeme")]
-        access_key: String,
-        #[arg(long, default_value = "changeme")]
-        secret_key: String,
-        #[arg(long, default_value = "us-east-1")]
-        region: String,
-        #[arg(long)]
-        endpoint: String,
-        #[arg(long)]
-        bucket: String,
-        #[arg(long, default_value = "60")]
-        duration_secs: u64,
-        #[arg(long, default_value = "10")]
-        concurrent: usize,
-        #[arg(long, default_value = "")]
-        prefix: String,
-    },
+    List(ListArgs),
+    /// Run server-side COPY benchmark
+    Copy(copy::CopyArgs),
+    /// Stress multipart upload bookkeeping: many parts, overwrites, aborts and listings
+    MultipartStress(multipart::MultipartStressArgs),
+    /// Build version stacks in a versioned bucket, then read, list and delete versions
+    Versions(versions::VersionsArgs),
+    /// Measure HeadObject, GetObjectTagging or PutObjectTagging on existing objects
+    Tagging(metadata::TaggingArgs),
+    /// Contend on keys with conditional PUTs (create-only, compare-and-swap) or issue conditional GETs
+    Conditional(conditional::ConditionalArgs),
+    /// Presign GET or PUT URLs, then drive them with a plain HTTP client and optionally compare with signed requests
+    Presigned(presigned::PresignedArgs),
+    /// Check read-after-write and list-after-write consistency while keys are overwritten and deleted
+    Consistency(consistency::ConsistencyArgs),
+    /// Step concurrency through a range of levels to find the saturation point
+    Sweep(sweep::SweepArgs),
+    /// Search for the highest concurrency that keeps tail latency within an SLO
+    Autotune(autotune::AutotuneArgs),
+    /// Serve workloads pushed by a coordinator
+    Agent(distributed::AgentArgs),
+    /// Run a workload on several agents with a synchronized start and merge the results
+    Coordinate(distributed::CoordinateArgs),
+    /// Serve an in-memory S3-compatible endpoint for offline testing
+    MockServer(mock_server::MockServerArgs),
+}
+
+/// Endpoint, bucket and credentials shared by every benchmark
+#[derive(Args, Clone)]
+struct ConnectionArgs {
+    #[command(flatten)]
+    credentials: CredentialArgs,
+    #[arg(long, default_value = "us-east-1")]
+    region: String,
+    /// Endpoint URL; repeat to spread operations over several endpoints
+    #[arg(long, required_unless_present = "endpoints_file")]
+    endpoint: Vec<String>,
+    /// File with one endpoint URL per line, used in addition to --endpoint
+    #[arg(long)]
+    endpoints_file: Option<PathBuf>,
+    /// How each operation picks an endpoint when there are several
+    #[arg(long, value_enum, default_value = "round-robin")]
+    endpoint_selection: endpoints::Selection,
+    #[arg(long)]
+    bucket: String,
+    /// Address buckets as <bucket>.<endpoint host> instead of <endpoint>/<bucket>
+    #[arg(long)]
+    virtual_hosted_style: bool,
+    #[command(flatten)]
+    tls: TlsArgs,
+    #[command(flatten)]
+    http: HttpArgs,
+    #[command(flatten)]
+    client: ClientArgs,
+}
+
+impl ConnectionArgs {
+    fn endpoints(&self) -> Result<Vec<String>> {
+        let mut endpoints = self.endpoint.clone();
+        if let Some(path) = &self.endpoints_file {
+            endpoints.extend(endpoints::read_endpoints_file(path)?);
+        }
+        if endpoints.is_empty() {
+            anyhow::bail!("No endpoints given");
+        }
+        Ok(endpoints)
+    }
+    
+    /// Endpoints as shown in run headers
+    fn describe_endpoints(&self) -> String {
+        let mut parts = self.endpoint.clone();
+        if let Some(path) = &self.endpoints_file {
+            parts.push(format!("endpoints from {}", path.display()));
+        }
+        if parts.len() > 1 || self.endpoints_file.is_some() {
+            format!("{} ({:?} selection)", parts.join(", "), self.endpoint_selection)
+        } else {
+            parts.join(", ")
+        }
+    }
+}
+
+/// When a benchmark stops issuing new operations and which part of the run is measured
+#[derive(Args, Clone, Copy)]
+struct RunArgs {
+    /// Run time in seconds (default: 60, or unlimited when --ops/--total-bytes is given; then it acts as a cap)
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    duration_secs: Option<u64>,
+    /// Stop after issuing this many operations
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    ops: Option<u64>,
+    /// Stop after issuing operations covering this many bytes
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    total_bytes: Option<u64>,
+    /// Exclude operations completing during this initial ramp-up (e.g. 10s)
+    #[arg(long, value_parser = humantime::parse_duration)]
+    warmup: Option<Duration>,
+    /// Exclude operations completing this long before issuing stops, and during the drain
+    #[arg(long, value_parser = humantime::parse_duration)]
+    cooldown: Option<Duration>,
+}
+
+impl RunArgs {
+    fn resolve(self) -> StopCondition {
+        let duration_secs = match (self.duration_secs, self.ops, self.total_bytes) {
+            (Some(secs), _, _) => Some(secs),
+            (None, None, None) => Some(60),
+            (None, _, _) => None,
+        };
+        StopCondition {
+            duration: duration_secs.map(Duration::from_secs),
+            ops: self.ops,
+            bytes: self.total_bytes,
+        }
+    }
+    
+    /// Catch a window that would be empty before running, when the run length is known up front
+    fn validate(&self) -> Result<()> {
+        let excluded = self.warmup.unwrap_or_default() + self.cooldown.unwrap_or_default();
+        if let Some(duration) = self.resolve().duration {
+            if excluded > Duration::ZERO && excluded >= duration {
+                anyhow::bail!(
+                    "Measurement window is empty: warmup + cooldown ({:.2}s) cover the whole {}s run",
+                    excluded.as_secs_f64(),
+                    duration.as_secs()
+                );
+            }
+        }
+        Ok(())
+    }
+    
+    /// Measured window for a run that stopped issuing operations at `issue_end`,
+    /// or `None` when neither --warmup nor --cooldown was given.
+    fn window(&self, issue_end: Duration) -> Result<Option<MeasurementWindow>> {
+        if self.warmup.is_none() && self.cooldown.is_none() {
+            return Ok(None);
+        }
+        let start = self.warmup.unwrap_or_default();
+        let end = issue_end.saturating_sub(self.cooldown.unwrap_or_default());
+        if end <= start {
+            anyhow::bail!(
+                "Measurement window is empty: run issued operations for {:.2}s, warmup + cooldown exceed it",
+                issue_end.as_secs_f64()
+            );
+        }
+        Ok(Some(MeasurementWindow { start, end }))
+    }
+}
+
+/// Steady-state portion of a run, as offsets from the benchmark start
+#[derive(Clone, Copy)]
+struct MeasurementWindow {
+    start: Duration,
+    end: Duration,
+}
+
+impl MeasurementWindow {
+    fn contains(&self, completed_at: Duration) -> bool {
+        completed_at >= self.start && completed_at <= self.end
+    }
+    
+    fn length(&self) -> Duration {
+        self.end - self.start
+    }
+}
+
+/// Whether an operation finishing at `completed_at` counts towards the reported stats
+fn is_measured(window: Option<MeasurementWindow>, start: Instant, completed_at: Instant) -> bool {
+    window.is_none_or(|w| w.contains(completed_at.duration_since(start)))
+}
+
+/// Resolved stop condition; whichever limit is reached first ends the run
+struct StopCondition {
+    duration: Option<Duration>,
+    ops: Option<u64>,
+    bytes: Option<u64>,
+}
+
+impl StopCondition {
+    fn reached(&self, elapsed: Duration, ops: u64, bytes: u64) -> bool {
+        self.duration.is_some_and(|d| elapsed >= d)
+            || self.ops.is_some_and(|n| ops >= n)
+            || self.bytes.is_some_and(|n| bytes >= n)
+    }
+    
+    /// Progress bar measuring the primary target: ops, then bytes, then time
+    fn progress_bar(&self) -> ProgressBar {
+        let (len, template) = if let Some(ops) = self.ops {
+            (ops, "{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} ops ({msg})")
+        } else if let Some(bytes) = self.bytes {
+            (bytes, "{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({msg})")
+        } else {
+            (
+                self.duration.unwrap_or_default().as_secs(),
+                "{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len}s ({msg})",
+            )
+        };
+        let pb = ProgressBar::new(len);
+        pb.set_style(ProgressStyle::default_bar()
+            .template(template)
+            .unwrap()
+            .progress_chars("#>-"));
+        pb
+    }
+    
+    fn update_progress(&self, pb: &ProgressBar, elapsed: Duration, ops: u64, bytes: u64) {
+        let position = if self.ops.is_some() {
+            ops
+        } else if self.bytes.is_some() {
+            bytes
+        } else {
+            elapsed.as_secs()
+        };
+        pb.set_position(position.min(pb.length().unwrap_or(u64::MAX)));
+    }
+}
+
+impl std::fmt::Display for StopCondition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut limits = Vec::new();
+        if let Some(ops) = self.ops {
+            limits.push(format!("{} ops", ops));
+        }
+        if let Some(bytes) = self.bytes {
+            limits.push(format!("{} bytes ({:.2} MB)", bytes, bytes as f64 / 1_048_576.0));
+        }
+        if let Some(duration) = self.duration {
+            limits.push(format!("{}s", duration.as_secs()));
+        }
+        write!(f, "{}", limits.join(" or "))
+    }
+}
+
+#[derive(Args)]
+struct PutArgs {
+    #[command(flatten)]
+    conn: ConnectionArgs,
+    #[command(flatten)]
+    run: RunArgs,
+    #[arg(long, default_value = "10")]
+    concurrent: usize,
+    #[command(flatten)]
+    bandwidth: BandwidthArgs,
+    #[arg(long, default_value = "1048576")] // 1MB default
+    object_size: usize,
+    #[arg(long, default_value = "8388608")] // 8MB default
+    part_size: usize,
+    #[arg(long)]
+    disable_multipart: bool,
+    #[arg(long, default_value = "test-object/")]
+    prefix: String,
+    #[command(flatten)]
+    upload: UploadArgs,
+    #[command(flatten)]
+    namespace: NamespaceArgs,
+    #[command(flatten)]
+    metadata: MetadataArgs,
+}
+
+#[derive(Args)]
+struct GetArgs {
+    #[command(flatten)]
+    conn: ConnectionArgs,
+    #[command(flatten)]
+    run: RunArgs,
+    #[arg(long, default_value = "10")]
+    concurrent: usize,
+    #[command(flatten)]
+    bandwidth: BandwidthArgs,
+    #[arg(long, default_value = "test-object/")]
+    prefix: String,
+    /// Range length: N bytes, MIN..MAX (uniform) or exp:MEAN (exponential)
+    #[arg(long)]
+    range_bytes: Option<RangeLength>,
+    /// Where ranges are placed within each object
+    #[arg(long, value_enum, default_value = "head")]
+    range_pattern: RangePattern,
+    /// Distance between successive chunks for the sequential pattern (default: range length)
+    #[arg(long)]
+    range_stride: Option<u64>,
+    /// Chunks per operation (sequential) or column chunks after the footer (parquet)
+    #[arg(long, default_value = "1")]
+    ranges_per_op: usize,
+    /// Footer size read first by the parquet pattern
+    #[arg(long, default_value = "65536")]
+    footer_bytes: u64,
+}
+
+#[derive(Args)]
+struct ListArgs {
+    #[command(flatten)]
+    conn: ConnectionArgs,
+    #[command(flatten)]
+    run: RunArgs,
+    #[arg(long, default_value = "10")]
+    concurrent: usize,
+    #[arg(long, default_value = "")]
+    prefix: String,
+    #[command(flatten)]
+    list: ListOptions,
+}
+
+/// Placement of ranged reads within an object
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum RangePattern {
+    /// Always read from offset 0
+    Head,
+    /// Read at a uniformly random offset
+    Random,
+    /// Walk through the object in strided chunks across successive visits
+    Sequential,
+    /// Read the footer, then several column chunks in parallel
+    Parquet,
+}
+
+/// Distribution of range lengths, parsed from `N`, `MIN..MAX` or `exp:MEAN`
+#[derive(Clone, Copy, Debug)]
+enum RangeLength {
+    Fixed(u64),
+    Uniform(u64, u64),
+    Exponential(u64),
+}
+
+impl FromStr for RangeLength {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let parse = |v: &str| {
+            v.trim()
+                .parse::<u64>()
+                .ok()
+                .filter(|n| *n > 0)
+                .ok_or_else(|| format!("invalid range length '{}'", v))
+        };
+        if let Some(mean) = s.strip_prefix("exp:") {
+            Ok(RangeLength::Exponential(parse(mean)?))
+        } else if let Some((min, max)) = s.split_once("..") {
+            let (min, max) = (parse(min)?, parse(max)?);
+            if min > max {
+                return Err(format!("range length minimum {} exceeds maximum {}", min, max));
+            }
+            Ok(RangeLength::Uniform(min, max))
+        } else {
+            Ok(RangeLength::Fixed(parse(s)?))
+        }
+    }
+}
+
+impl RangeLength {
+    fn sample(&self, rng: &mut impl Rng) -> u64 {
+        match *self {
+            RangeLength::Fixed(n) => n,
+            RangeLength::Uniform(min, max) => rng.gen_range(min..=max),
+            RangeLength::Exponential(mean) => {
+                let u: f64 = rng.gen_range(f64::EPSILON..1.0);
+                ((-u.ln() * mean as f64) as u64).max(1)
+            }
+        }
+    }
+}
+
+/// Everything needed to turn an object size into a list of byte ranges
+#[derive(Clone, Copy)]
+struct RangeSpec {
+    pattern: RangePattern,
+    length: RangeLength,
+    stride: Option<u64>,
+    ranges_per_op: usize,
+    footer_bytes: u64,
+}
+
+impl RangeSpec {
+    /// Plan the `(offset, length)` ranges for one operation. `visit` counts how
+    /// many times this object has been read before and drives the sequential walk.
+    fn plan(&self, object_size: u64, visit: u64, rng: &mut impl Rng) -> Vec<(u64, u64)> {
+        match self.pattern {
+            RangePattern::Head => {
+                vec![(0, self.length.sample(rng).min(object_size))]
+            }
+            RangePattern::Random => {
+                let len = self.length.sample(rng).min(object_size);
+                vec![(rng.gen_range(0..=object_size - len), len)]
+            }
+            RangePattern::Sequential => {
+                let per_op = self.ranges_per_op.max(1) as u64;
+                let mut ranges = Vec::with_capacity(per_op as usize);
+                for i in 0..per_op {
+                    let len = self.length.sample(rng).min(object_size);
+                    let stride = self.stride.unwrap_or(len).max(1);
+                    let offset = ((visit * per_op + i) * stride) % object_size;
+                    ranges.push((offset, len.min(object_size - offset)));
+                }
+                ranges
+            }
+            RangePattern::Parquet => {
+                let footer = self.footer_bytes.clamp(1, object_size);
+                let body = object_size - footer;
+                let mut columns = Vec::with_capacity(self.ranges_per_op);
+                if body > 0 {
+                    for _ in 0..self.ranges_per_op {
+                        let len = self.length.sample(rng).min(body);
+                        columns.push((rng.gen_range(0..=body - len), len));
+                    }
+                    columns.sort_unstable();
+                }
+                let mut ranges = vec![(body, footer)];
+                ranges.extend(columns);
+                ranges
+            }
+        }
+    }
+}
+
+/// Object discovered by listing, with the size reported by the listing
+struct ObjectInfo {
+    key: String,
+    size: u64,
 }
 
+/// Upper bound of the latency histogram (one hour); slower operations are clamped to it
+const MAX_TRACKED_LATENCY_US: u64 = 3_600_000_000;
+
 struct Stats {
     operations: u64,
     bytes_transferred: u64,
+    objects_listed: u64,
     errors: u64,
     duration: Duration,
     total_latency_ms: f64,
+    /// Latency of successful operations in microseconds
+    latency: Histogram<u64>,
+    window: Option<MeasurementWindow>,
+    excluded: u64,
+    /// Requests, retries and timeouts by S3 operation over the whole run, warmup and cooldown included
+    requests: BTreeMap<String, RequestCounts>,
+    /// Connections opened and reused over the whole run, warmup and cooldown included
+    connections: ConnectionCounts,
+    /// Measured operations by endpoint, in the order the endpoints were given
+    endpoints: Vec<EndpointStats>,
 }
 
 impl Stats {
+    fn new() -> Self {
+        Stats {
+            operations: 0,
+            bytes_transferred: 0,
+            objects_listed: 0,
+            errors: 0,
+            duration: Duration::ZERO,
+            total_latency_ms: 0.0,
+            latency: Histogram::new_with_bounds(1, MAX_TRACKED_LATENCY_US, 3).expect("valid histogram bounds"),
+            window: None,
+            excluded: 0,
+            requests: BTreeMap::new(),
+            connections: ConnectionCounts::default(),
+            endpoints: Vec::new(),
+        }
+    }
+    
+    fn record_latency(&mut self, latency: Duration) {
+        self.total_latency_ms += latency.as_secs_f64() * 1000.0;
+        self.latency.saturating_record(latency.as_micros() as u64);
+    }
+    
+    fn percentile_ms(&self, quantile: f64) -> f64 {
+        self.latency.value_at_quantile(quantile) as f64 / 1000.0
+    }
+    
+    fn ops_per_sec(&self) -> f64 {
+        self.operations as f64 / self.duration.as_secs_f64()
+    }
+    
+    fn mb_per_sec(&self) -> f64 {
+        (self.bytes_transferred as f64 / 1_048_576.0) / self.duration.as_secs_f64()
+    }
+    
+    fn error_rate(&self) -> f64 {
+        if self.operations > 0 {
+            self.errors as f64 / self.operations as f64
+        } else {
+            0.0
+        }
+    }
+    
     fn print(&self, operation: &str) {
-        let ops_per_sec = self.operations as f64 / self.duration.as_secs_f64();
-        let mb_per_sec = (self.bytes_transferred as f64 / 1_048_576.0) / self.duration.as_secs_f64();
+        let ops_per_sec = self.ops_per_sec();
+        let mb_per_sec = self.mb_per_sec();
         let successful = self.operations - self.errors;
         let avg_latency_ms = if successful > 0 {
             self.total_latency_ms / successful as f64
@@ -111,28 +554,92 @@ impl Stats {
         
         println!("\n=== {} Benchmark Results ===", operation);
         println!("Duration: {:.2}s", self.duration.as_secs_f64());
+        if let Some(window) = self.window {
+            println!(
+                "Measured window: {:.2}s - {:.2}s ({} warmup/cooldown operations excluded)",
+                window.start.as_secs_f64(),
+                window.end.as_secs_f64(),
+                self.excluded
+            );
+        }
         println!("Total operations: {}", self.operations);
         println!("Successful: {}", successful);
         println!("Errors: {}", self.errors);
         println!("Operations/sec: {:.2}", ops_per_sec);
         println!("Average latency: {:.2} ms", avg_latency_ms);
+        println!(
+            "Latency p50/p90/p99/max: {:.2} / {:.2} / {:.2} / {:.2} ms",
+            self.percentile_ms(0.5),
+            self.percentile_ms(0.9),
+            self.percentile_ms(0.99),
+            self.latency.max() as f64 / 1000.0
+        );
         println!("Data transferred: {:.2} MB", self.bytes_transferred as f64 / 1_048_576.0);
         println!("Throughput: {:.2} MB/s", mb_per_sec);
+        if !self.requests.is_empty() {
+            println!("Requests by S3 operation:");
+            for (operation, counts) in &self.requests {
+                println!(
+                    "  {}: {} requests, {} retries, {} timeouts, {:.1} µs signing per attempt",
+                    operation,
+                    counts.requests,
+                    counts.retries,
+                    counts.timeouts,
+                    counts.signing_per_attempt().as_secs_f64() * 1e6
+                );
+            }
+        }
+        let connection_uses = self.connections.opened + self.connections.reused;
+        if connection_uses > 0 {
+            println!(
+                "Connections: {} opened, {} requests on reused connections ({:.1}%)",
+                self.connections.opened,
+                self.connections.reused,
+                self.connections.reused as f64 * 100.0 / connection_uses as f64
+            );
+        }
+        if self.endpoints.len() > 1 {
+            println!("Results by endpoint:");
+            for endpoint in &self.endpoints {
+                println!(
+                    "  {}: {} ops ({:.2}/sec), {} errors, {:.2} MB, p50/p99 {:.2} / {:.2} ms",
+                    endpoint.endpoint,
+                    endpoint.operations,
+                    endpoint.operations as f64 / self.duration.as_secs_f64(),
+                    endpoint.errors,
+                    endpoint.bytes_transferred as f64 / 1_048_576.0,
+                    endpoint.percentile_ms(0.5),
+                    endpoint.percentile_ms(0.99)
+                );
+            }
+        }
     }
 }
 
-fn create_s3_client(access_key: String, secret_key: String, region: String, endpoint: String) -> S3Client {
-    let credentials = Credentials::new(access_key, secret_key, None, None, "static");
+/// Build one client per endpoint; they share credentials, the HTTP client and the request and connection counters
+async fn connect(conn: &ConnectionArgs) -> Result<Arc<EndpointPool>> {
+    let endpoints = conn.endpoints()?;
+    let sdk_config = conn.credentials.load(&conn.region).await?;
+    let connections = Arc::new(ConnectionCounters::default());
+    let http_client = transport::http_client(&conn.tls, &conn.http, connections.clone())?;
+    let requests = Arc::new(RequestCounters::default());
     
-    let config = S3ConfigBuilder::new()
-        .behavior_version(BehaviorVersion::latest())
-        .region(Region::new(region))
-        .endpoint_url(endpoint)
-        .credentials_provider(credentials)
-        .force_path_style(true)
-        .build();
+    let clients = endpoints
+        .into_iter()
+        .map(|endpoint| {
+            let config = S3ConfigBuilder::from(&sdk_config)
+                .endpoint_url(endpoint.clone())
+                .force_path_style(!conn.virtual_hosted_style)
+                .http_client(http_client.clone())
+                .retry_config(conn.client.retry_config())
+                .timeout_config(conn.client.timeout_config())
+                .interceptor(CountingInterceptor(requests.clone()))
+                .build();
+            (endpoint, S3Client::from_conf(config))
+        })
+        .collect();
     
-    S3Client::from_conf(config)
+    Ok(Arc::new(EndpointPool::new(clients, conn.endpoint_selection, requests, connections)))
 }
 
 fn generate_random_data(size: usize) -> Vec<u8> {
@@ -145,20 +652,24 @@ async fn put_object_simple(
     client: &S3Client,
     bucket: &str,
     key: &str,
-    data: Vec<u8>,
+    payload: Payload,
+    uploader: &Uploader,
+    attributes: &ObjectAttributes,
 ) -> Result<usize> {
-    let size = data.len();
+    let size = payload.len();
     println!("[PUT] Starting simple upload for key: {} (size: {} bytes)", key, size);
-    let body = ByteStream::from(data);
+    let body = uploader.body(payload);
     
-    client
+    let request = client
         .put_object()
         .bucket(bucket)
         .key(key)
-        .body(body)
-        .send()
-        .await
-        .context("Failed to put object")?;
+        .body(body);
+    let mut request = attributes.apply_put(request).customize();
+    if let Some(config) = uploader.config_override() {
+        request = request.config_override(config);
+    }
+    request.send().await.context("Failed to put object")?;
     
     println!("[PUT] Completed simple upload for key: {}", key);
     Ok(size)
@@ -168,19 +679,23 @@ async fn put_object_multipart(
     client: &S3Client,
     bucket: &str,
     key: &str,
-    data: Vec<u8>,
+    payload: Payload,
     part_size: usize,
+    uploader: &Uploader,
+    attributes: &ObjectAttributes,
 ) -> Result<usize> {
-    let total_size = data.len();
-    let num_parts = (total_size + part_size - 1) / part_size;
+    let total_size = payload.len();
+    let num_parts = total_size.div_ceil(part_size);
     
     println!("[PUT-MP] Starting multipart upload for key: {} (size: {} bytes, {} parts)", key, total_size, num_parts);
     
     // Initiate multipart upload
-    let multipart = client
+    let request = client
         .create_multipart_upload()
         .bucket(bucket)
-        .key(key)
+        .key(key);
+    let multipart = attributes
+        .apply_create(request)
         .send()
         .await
         .context("Failed to create multipart upload")?;
@@ -190,31 +705,33 @@ async fn put_object_multipart(
     
     // Upload parts in parallel
     let mut upload_tasks = Vec::new();
-    let mut part_number = 1;
     
-    for chunk in data.chunks(part_size) {
+    for (part_index, part) in payload.split(part_size).into_iter().enumerate() {
         let client = client.clone();
         let bucket = bucket.to_string();
         let key = key.to_string();
         let upload_id = upload_id.to_string();
-        let chunk_data = Bytes::copy_from_slice(chunk);
-        let current_part = part_number;
+        let current_part = part_index as i32 + 1;
+        let uploader = uploader.clone();
         
         println!("[PUT-MP] Spawning upload task for part {} of {} for key: {}", current_part, num_parts, key);
         
         let task = tokio::spawn(async move {
             println!("[PUT-MP] Uploading part {} for key: {}", current_part, key);
-            let body = ByteStream::from(chunk_data);
+            let body = uploader.body(part);
             
-            let result = client
+            let mut request = client
                 .upload_part()
                 .bucket(bucket)
                 .key(&key)
                 .upload_id(upload_id)
                 .part_number(current_part)
                 .body(body)
-                .send()
-                .await;
+                .customize();
+            if let Some(config) = uploader.config_override() {
+                request = request.config_override(config);
+            }
+            let result = request.send().await;
             
             match &result {
                 Ok(_) => println!("[PUT-MP] Completed part {} for key: {}", current_part, key),
@@ -225,7 +742,6 @@ async fn put_object_multipart(
         });
         
         upload_tasks.push(task);
-        part_number += 1;
     }
     
     println!("[PUT-MP] Waiting for {} parallel part uploads to complete for key: {}", upload_tasks.len(), key);
@@ -269,7 +785,7 @@ async fn put_object_multipart(
     Ok(total_size)
 }
 
-async fn get_object(client: &S3Client, bucket: &str, key: &str) -> Result<usize> {
+async fn get_object(client: &S3Client, bucket: &str, key: &str, throttle: &Throttle) -> Result<usize> {
     println!("[GET] Starting download for key: {}", key);
     let resp = client
         .get_object()
@@ -279,15 +795,21 @@ async fn get_object(client: &S3Client, bucket: &str, key: &str) -> Result<usize>
         .await
         .context("Failed to get object")?;
     
-    let data = resp.body.collect().await.context("Failed to read body")?;
-    let size = data.into_bytes().len();
+    let size = throttle.read_body(resp.body).await?;
     println!("[GET] Completed download for key: {} (size: {} bytes)", key, size);
     Ok(size)
 }
 
-async fn get_object_range(client: &S3Client, bucket: &str, key: &str, range_bytes: usize) -> Result<usize> {
-    println!("[GET-RANGE] Starting range download for key: {} (first {} bytes)", key, range_bytes);
-    let range = format!("bytes=0-{}", range_bytes - 1);
+async fn get_object_range(
+    client: &S3Client,
+    bucket: &str,
+    key: &str,
+    offset: u64,
+    length: u64,
+    throttle: &Throttle,
+) -> Result<usize> {
+    println!("[GET-RANGE] Starting range download for key: {} ({} bytes at offset {})", key, length, offset);
+    let range = format!("bytes={}-{}", offset, offset + length - 1);
     let resp = client
         .get_object()
         .bucket(bucket)
@@ -297,20 +819,45 @@ async fn get_object_range(client: &S3Client, bucket: &str, key: &str, range_byte
         .await
         .context("Failed to get object range")?;
     
-    let data = resp.body.collect().await.context("Failed to read body")?;
-    let size = data.into_bytes().len();
+    let size = throttle.read_body(resp.body).await?;
     println!("[GET-RANGE] Completed range download for key: {} (size: {} bytes)", key, size);
     Ok(size)
 }
 
-async fn list_objects(client: &S3Client, bucket: &str, prefix: &str) -> Result<usize> {
-    println!("[LIST] Starting list operation with prefix: '{}'", prefix);
-    let mut count = 0;
+/// Read a planned list of ranges. The parquet pattern reads the footer first and
+/// then fetches all column chunks concurrently, the others read in order.
+async fn get_object_ranges(
+    client: &S3Client,
+    bucket: &str,
+    key: &str,
+    ranges: &[(u64, u64)],
+    pattern: RangePattern,
+    throttle: &Throttle,
+) -> Result<usize> {
+    let Some((&(offset, length), rest)) = ranges.split_first() else {
+        return Ok(0);
+    };
+    let mut total = get_object_range(client, bucket, key, offset, length, throttle).await?;
+    
+    if pattern == RangePattern::Parquet {
+        let reads = rest
+            .iter()
+            .map(|&(offset, length)| get_object_range(client, bucket, key, offset, length, throttle));
+        total += futures::future::try_join_all(reads).await?.into_iter().sum::<usize>();
+    } else {
+        for &(offset, length) in rest {
+            total += get_object_range(client, bucket, key, offset, length, throttle).await?;
+        }
+    }
+    
+    Ok(total)
+}
+
+async fn discover_objects(client: &S3Client, bucket: &str, prefix: &str) -> Result<Vec<ObjectInfo>> {
+    let mut objects = Vec::new();
     let mut continuation_token: Option<String> = None;
-    let mut page = 1;
     
     loop {
-        println!("[LIST] Fetching page {} for prefix: '{}'", page, prefix);
         let mut request = client.list_objects_v2().bucket(bucket).max_keys(1000);
         
         if !prefix.is_empty() {
@@ -323,461 +870,1404 @@ async fn list_objects(client: &S3Client, bucket: &str, prefix: &str) -> Result<u
         
         let resp = request.send().await.context("Failed to list objects")?;
         
-        let page_count = resp.contents().len();
-        count += page_count;
-        println!("[LIST] Page {} returned {} objects (total so far: {})", page, page_count, count);
+        for obj in resp.contents() {
+            if let Some(key) = obj.key() {
+                objects.push(ObjectInfo {
+                    key: key.to_string(),
+                    size: obj.size().unwrap_or(0).max(0) as u64,
+                });
+            }
+        }
         
         if resp.is_truncated() == Some(true) {
             continuation_token = resp.next_continuation_token().map(String::from);
-            page += 1;
         } else {
             break;
         }
     }
     
-    println!("[LIST] Completed list operation with prefix: '{}' (total: {} objects)", prefix, count);
-    Ok(count)
+    Ok(objects)
 }
 
-async fn run_put_benchmark(
-    access_key: String,
-    secret_key: String,
-    region: String,
-    endpoint: String,
-    bucket: String,
-    duration_secs: u64,
+/// What the value returned by each operation counts
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum OpUnit {
+    Bytes,
+    Objects,
+}
+
+/// Issue operations produced by `next_op` with at most `concurrent` in flight until
+/// the run's stop condition is reached, then wait for the stragglers and aggregate.
+/// `next_op` gets the operation index and returns the planned payload size together
+/// with the operation itself.
+async fn drive<F, Fut>(
+    label: &str,
     concurrent: usize,
-    object_size: usize,
-    part_size: usize,
-    disable_multipart: bool,
-    prefix: String,
-) -> Result<()> {
-    let client = Arc::new(create_s3_client(access_key, secret_key, region, endpoint.clone()));
+    run: RunArgs,
+    issue_delay: Duration,
+    unit: OpUnit,
+    endpoints: &Arc<EndpointPool>,
+    mut next_op: F,
+) -> Result<Stats>
+where
+    F: FnMut(u64, S3Client) -> (u64, Fut),
+    Fut: Future<Output = Result<usize>> + Send + 'static,
+{
+    run.validate()?;
     let semaphore = Arc::new(Semaphore::new(concurrent));
-    let duration = Duration::from_secs(duration_secs);
-    
-    println!("Starting PUT benchmark...");
-    println!("Endpoint: {}", endpoint);
-    println!("Bucket: {}", bucket);
-    println!("Duration: {}s", duration_secs);
-    println!("Concurrent operations: {}", concurrent);
-    println!("Object size: {} bytes ({:.2} MB)", object_size, object_size as f64 / 1_048_576.0);
-    println!("Part size: {} bytes ({:.2} MB)", part_size, part_size as f64 / 1_048_576.0);
-    println!("Multipart: {}", !disable_multipart);
+    let stop = run.resolve();
+    let requests_before = endpoints.requests.snapshot();
+    let connections_before = endpoints.connections.snapshot();
     
     let start = Instant::now();
     let mut tasks = Vec::new();
     let mut operation_count = 0u64;
-    let mut bytes_transferred = 0u64;
-    let mut errors = 0u64;
-    let mut total_latency_ms = 0.0;
+    let mut bytes_issued = 0u64;
+    let mut stats = Stats::new();
+    stats.endpoints = endpoints.urls().map(EndpointStats::new).collect();
     
-    let pb = ProgressBar::new(duration_secs);
-    pb.set_style(ProgressStyle::default_bar()
-        .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len}s ({msg})")
-        .unwrap()
-        .progress_chars("#>-"));
+    let pb = stop.progress_bar();
     
-    while start.elapsed() < duration {
+    while !stop.reached(start.elapsed(), operation_count, bytes_issued) {
         let permit = semaphore.clone().acquire_owned().await?;
-        let client = client.clone();
-        let bucket = bucket.clone();
-        let key = format!("{}{}-{}", prefix, operation_count, chrono::Utc::now().timestamp_nanos_opt().unwrap_or(0));
-        
-        println!("[BENCH] Generating random data for operation {} (size: {} bytes)", operation_count, object_size);
-        let data = generate_random_data(object_size);
+        let lease = endpoints.pick();
+        let endpoint = lease.index();
+        let (expected_bytes, op) = next_op(operation_count, lease.client().clone());
+        // An operation planning no bytes brings --total-bytes no closer, so the run could never stop
+        if stop.bytes.is_some() && expected_bytes == 0 {
+            anyhow::bail!(
+                "{} operation {} transfers no bytes, so --total-bytes cannot stop the run; use --ops or --duration-secs",
+                label,
+                operation_count
+            );
+        }
         
-        println!("[BENCH] Spawning PUT task {} for key: {}", operation_count, key);
         let task = tokio::spawn(async move {
             let op_start = Instant::now();
-            let result = if disable_multipart || object_size < part_size {
-                put_object_simple(&client, &bucket, &key, data).await
-            } else {
-                put_object_multipart(&client, &bucket, &key, data, part_size).await
-            };
+            let result = op.await;
             let latency = op_start.elapsed();
+            drop(lease);
             drop(permit);
-            (result, latency)
+            (result, latency, Instant::now())
         });
         
-        tasks.push(task);
+        tasks.push((endpoint, task));
         operation_count += 1;
+        bytes_issued += expected_bytes;
         
-        pb.set_message(format!("ops: {}, errors: {}", operation_count, errors));
-        pb.set_position(start.elapsed().as_secs().min(duration_secs));
+        pb.set_message(format!("ops: {}, errors: {}", operation_count, stats.errors));
+        stop.update_progress(&pb, start.elapsed(), operation_count, bytes_issued);
         
         // Small delay to prevent overwhelming the system
-        tokio::time::sleep(Duration::from_millis(10)).await;
+        tokio::time::sleep(issue_delay).await;
     }
     
-    println!("[BENCH] Duration reached, waiting for {} in-flight operations to complete...", tasks.len());
+    println!("[BENCH] Stop condition reached, waiting for {} in-flight {} operations to complete...", tasks.len(), label);
     pb.finish_with_message("Waiting for all operations to complete...");
+    let window = run.window(start.elapsed())?;
     
     // Wait for all tasks to complete
-    println!("[BENCH] Collecting results from {} tasks...", tasks.len());
-    for (idx, task) in tasks.into_iter().enumerate() {
-        println!("[BENCH] Waiting for task {} of {} to complete...", idx + 1, operation_count);
-        match task.await {
-            Ok((Ok(size), latency)) => {
-                println!("[BENCH] Task {} succeeded: {} bytes in {:.2}ms", idx + 1, size, latency.as_secs_f64() * 1000.0);
-                bytes_transferred += size as u64;
-                total_latency_ms += latency.as_secs_f64() * 1000.0;
+    println!("[BENCH] Collecting results from {} {} tasks...", tasks.len(), label);
+    for (idx, (endpoint, task)) in tasks.into_iter().enumerate() {
+        println!("[BENCH] Waiting for {} task {} of {} to complete...", label, idx + 1, operation_count);
+        let result = task.await;
+        match result {
+            Ok((_, _, completed_at)) if !is_measured(window, start, completed_at) => {
+                println!("[BENCH] {} task {} completed outside the measured window", label, idx + 1);
+                stats.excluded += 1;
+                continue;
+            }
+            Ok((Ok(value), latency, _)) => {
+                match unit {
+                    OpUnit::Bytes => {
+                        println!("[BENCH] {} task {} succeeded: {} bytes in {:.2}ms", label, idx + 1, value, latency.as_secs_f64() * 1000.0);
+                        stats.bytes_transferred += value as u64;
+                        stats.endpoints[endpoint].bytes_transferred += value as u64;
+                    }
+                    OpUnit::Objects => {
+                        println!("[BENCH] {} task {} succeeded: {} objects in {:.2}ms", label, idx + 1, value, latency.as_secs_f64() * 1000.0);
+                        stats.objects_listed += value as u64;
+                    }
+                }
+                stats.record_latency(latency);
+                stats.endpoints[endpoint].latency.saturating_record(latency.as_micros() as u64);
             }
-            Ok((Err(e), _)) => {
-                println!("[BENCH] Task {} failed with error: {:?}", idx + 1, e);
-                errors += 1;
+            Ok((Err(e), _, _)) => {
+                println!("[BENCH] {} task {} failed with error: {:?}", label, idx + 1, e);
+                stats.errors += 1;
+                stats.endpoints[endpoint].errors += 1;
             }
             Err(e) => {
-                println!("[BENCH] Task {} panicked: {:?}", idx + 1, e);
-                errors += 1;
+                println!("[BENCH] {} task {} panicked: {:?}", label, idx + 1, e);
+                stats.errors += 1;
+                stats.endpoints[endpoint].errors += 1;
             }
         }
+        stats.endpoints[endpoint].operations += 1;
     }
     
-    println!("[BENCH] All PUT tasks completed!");
+    println!("[BENCH] All {} tasks completed!", label);
     
     let total_duration = start.elapsed();
+    stats.operations = operation_count - stats.excluded;
+    stats.duration = window.map_or(total_duration, |w| w.length());
+    stats.window = window;
+    stats.requests = endpoints.requests.since(&requests_before);
+    stats.connections = endpoints.connections.since(connections_before);
     
-    let stats = Stats {
-        operations: operation_count,
-        bytes_transferred,
-        errors,
-        duration: total_duration,
-        total_latency_ms,
-    };
-    
-    stats.print("PUT");
-    
-    Ok(())
+    Ok(stats)
 }
 
-async fn run_get_benchmark(
-    access_key: String,
-    secret_key: String,
-    region: String,
-    endpoint: String,
+/// PUT benchmark configuration, ready to be executed at any concurrency
+struct PutWorkload {
+    endpoints: Arc<EndpointPool>,
     bucket: String,
-    duration_secs: u64,
-    concurrent: usize,
+    run: RunArgs,
+    object_size: usize,
+    part_size: usize,
+    disable_multipart: bool,
     prefix: String,
-    range_bytes: Option<usize>,
-) -> Result<()> {
-    let client = Arc::new(create_s3_client(access_key, secret_key, region, endpoint.clone()));
-    let semaphore = Arc::new(Semaphore::new(concurrent));
-    let duration = Duration::from_secs(duration_secs);
-    
-    println!("Starting GET benchmark...");
-    println!("Endpoint: {}", endpoint);
-    println!("Bucket: {}", bucket);
-    println!("Duration: {}s", duration_secs);
-    println!("Concurrent operations: {}", concurrent);
-    if let Some(bytes) = range_bytes {
-        println!("Range query: reading first {} bytes", bytes);
+    upload: UploadArgs,
+    uploader: Uploader,
+    namespace: Namespace,
+    attributes: ObjectAttributes,
+    metadata: MetadataArgs,
+}
+
+impl PutWorkload {
+    async fn new(args: PutArgs) -> Result<Self> {
+        args.upload.validate()?;
+        args.namespace.validate()?;
+        args.metadata.validate()?;
+        let endpoints = connect(&args.conn).await?;
+        Ok(PutWorkload {
+            endpoints,
+            bucket: args.conn.bucket,
+            run: args.run,
+            object_size: args.object_size,
+            part_size: args.part_size,
+            disable_multipart: args.disable_multipart,
+            prefix: args.prefix,
+            uploader: Uploader::new(&args.upload, Throttle::new(&args.bandwidth)),
+            upload: args.upload,
+            namespace: Namespace::new(&args.namespace),
+            attributes: ObjectAttributes::new(&args.metadata),
+            metadata: args.metadata,
+        })
     }
     
-    // First, list objects to know what to get
-    println!("Listing objects with prefix '{}'...", prefix);
-    let mut objects = Vec::new();
-    let mut continuation_token: Option<String> = None;
+    fn print_config(&self) {
+        println!("Object size: {} bytes ({:.2} MB)", self.object_size, self.object_size as f64 / 1_048_576.0);
+        println!("Part size: {} bytes ({:.2} MB)", self.part_size, self.part_size as f64 / 1_048_576.0);
+        println!("Multipart: {}", !self.disable_multipart);
+        println!("Upload: {}", self.upload);
+        println!("Key layout: {}", self.namespace);
+        println!("Metadata: {}", self.metadata);
+    }
     
-    loop {
-        let mut request = client.list_objects_v2().bucket(&bucket).max_keys(1000);
+    async fn execute(&self, concurrent: usize) -> Result<Stats> {
+        let PutWorkload { object_size, part_size, disable_multipart, .. } = *self;
         
-        if !prefix.is_empty() {
-            request = request.prefix(&prefix);
+        drive("PUT", concurrent, self.run, Duration::from_millis(10), OpUnit::Bytes, &self.endpoints, |operation_count, client| {
+            let bucket = self.bucket.clone();
+            let uploader = self.uploader.clone();
+            let attributes = self.attributes.clone();
+            let key = self.namespace.key(&self.prefix, operation_count);
+            
+            println!("[BENCH] Generating random data for operation {} (size: {} bytes)", operation_count, object_size);
+            let payload = uploader.payload(object_size);
+            
+            println!("[BENCH] Spawning PUT task {} for key: {}", operation_count, key);
+            let op = async move {
+                if disable_multipart || object_size < part_size {
+                    put_object_simple(&client, &bucket, &key, payload, &uploader, &attributes).await
+                } else {
+                    put_object_multipart(&client, &bucket, &key, payload, part_size, &uploader, &attributes).await
+                }
+            };
+            (object_size as u64, op)
+        })
+        .await
+    }
+}
+
+/// GET benchmark configuration with the object list discovered up front
+struct GetWorkload {
+    endpoints: Arc<EndpointPool>,
+    bucket: String,
+    run: RunArgs,
+    range_spec: Option<RangeSpec>,
+    objects: Vec<ObjectInfo>,
+    throttle: Throttle,
+}
+
+impl GetWorkload {
+    async fn prepare(args: GetArgs) -> Result<Self> {
+        let range_spec = match args.range_bytes {
+            Some(length) => Some(RangeSpec {
+                pattern: args.range_pattern,
+                length,
+                stride: args.range_stride,
+                ranges_per_op: args.ranges_per_op,
+                footer_bytes: args.footer_bytes,
+            }),
+            None if args.range_pattern != RangePattern::Head => {
+                anyhow::bail!("--range-pattern {:?} requires --range-bytes", args.range_pattern);
+            }
+            None => None,
+        };
+        let endpoints = connect(&args.conn).await?;
+        let bucket = args.conn.bucket;
+        let prefix = args.prefix;
+        
+        // First, list objects to know what to get
+        println!("Listing objects with prefix '{}'...", prefix);
+        let mut objects = discover_objects(endpoints.primary(), &bucket, &prefix).await?;
+        
+        // Ranged reads need real sizes to place offsets; empty objects have no valid range.
+        // Reading an empty object brings --total-bytes no closer either.
+        if range_spec.is_some() || args.run.total_bytes.is_some() {
+            let found = objects.len();
+            objects.retain(|obj| obj.size > 0);
+            if found > 0 && objects.is_empty() {
+                anyhow::bail!("All {} objects with prefix '{}' are empty; ranged reads and --total-bytes need data", found, prefix);
+            }
         }
         
-        if let Some(token) = continuation_token {
-            request = request.continuation_token(token);
+        if objects.is_empty() {
+            anyhow::bail!("No objects found with prefix '{}'. Please run PUT benchmark first.", prefix);
         }
         
-        let resp = request.send().await.context("Failed to list objects")?;
+        println!("Found {} objects to download", objects.len());
         
-        for obj in resp.contents() {
-            if let Some(key) = obj.key() {
-                objects.push(key.to_string());
+        Ok(GetWorkload {
+            endpoints,
+            bucket,
+            run: args.run,
+            range_spec,
+            objects,
+            throttle: Throttle::new(&args.bandwidth),
+        })
+    }
+    
+    fn print_config(&self) {
+        if let Some(spec) = &self.range_spec {
+            println!("Range query: {:?} pattern, length {:?}", spec.pattern, spec.length);
+            match spec.pattern {
+                RangePattern::Sequential => println!(
+                    "Chunks per op: {}, stride: {}",
+                    spec.ranges_per_op,
+                    spec.stride.map_or("range length".to_string(), |s| s.to_string())
+                ),
+                RangePattern::Parquet => println!(
+                    "Footer: {} bytes, column chunks per op: {}",
+                    spec.footer_bytes, spec.ranges_per_op
+                ),
+                RangePattern::Head | RangePattern::Random => {}
             }
         }
+    }
+    
+    async fn execute(&self, concurrent: usize) -> Result<Stats> {
+        let objects = &self.objects;
         
-        if resp.is_truncated() == Some(true) {
-            continuation_token = resp.next_continuation_token().map(String::from);
-        } else {
-            break;
+        drive("GET", concurrent, self.run, Duration::from_millis(10), OpUnit::Bytes, &self.endpoints, |operation_count, client| {
+            let bucket = self.bucket.clone();
+            let throttle = self.throttle.clone();
+            let object_index = operation_count as usize;
+            let object = &objects[object_index % objects.len()];
+            let visit = (object_index / objects.len()) as u64;
+            let key = object.key.clone();
+            let ranges = self
+                .range_spec
+                .map(|spec| (spec.plan(object.size, visit, &mut rand::thread_rng()), spec.pattern));
+            let expected_bytes = match &ranges {
+                Some((ranges, _)) => ranges.iter().map(|(_, length)| length).sum(),
+                None => object.size,
+            };
+            
+            println!("[BENCH] Spawning GET task {} for key: {}", operation_count, key);
+            let op = async move {
+                let received = if let Some((ranges, pattern)) = ranges {
+                    get_object_ranges(&client, &bucket, &key, &ranges, pattern, &throttle).await?
+                } else {
+                    get_object(&client, &bucket, &key, &throttle).await?
+                };
+                // A body cut short under a matching Content-Length otherwise passes as a fast read
+                if received as u64 != expected_bytes {
+                    anyhow::bail!("Received {} of {} expected bytes for {}", received, expected_bytes, key);
+                }
+                Ok(received)
+            };
+            (expected_bytes, op)
+        })
+        .await
+    }
+}
+
+/// LIST benchmark configuration
+struct ListWorkload {
+    endpoints: Arc<EndpointPool>,
+    bucket: String,
+    run: RunArgs,
+    prefix: String,
+    lister: Lister,
+    /// Existing keys under the prefix that operations start after, with --random-start-after
+    start_keys: Arc<Vec<String>>,
+}
+
+impl ListWorkload {
+    async fn new(args: ListArgs) -> Result<Self> {
+        if args.run.total_bytes.is_some() {
+            anyhow::bail!("--total-bytes is not supported for LIST; use --ops or --duration-secs");
+        }
+        args.list.validate()?;
+        let endpoints = connect(&args.conn).await?;
+        let mut start_keys = Vec::new();
+        if args.list.random_start_after {
+            println!("Discovering objects under prefix '{}' for random start-after keys...", args.prefix);
+            start_keys = discover_objects(endpoints.primary(), &args.conn.bucket, &args.prefix)
+                .await?
+                .into_iter()
+                .map(|object| object.key)
+                .collect();
+            if start_keys.is_empty() {
+                anyhow::bail!("No objects found under prefix '{}' to start listings after", args.prefix);
+            }
         }
+        Ok(ListWorkload {
+            endpoints,
+            bucket: args.conn.bucket,
+            run: args.run,
+            prefix: args.prefix,
+            lister: Lister::new(args.list),
+            start_keys: Arc::new(start_keys),
+        })
     }
     
-    if objects.is_empty() {
-        anyhow::bail!("No objects found with prefix '{}'. Please run PUT benchmark first.", prefix);
+    fn print_config(&self) {
+        println!("Prefix: '{}'", self.prefix);
+        println!("Listing: {}", self.lister.options());
     }
     
-    println!("Found {} objects to download", objects.len());
-    
-    let start = Instant::now();
-    let mut tasks = Vec::new();
-    let mut operation_count = 0u64;
-    let mut bytes_transferred = 0u64;
-    let mut errors = 0u64;
-    let mut total_latency_ms = 0.0;
-    let mut object_index = 0;
-    
-    let pb = ProgressBar::new(duration_secs);
-    pb.set_style(ProgressStyle::default_bar()
-        .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len}s ({msg})")
-        .unwrap()
-        .progress_chars("#>-"));
-    
-    while start.elapsed() < duration {
-        let permit = semaphore.clone().acquire_owned().await?;
-        let client = client.clone();
-        let bucket = bucket.clone();
-        let key = objects[object_index % objects.len()].clone();
-        object_index += 1;
-        
-        println!("[BENCH] Spawning GET task {} for key: {}", operation_count, key);
-        let task = tokio::spawn(async move {
-            let op_start = Instant::now();
-            let result = if let Some(bytes) = range_bytes {
-                get_object_range(&client, &bucket, &key, bytes).await
+    async fn execute(&self, concurrent: usize) -> Result<Stats> {
+        self.lister.begin_run();
+        drive("LIST", concurrent, self.run, Duration::from_millis(100), OpUnit::Objects, &self.endpoints, |operation_count, client| {
+            let bucket = self.bucket.clone();
+            let prefix = self.prefix.clone();
+            let lister = self.lister.clone();
+            let start_after = if self.start_keys.is_empty() {
+                None
             } else {
-                get_object(&client, &bucket, &key).await
+                Some(self.start_keys[rand::thread_rng().gen_range(0..self.start_keys.len())].clone())
             };
-            let latency = op_start.elapsed();
-            drop(permit);
-            (result, latency)
-        });
-        
-        tasks.push(task);
-        operation_count += 1;
-        
-        pb.set_message(format!("ops: {}, errors: {}", operation_count, errors));
-        pb.set_position(start.elapsed().as_secs().min(duration_secs));
-        
-        tokio::time::sleep(Duration::from_millis(10)).await;
+            
+            println!("[BENCH] Spawning LIST task {} with prefix: '{}'", operation_count, prefix);
+            let op = async move { lister.list(&client, &bucket, &prefix, start_after).await };
+            (0, op)
+        })
+        .await
     }
     
-    println!("[BENCH] Duration reached, waiting for {} in-flight GET operations to complete...", tasks.len());
-    pb.finish_with_message("Waiting for all operations to complete...");
+    fn print_summary(stats: &Stats) {
+        println!("Total objects listed: {}", stats.objects_listed);
+        println!("Avg objects per list: {:.2}", stats.objects_listed as f64 / stats.operations as f64);
+    }
+}
+
+/// Benchmark chosen on the command line by modes that drive a workload repeatedly
+#[derive(Subcommand)]
+enum Workload {
+    /// Drive the PUT benchmark
+    Put(PutArgs),
+    /// Drive the GET benchmark
+    Get(GetArgs),
+    /// Drive the LIST benchmark
+    List(ListArgs),
+    /// Drive the COPY benchmark
+    Copy(copy::CopyArgs),
+}
+
+impl Workload {
+    fn endpoint(&self) -> String {
+        match self {
+            Workload::Put(args) => args.conn.describe_endpoints(),
+            Workload::Get(args) => args.conn.describe_endpoints(),
+            Workload::List(args) => args.conn.describe_endpoints(),
+            Workload::Copy(args) => args.conn.describe_endpoints(),
+        }
+    }
     
-    // Wait for all tasks to complete
-    println!("[BENCH] Collecting results from {} GET tasks...", tasks.len());
-    for (idx, task) in tasks.into_iter().enumerate() {
-        println!("[BENCH] Waiting for GET task {} of {} to complete...", idx + 1, operation_count);
-        match task.await {
-            Ok((Ok(size), latency)) => {
-                println!("[BENCH] GET task {} succeeded: {} bytes in {:.2}ms", idx + 1, size, latency.as_secs_f64() * 1000.0);
-                bytes_transferred += size as u64;
-                total_latency_ms += latency.as_secs_f64() * 1000.0;
-            }
-            Ok((Err(e), _)) => {
-                println!("[BENCH] GET task {} failed with error: {:?}", idx + 1, e);
-                errors += 1;
-            }
-            Err(e) => {
-                println!("[BENCH] GET task {} panicked: {:?}", idx + 1, e);
-                errors += 1;
-            }
+    fn concurrent(&self) -> usize {
+        match self {
+            Workload::Put(args) => args.concurrent,
+            Workload::Get(args) => args.concurrent,
+            Workload::List(args) => args.concurrent,
+            Workload::Copy(args) => args.concurrent,
+        }
+    }
+    
+    async fn prepare(self) -> Result<PreparedWorkload> {
+        Ok(match self {
+            Workload::Put(args) => PreparedWorkload::Put(Box::new(PutWorkload::new(args).await?)),
+            Workload::Get(args) => PreparedWorkload::Get(GetWorkload::prepare(args).await?),
+            Workload::List(args) => PreparedWorkload::List(ListWorkload::new(args).await?),
+            Workload::Copy(args) => PreparedWorkload::Copy(copy::CopyWorkload::prepare(args).await?),
+        })
+    }
+}
+
+enum PreparedWorkload {
+    Put(Box<PutWorkload>),
+    Get(GetWorkload),
+    List(ListWorkload),
+    Copy(copy::CopyWorkload),
+}
+
+impl PreparedWorkload {
+    fn label(&self) -> &'static str {
+        match self {
+            PreparedWorkload::Put(_) => "PUT",
+            PreparedWorkload::Get(_) => "GET",
+            PreparedWorkload::List(_) => "LIST",
+            PreparedWorkload::Copy(_) => "COPY",
         }
     }
     
-    println!("[BENCH] All GET tasks completed!");
+    /// Stop condition and measurement window used by each `execute`
+    fn run_mut(&mut self) -> &mut RunArgs {
+        match self {
+            PreparedWorkload::Put(w) => &mut w.run,
+            PreparedWorkload::Get(w) => &mut w.run,
+            PreparedWorkload::List(w) => &mut w.run,
+            PreparedWorkload::Copy(w) => &mut w.run,
+        }
+    }
     
-    let total_duration = start.elapsed();
+    fn print_config(&self) {
+        match self {
+            PreparedWorkload::Put(w) => w.print_config(),
+            PreparedWorkload::Get(w) => w.print_config(),
+            PreparedWorkload::List(w) => w.print_config(),
+            PreparedWorkload::Copy(w) => w.print_config(),
+        }
+    }
     
-    let stats = Stats {
-        operations: operation_count,
-        bytes_transferred,
-        errors,
-        duration: total_duration,
-        total_latency_ms,
-    };
+    async fn execute(&self, concurrent: usize) -> Result<Stats> {
+        match self {
+            PreparedWorkload::Put(w) => w.execute(concurrent).await,
+            PreparedWorkload::Get(w) => w.execute(concurrent).await,
+            PreparedWorkload::List(w) => w.execute(concurrent).await,
+            PreparedWorkload::Copy(w) => w.execute(concurrent).await,
+        }
+    }
+}
+
+async fn run_put_benchmark(args: PutArgs) -> Result<()> {
+    let endpoint = args.conn.describe_endpoints();
+    let client_config = args.conn.client.to_string();
+    let http_config = args.conn.http.to_string();
+    let bandwidth = args.bandwidth.to_string();
+    let credentials = args.conn.credentials.to_string();
+    let concurrent = args.concurrent;
+    let workload = PutWorkload::new(args).await?;
+    
+    println!("Starting PUT benchmark...");
+    println!("Endpoint: {}", endpoint);
+    println!("Client: {}", client_config);
+    println!("HTTP: {}", http_config);
+    println!("Bandwidth: {}", bandwidth);
+    println!("Credentials: {}", credentials);
+    println!("Bucket: {}", workload.bucket);
+    println!("Stop after: {}", workload.run.resolve());
+    println!("Concurrent operations: {}", concurrent);
+    workload.print_config();
+    
+    let stats = workload.execute(concurrent).await?;
+    stats.print("PUT");
+    
+    Ok(())
+}
+
+async fn run_get_benchmark(args: GetArgs) -> Result<()> {
+    let endpoint = args.conn.describe_endpoints();
+    let concurrent = args.concurrent;
     
+    println!("Starting GET benchmark...");
+    println!("Endpoint: {}", endpoint);
+    println!("Client: {}", args.conn.client);
+    println!("HTTP: {}", args.conn.http);
+    println!("Bandwidth: {}", args.bandwidth);
+    println!("Credentials: {}", args.conn.credentials);
+    println!("Bucket: {}", args.conn.bucket);
+    println!("Stop after: {}", args.run.resolve());
+    println!("Concurrent operations: {}", concurrent);
+    
+    let workload = GetWorkload::prepare(args).await?;
+    workload.print_config();
+    
+    let stats = workload.execute(concurrent).await?;
     stats.print("GET");
     
     Ok(())
 }
 
-async fn run_list_benchmark(
-    access_key: String,
-    secret_key: String,
-    region: String,
-    endpoint: String,
-    bucket: String,
-    duration_secs: u64,
-    concurrent: usize,
-    prefix: String,
-) -> Result<()> {
-    let client = Arc::new(create_s3_client(access_key, secret_key, region, endpoint.clone()));
-    let semaphore = Arc::new(Semaphore::new(concurrent));
-    let duration = Duration::from_secs(duration_secs);
+async fn run_list_benchmark(args: ListArgs) -> Result<()> {
+    let endpoint = args.conn.describe_endpoints();
+    let client_config = args.conn.client.to_string();
+    let http_config = args.conn.http.to_string();
+    let credentials = args.conn.credentials.to_string();
+    let concurrent = args.concurrent;
+    let workload = ListWorkload::new(args).await?;
     
     println!("Starting LIST benchmark...");
     println!("Endpoint: {}", endpoint);
-    println!("Bucket: {}", bucket);
-    println!("Duration: {}s", duration_secs);
+    println!("Client: {}", client_config);
+    println!("HTTP: {}", http_config);
+    println!("Credentials: {}", credentials);
+    println!("Bucket: {}", workload.bucket);
+    println!("Stop after: {}", workload.run.resolve());
     println!("Concurrent operations: {}", concurrent);
-    println!("Prefix: '{}'", prefix);
+    workload.print_config();
     
-    let start = Instant::now();
-    let mut tasks = Vec::new();
-    let mut operation_count = 0u64;
-    let mut errors = 0u64;
-    let mut total_objects_listed = 0u64;
-    let mut total_latency_ms = 0.0;
+    let stats = workload.execute(concurrent).await?;
+    stats.print("LIST");
+    ListWorkload::print_summary(&stats);
+    workload.lister.print_pages(stats.window);
     
-    let pb = ProgressBar::new(duration_secs);
-    pb.set_style(ProgressStyle::default_bar()
-        .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len}s ({msg})")
-        .unwrap()
-        .progress_chars("#>-"));
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let cli = Cli::parse();
     
-    while start.elapsed() < duration {
-        let permit = semaphore.clone().acquire_owned().await?;
-        let client = client.clone();
-        let bucket = bucket.clone();
-        let prefix = prefix.clone();
+    match cli.command {
+        Commands::Put(args) => run_put_benchmark(args).await?,
+        Commands::Get(args) => run_get_benchmark(args).await?,
+        Commands::List(args) => run_list_benchmark(args).await?,
+        Commands::Copy(args) => copy::run_copy_benchmark(args).await?,
+        Commands::MultipartStress(args) => multipart::run_multipart_stress(args).await?,
+        Commands::Versions(args) => versions::run_versions_benchmark(args).await?,
+        Commands::Tagging(args) => metadata::run_tagging_benchmark(args).await?,
+        Commands::Conditional(args) => conditional::run_conditional_benchmark(args).await?,
+        Commands::Presigned(args) => presigned::run_presigned_benchmark(args).await?,
+        Commands::Consistency(args) => consistency::run_consistency_check(args).await?,
+        Commands::Sweep(args) => sweep::run_sweep(args).await?,
+        Commands::Autotune(args) => autotune::run_autotune(args).await?,
+        Commands::Agent(args) => distributed::run_agent(args).await?,
+        Commands::Coordinate(args) => distributed::run_coordinator(args).await?,
+        Commands::MockServer(args) => mock_server::run_mock_server(args).await?,
+    }
+    
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    use mock_server::MockServer;
+    
+    const BUCKET: &str = "bench";
+    
+    /// Signing time varies from run to run; compare the other counts
+    fn without_signing(counts: RequestCounts) -> RequestCounts {
+        RequestCounts { signing: Duration::ZERO, ..counts }
+    }
+    
+    fn parse(args: &[&str]) -> Commands {
+        Cli::try_parse_from(std::iter::once("s3-load-gen").chain(args.iter().copied()))
+            .unwrap()
+            .command
+    }
+    
+    /// `command` against the mock server with test credentials, followed by `extra`
+    fn command_args(command: &str, server: &MockServer, extra: &[&str]) -> Commands {
+        let mut args = vec![command, "--endpoint", &server.endpoint, "--bucket", BUCKET, "--access-key", "test", "--secret-key", "test"];
+        args.extend_from_slice(extra);
+        parse(&args)
+    }
+    
+    fn put_args(server: &MockServer, extra: &[&str]) -> PutArgs {
+        match command_args("put", server, extra) {
+            Commands::Put(args) => args,
+            _ => unreachable!(),
+        }
+    }
+    
+    fn get_args(server: &MockServer, extra: &[&str]) -> GetArgs {
+        match command_args("get", server, extra) {
+            Commands::Get(args) => args,
+            _ => unreachable!(),
+        }
+    }
+    
+    fn list_args(server: &MockServer, extra: &[&str]) -> ListArgs {
+        match command_args("list", server, extra) {
+            Commands::List(args) => args,
+            _ => unreachable!(),
+        }
+    }
+    
+    #[tokio::test]
+    async fn put_get_list_against_mock_server() {
+        let server = MockServer::start("127.0.0.1:0").await.unwrap();
         
-        println!("[BENCH] Spawning LIST task {} with prefix: '{}'", operation_count, prefix);
-        let task = tokio::spawn(async move {
-            let op_start = Instant::now();
-            let result = list_objects(&client, &bucket, &prefix).await;
-            let latency = op_start.elapsed();
-            drop(permit);
-            (result, latency)
-        });
+        // 3 KiB objects with 1 KiB parts go through the multipart path
+        let put = PutWorkload::new(put_args(&server, &["--ops", "6", "--object-size", "3072", "--part-size", "1024", "--prefix", "mp/"])).await.unwrap();
+        let stats = put.execute(3).await.unwrap();
+        assert_eq!((stats.operations, stats.errors), (6, 0));
+        assert_eq!(stats.bytes_transferred, 6 * 3072);
+        assert_eq!(server.store.object_count(BUCKET), 6);
+        assert_eq!(server.store.pending_uploads(), 0);
         
-        tasks.push(task);
-        operation_count += 1;
+        let get = GetWorkload::prepare(get_args(&server, &["--ops", "12", "--prefix", "mp/"])).await.unwrap();
+        let stats = get.execute(4).await.unwrap();
+        assert_eq!((stats.operations, stats.errors), (12, 0));
+        assert_eq!(stats.bytes_transferred, 12 * 3072);
+        
+        let list = ListWorkload::new(list_args(&server, &["--ops", "2", "--prefix", "mp/"])).await.unwrap();
+        let stats = list.execute(2).await.unwrap();
+        assert_eq!((stats.operations, stats.errors, stats.objects_listed), (2, 0, 12));
+    }
+    
+    #[tokio::test]
+    async fn ranged_gets_read_planned_bytes() {
+        let server = MockServer::start("127.0.0.1:0").await.unwrap();
+        PutWorkload::new(put_args(&server, &["--ops", "4", "--object-size", "100000", "--disable-multipart"])).await.unwrap()
+            .execute(4)
+            .await
+            .unwrap();
+        
+        for pattern in ["head", "random", "sequential", "parquet"] {
+            let args = get_args(&server, &[
+                "--ops", "8",
+                "--range-pattern", pattern,
+                "--range-bytes", "1000",
+                "--ranges-per-op", "3",
+                "--footer-bytes", "500",
+            ]);
+            let stats = GetWorkload::prepare(args).await.unwrap().execute(4).await.unwrap();
+            let per_op = match pattern {
+                "head" | "random" => 1000,
+                "sequential" => 3 * 1000,
+                _ => 500 + 3 * 1000,
+            };
+            assert_eq!(stats.errors, 0, "{} pattern", pattern);
+            assert_eq!(stats.bytes_transferred, 8 * per_op, "{} pattern", pattern);
+        }
+    }
+    
+    #[tokio::test]
+    async fn discovery_follows_list_pagination() {
+        let server = MockServer::start("127.0.0.1:0").await.unwrap();
+        let endpoints = connect(&put_args(&server, &[]).conn).await.unwrap();
+        let client = endpoints.primary();
+        let keys: Vec<String> = (0..1203).map(|i| format!("page/{:05}", i)).collect();
+        let (uploader, attributes) = (Uploader::default(), ObjectAttributes::default());
+        let puts = keys.iter().map(|key| put_object_simple(client, BUCKET, key, Payload::Buffered(Bytes::from(vec![1; 10])), &uploader, &attributes));
+        futures::future::try_join_all(puts).await.unwrap();
         
-        pb.set_message(format!("ops: {}, errors: {}", operation_count, errors));
-        pb.set_position(start.elapsed().as_secs().min(duration_secs));
+        let objects = discover_objects(client, BUCKET, "page/").await.unwrap();
+        assert_eq!(objects.len(), 1203);
+        assert!(objects.windows(2).all(|w| w[0].key < w[1].key));
+        let lister = Lister::new(list_args(&server, &[]).list);
+        assert_eq!(lister.list(client, BUCKET, "page/00", None).await.unwrap(), 1000);
+    }
+    
+    #[tokio::test]
+    async fn operations_are_spread_over_endpoints() {
+        let first = MockServer::start("127.0.0.1:0").await.unwrap();
+        let second = MockServer::start("127.0.0.1:0").await.unwrap();
+        let args = put_args(&first, &["--endpoint", &second.endpoint, "--ops", "8", "--object-size", "1024"]);
+        let stats = PutWorkload::new(args).await.unwrap().execute(2).await.unwrap();
+        assert_eq!((stats.operations, stats.errors), (8, 0));
+        assert_eq!(first.store.object_count(BUCKET), 4);
+        assert_eq!(second.store.object_count(BUCKET), 4);
+        let per_endpoint: Vec<_> = stats.endpoints.iter().map(|e| (e.endpoint.as_str(), e.operations, e.bytes_transferred)).collect();
+        assert_eq!(per_endpoint, [(first.endpoint.as_str(), 4, 4 * 1024), (second.endpoint.as_str(), 4, 4 * 1024)]);
+    }
+    
+    #[tokio::test]
+    async fn least_outstanding_avoids_slow_endpoint() {
+        let slow = faulty_server("rules:\n  - operations: [PutObject]\n    latency_ms: 300\n").await;
+        let fast = MockServer::start("127.0.0.1:0").await.unwrap();
+        let file = std::env::temp_dir().join(format!("s3-load-gen-endpoints-{}.txt", std::process::id()));
+        std::fs::write(&file, format!("# storage nodes\n{}\n\n{}  # fast node\n", slow.endpoint, fast.endpoint)).unwrap();
+        let mut args = vec!["put", "--endpoints-file", file.to_str().unwrap(), "--endpoint-selection", "least-outstanding"];
+        args.extend_from_slice(&["--bucket", BUCKET, "--access-key", "test", "--secret-key", "test", "--ops", "40", "--object-size", "1024"]);
+        let Commands::Put(args) = parse(&args) else { unreachable!() };
+        let stats = PutWorkload::new(args).await.unwrap().execute(2).await.unwrap();
+        std::fs::remove_file(&file).unwrap();
         
-        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!((stats.operations, stats.errors), (40, 0));
+        assert_eq!(stats.endpoints[0].endpoint, slow.endpoint);
+        assert!(stats.endpoints[0].operations < 10, "{} ops sent to the slow endpoint", stats.endpoints[0].operations);
+        assert_eq!(slow.store.object_count(BUCKET) + fast.store.object_count(BUCKET), 40);
     }
     
-    println!("[BENCH] Duration reached, waiting for {} in-flight LIST operations to complete...", tasks.len());
-    pb.finish_with_message("Waiting for all operations to complete...");
+    #[tokio::test]
+    async fn connection_reuse_is_counted() {
+        let server = MockServer::start("127.0.0.1:0").await.unwrap();
+        let put = |extra: &[&str]| {
+            let mut args = vec!["--ops", "10", "--object-size", "1024"];
+            args.extend_from_slice(extra);
+            put_args(&server, &args)
+        };
+        
+        let stats = PutWorkload::new(put(&[])).await.unwrap().execute(1).await.unwrap();
+        assert_eq!(stats.connections, ConnectionCounts { opened: 1, reused: 9 });
+        
+        let stats = PutWorkload::new(put(&["--max-idle-per-host", "0"])).await.unwrap().execute(1).await.unwrap();
+        assert_eq!(stats.connections, ConnectionCounts { opened: 10, reused: 0 });
+        
+        let stats = PutWorkload::new(put(&["--http-version", "http2", "--no-tcp-nodelay", "--tcp-keepalive", "30s"])).await.unwrap().execute(4).await.unwrap();
+        assert_eq!((stats.operations, stats.errors), (10, 0));
+        assert_eq!(stats.connections, ConnectionCounts { opened: 1, reused: 9 });
+    }
     
-    // Wait for all tasks to complete
-    println!("[BENCH] Collecting results from {} LIST tasks...", tasks.len());
-    for (idx, task) in tasks.into_iter().enumerate() {
-        println!("[BENCH] Waiting for LIST task {} of {} to complete...", idx + 1, operation_count);
-        match task.await {
-            Ok((Ok(count), latency)) => {
-                println!("[BENCH] LIST task {} succeeded: {} objects in {:.2}ms", idx + 1, count, latency.as_secs_f64() * 1000.0);
-                total_objects_listed += count as u64;
-                total_latency_ms += latency.as_secs_f64() * 1000.0;
-            }
-            Ok((Err(e), _)) => {
-                println!("[BENCH] LIST task {} failed with error: {:?}", idx + 1, e);
-                errors += 1;
-            }
-            Err(e) => {
-                println!("[BENCH] LIST task {} panicked: {:?}", idx + 1, e);
-                errors += 1;
-            }
+    #[tokio::test]
+    async fn resolve_overrides_and_source_addresses_apply() {
+        let server = MockServer::start("127.0.0.1:0").await.unwrap();
+        let port = server.endpoint.rsplit(':').next().unwrap();
+        let endpoint = format!("http://gateway.invalid:{}", port);
+        let resolve = format!("gateway.invalid:{}:127.0.0.1", port);
+        let put = |extra: &[&str]| {
+            let mut args = vec!["put", "--endpoint", &endpoint, "--resolve", &resolve, "--bucket", BUCKET];
+            args.extend_from_slice(&["--access-key", "test", "--secret-key", "test", "--ops", "2", "--object-size", "1024", "--retry-mode", "none"]);
+            args.extend_from_slice(extra);
+            let Commands::Put(args) = parse(&args) else { unreachable!() };
+            args
+        };
+        
+        let stats = PutWorkload::new(put(&["--local-address", "127.0.0.1"])).await.unwrap().execute(1).await.unwrap();
+        assert_eq!((stats.operations, stats.errors), (2, 0));
+        assert_eq!(server.store.object_count(BUCKET), 2);
+        
+        // A source address this host does not own cannot be bound
+        let stats = PutWorkload::new(put(&["--local-address", "192.0.2.1"])).await.unwrap().execute(1).await.unwrap();
+        assert_eq!((stats.operations, stats.errors), (2, 2));
+    }
+    
+    #[tokio::test]
+    async fn tls_with_client_certificates_and_virtual_hosted_addressing() {
+        // testdata/tls: a CA, a server certificate for s3.test and *.s3.test, and a client certificate
+        let tls = |name: &str| format!("{}/testdata/tls/{}", env!("CARGO_MANIFEST_DIR"), name);
+        let (server_cert, server_key, ca) = (tls("server.pem"), tls("server.key"), tls("ca.pem"));
+        let (client_cert, client_key) = (tls("client.pem"), tls("client.key"));
+        let args = [
+            "mock-server", "--listen", "127.0.0.1:0", "--tls-cert", &server_cert, "--tls-key", &server_key,
+            "--client-ca", &ca, "--domain", "s3.test",
+        ];
+        let Commands::MockServer(args) = parse(&args) else { unreachable!() };
+        let server = mock_server::MockServer::launch(&args).await.unwrap();
+        assert!(server.endpoint.starts_with("https://"), "{}", server.endpoint);
+        
+        let port = server.endpoint.rsplit(':').next().unwrap();
+        let endpoint = format!("https://s3.test:{}", port);
+        let resolve = format!("s3.test:{0}:127.0.0.1,bench.s3.test:{0}:127.0.0.1", port);
+        let resolve: Vec<&str> = resolve.split(',').collect();
+        let put = |extra: &[&str]| {
+            let mut args = vec!["put", "--endpoint", &endpoint, "--resolve", resolve[0], "--resolve", resolve[1], "--bucket", BUCKET];
+            args.extend_from_slice(&["--ca-bundle", &ca, "--access-key", "test", "--secret-key", "test", "--ops", "2", "--object-size", "1024"]);
+            args.extend_from_slice(&["--retry-mode", "none"]);
+            args.extend_from_slice(extra);
+            let Commands::Put(args) = parse(&args) else { unreachable!() };
+            args
+        };
+        
+        // Handshake with a client certificate, requests addressed to bench.s3.test
+        let mtls = ["--client-cert", client_cert.as_str(), "--client-key", client_key.as_str(), "--virtual-hosted-style"];
+        let stats = PutWorkload::new(put(&mtls)).await.unwrap().execute(1).await.unwrap();
+        assert_eq!((stats.operations, stats.errors), (2, 0));
+        assert_eq!(server.store.object_count(BUCKET), 2);
+        
+        // Path-style requests over the same listener
+        let stats = PutWorkload::new(put(&mtls[..4])).await.unwrap().execute(1).await.unwrap();
+        assert_eq!((stats.operations, stats.errors), (2, 0));
+        assert_eq!(server.store.object_count(BUCKET), 4);
+        
+        // The server refuses clients without a certificate
+        let stats = PutWorkload::new(put(&[])).await.unwrap().execute(1).await.unwrap();
+        assert_eq!((stats.operations, stats.errors), (2, 2));
+        assert_eq!(server.store.object_count(BUCKET), 4);
+    }
+    
+    #[tokio::test]
+    async fn bandwidth_limits_pace_transfers() {
+        let server = MockServer::start("127.0.0.1:0").await.unwrap();
+        
+        // 4 x 64 KiB through a shared 256 KiB/s limit take about a second in total
+        let args = put_args(&server, &["--ops", "4", "--object-size", "65536", "--bandwidth-limit", "256K"]);
+        let started = Instant::now();
+        let stats = PutWorkload::new(args).await.unwrap().execute(4).await.unwrap();
+        assert_eq!((stats.operations, stats.errors, stats.bytes_transferred), (4, 0, 4 * 65536));
+        assert!(started.elapsed() >= Duration::from_millis(800), "{:?}", started.elapsed());
+        
+        // Each download stream is limited separately, so concurrent GETs overlap
+        let args = get_args(&server, &["--ops", "4", "--bandwidth-limit-per-connection", "256K"]);
+        let stats = GetWorkload::prepare(args).await.unwrap().execute(4).await.unwrap();
+        assert_eq!((stats.operations, stats.errors, stats.bytes_transferred), (4, 0, 4 * 65536));
+        assert!(stats.percentile_ms(0.5) >= 150.0, "{}", stats.percentile_ms(0.5));
+        
+        // Multipart parts are paced too
+        let args = put_args(&server, &["--ops", "1", "--object-size", "65536", "--part-size", "16384", "--bandwidth-limit", "128K"]);
+        let stats = PutWorkload::new(args).await.unwrap().execute(1).await.unwrap();
+        assert_eq!((stats.operations, stats.errors), (1, 0));
+        assert!(stats.percentile_ms(0.5) >= 300.0, "{}", stats.percentile_ms(0.5));
+        
+        // Commands that do not pace bodies do not take the limits
+        let list = ["s3-load-gen", "list", "--endpoint", &server.endpoint, "--bucket", BUCKET, "--bandwidth-limit", "1M"];
+        assert!(Cli::try_parse_from(list).is_err());
+    }
+    
+    #[tokio::test]
+    async fn upload_modes_stream_and_sign_payloads() {
+        let cases: [(&[&str], &str); 4] = [
+            (&[], "SHA-256"),
+            (&["--upload-mode", "unsigned"], "UNSIGNED-PAYLOAD"),
+            (&["--upload-mode", "unsigned", "--unknown-length"], "UNSIGNED-PAYLOAD without Content-Length"),
+            (&["--upload-mode", "aws-chunked"], "STREAMING-AWS4-HMAC-SHA256-PAYLOAD-TRAILER"),
+        ];
+        let mut signing = BTreeMap::new();
+        for (mode, scheme) in cases {
+            let server = MockServer::start("127.0.0.1:0").await.unwrap();
+            let mut args = vec!["--ops", "3", "--object-size", "100000", "--stream-chunk-size", "10000"];
+            args.extend_from_slice(mode);
+            let stats = PutWorkload::new(put_args(&server, &args)).await.unwrap().execute(3).await.unwrap();
+            assert_eq!((stats.operations, stats.errors), (3, 0), "{}", scheme);
+            assert!(stats.requests["PutObject"].signing > Duration::ZERO, "{}", scheme);
+            signing.insert(scheme, stats.requests["PutObject"].signing);
+            assert_eq!(server.store.payload_signing(), BTreeMap::from([(scheme.to_string(), 3)]));
+            
+            let endpoints = connect(&put_args(&server, &[]).conn).await.unwrap();
+            let objects = discover_objects(endpoints.primary(), BUCKET, "test-object/").await.unwrap();
+            assert!(objects.iter().all(|obj| obj.size == 100000), "{}", scheme);
         }
+        // Ten chunk signatures per request are counted on top of the header signature
+        assert!(signing["STREAMING-AWS4-HMAC-SHA256-PAYLOAD-TRAILER"] > signing["UNSIGNED-PAYLOAD"], "{:?}", signing);
+        
+        // Parts of a multipart upload are streamed as well
+        let server = MockServer::start("127.0.0.1:0").await.unwrap();
+        let args = put_args(&server, &["--ops", "1", "--object-size", "3000", "--part-size", "1024", "--upload-mode", "aws-chunked"]);
+        let stats = PutWorkload::new(args).await.unwrap().execute(1).await.unwrap();
+        assert_eq!((stats.operations, stats.errors, stats.requests["UploadPart"].requests), (1, 0, 3));
+        
+        let args = put_args(&server, &["--upload-mode", "aws-chunked", "--unknown-length"]);
+        assert!(PutWorkload::new(args).await.is_err());
     }
     
-    println!("[BENCH] All LIST tasks completed!");
+    #[tokio::test]
+    async fn copies_small_and_large_objects_server_side() {
+        let server = MockServer::start("127.0.0.1:0").await.unwrap();
+        for (size, prefix) in [("1000", "src/small-"), ("2500", "src/large-")] {
+            let args = put_args(&server, &["--ops", "2", "--object-size", size, "--disable-multipart", "--prefix", prefix]);
+            PutWorkload::new(args).await.unwrap().execute(2).await.unwrap();
+        }
+        
+        let Commands::Copy(args) = command_args("copy", &server, &["--ops", "4", "--source-prefix", "src/", "--dest-prefix", "dst/", "--part-size", "1024"]) else { unreachable!() };
+        let stats = copy::CopyWorkload::prepare(args).await.unwrap().execute(2).await.unwrap();
+        assert_eq!((stats.operations, stats.errors), (4, 0));
+        assert_eq!(stats.bytes_transferred, 2 * 1000 + 2 * 2500);
+        assert_eq!(stats.requests["CopyObject"].requests, 2);
+        assert_eq!(stats.requests["UploadPartCopy"].requests, 2 * 3);
+        assert_eq!(server.store.pending_uploads(), 0);
+        
+        let endpoints = connect(&put_args(&server, &[]).conn).await.unwrap();
+        let client = endpoints.primary();
+        for source in discover_objects(client, BUCKET, "src/").await.unwrap() {
+            let dest = source.key.replacen("src/", "dst/", 1);
+            let read = |key: String| async move {
+                client.get_object().bucket(BUCKET).key(key).send().await.unwrap().body.collect().await.unwrap().into_bytes()
+            };
+            assert_eq!(read(source.key.clone()).await, read(dest).await, "{}", source.key);
+        }
+    }
     
-    let total_duration = start.elapsed();
+    #[tokio::test]
+    async fn multipart_stress_checks_upload_lifecycles() {
+        use std::sync::atomic::Ordering;
+        
+        let server = MockServer::start("127.0.0.1:0").await.unwrap();
+        let Commands::MultipartStress(args) = command_args("multipart-stress", &server, &["--ops", "4", "--parts", "1001", "--part-size", "64", "--abort-rate", "0.5", "--overwrite-rate", "0.2"]) else { unreachable!() };
+        let workload = multipart::MultipartStressWorkload::new(args).await.unwrap();
+        let stats = workload.execute(2).await.unwrap();
+        
+        let counters = &workload.counters;
+        let (completed, aborted) = (counters.completed.load(Ordering::Relaxed), counters.aborted.load(Ordering::Relaxed));
+        assert_eq!((stats.operations, stats.errors), (4, 0));
+        assert_eq!(counters.check_failures.load(Ordering::Relaxed), 0);
+        assert_eq!(completed + aborted, 4);
+        assert_eq!(server.store.object_count(BUCKET), completed as usize);
+        assert_eq!(server.store.pending_uploads(), 0);
+        let overwrites = counters.overwrites.load(Ordering::Relaxed);
+        assert_eq!(stats.requests["UploadPart"].requests, 4 * 1001 + overwrites);
+        // 1001 parts take two ListParts pages per upload, plus one more after each abort
+        assert_eq!(stats.requests["ListParts"].requests, 4 * 2 + aborted);
+        assert_eq!(stats.requests["ListMultipartUploads"].requests, 4);
+        
+        // Below S3's minimum part size completing fails, which is counted as its own outcome rather than an error
+        server.store.set_min_part_size(5 * 1024 * 1024);
+        let Commands::MultipartStress(args) = command_args("multipart-stress", &server, &["--ops", "2", "--parts", "3", "--part-size", "64", "--abort-rate", "0", "--prefix", "too-small/"]) else { unreachable!() };
+        let workload = multipart::MultipartStressWorkload::new(args).await.unwrap();
+        let stats = workload.execute(2).await.unwrap();
+        assert_eq!((stats.operations, stats.errors), (2, 0));
+        assert_eq!((workload.counters.too_small.load(Ordering::Relaxed), workload.counters.completed.load(Ordering::Relaxed)), (2, 0));
+        assert_eq!(server.store.pending_uploads(), 0);
+    }
     
-    let stats = Stats {
-        operations: operation_count,
-        bytes_transferred: 0,
-        errors,
-        duration: total_duration,
-        total_latency_ms,
-    };
+    #[tokio::test]
+    async fn multipart_stress_aborts_failed_uploads() {
+        let server = faulty_server("rules:\n  - operations: [UploadPart]\n    slow_down_rate: 1.0\n").await;
+        let Commands::MultipartStress(args) = command_args("multipart-stress", &server, &["--ops", "2", "--parts", "3", "--part-size", "64", "--retry-mode", "none"]) else { unreachable!() };
+        let stats = multipart::MultipartStressWorkload::new(args).await.unwrap().execute(2).await.unwrap();
+        assert_eq!((stats.operations, stats.errors), (2, 2));
+        assert_eq!(stats.requests["AbortMultipartUpload"].requests, 2);
+        assert_eq!(server.store.pending_uploads(), 0);
+    }
     
-    stats.print("LIST");
-    println!("Total objects listed: {}", total_objects_listed);
-    println!("Avg objects per list: {:.2}", total_objects_listed as f64 / operation_count as f64);
+    #[tokio::test]
+    async fn list_variants_paginate_and_roll_up_prefixes() {
+        let server = MockServer::start("127.0.0.1:0").await.unwrap();
+        let endpoints = connect(&put_args(&server, &[]).conn).await.unwrap();
+        let client = endpoints.primary();
+        // Three directories of four keys and two top-level keys
+        let mut keys: Vec<String> = (0..12).map(|i| format!("tree/dir{}/{}", i / 4, i % 4)).collect();
+        keys.extend(["tree/x", "tree/y"].map(String::from));
+        let (uploader, attributes) = (Uploader::default(), ObjectAttributes::default());
+        let puts = keys.iter().map(|key| put_object_simple(client, BUCKET, key, Payload::Buffered(Bytes::from(vec![1; 10])), &uploader, &attributes));
+        futures::future::try_join_all(puts).await.unwrap();
+        
+        // (options, entries per operation, requests per operation)
+        let cases: [(&[&str], u64, u64); 5] = [
+            (&["--delimiter", "/", "--max-keys", "2"], 5, 3),
+            (&["--delimiter", "/", "--max-keys", "2", "--api", "v1"], 5, 3),
+            (&["--max-keys", "5", "--api", "v1"], 14, 3),
+            (&["--max-keys", "5", "--scope", "page"], 5, 1),
+            (&["--max-keys", "3", "--delimiter", "/", "--scope", "page", "--api", "v1"], 3, 1),
+        ];
+        for (options, entries, requests) in cases {
+            let mut args = vec!["--ops", "2", "--prefix", "tree/"];
+            args.extend_from_slice(options);
+            let list = ListWorkload::new(list_args(&server, &args)).await.unwrap();
+            let stats = list.execute(2).await.unwrap();
+            assert_eq!((stats.operations, stats.errors, stats.objects_listed), (2, 0, 2 * entries), "{:?}", options);
+            let operation = if options.contains(&"v1") { "ListObjects" } else { "ListObjectsV2" };
+            assert_eq!(stats.requests[operation].requests, 2 * requests, "{:?}", options);
+        }
+        
+        // Starting after a random key of a directory lists what follows it
+        let list = ListWorkload::new(list_args(&server, &["--ops", "4", "--prefix", "tree/dir1/", "--random-start-after"])).await.unwrap();
+        let stats = list.execute(2).await.unwrap();
+        assert_eq!((stats.operations, stats.errors), (4, 0));
+        assert!(stats.objects_listed <= 4 * 3);
+        
+        assert!(ListWorkload::new(list_args(&server, &["--max-keys", "1001"])).await.is_err());
+        assert!(ListWorkload::new(list_args(&server, &["--prefix", "none/", "--random-start-after"])).await.is_err());
+    }
     
-    Ok(())
-}
-
-#[tokio::main]
-async fn main() -> Result<()> {
-    let cli = Cli::parse();
+    #[tokio::test]
+    async fn tree_layout_lists_as_directories() {
+        let server = MockServer::start("127.0.0.1:0").await.unwrap();
+        let args = put_args(&server, &["--ops", "18", "--object-size", "10", "--prefix", "layout/", "--key-layout", "tree", "--tree-depth", "2", "--tree-fanout", "3"]);
+        let stats = PutWorkload::new(args).await.unwrap().execute(4).await.unwrap();
+        assert_eq!((stats.operations, stats.errors), (18, 0));
+        
+        // Three top-level directories, each with three leaves of two objects
+        for (prefix, entries) in [("layout/", 3), ("layout/1/", 3), ("layout/1/2/", 2)] {
+            let list = ListWorkload::new(list_args(&server, &["--ops", "1", "--prefix", prefix, "--delimiter", "/"])).await.unwrap();
+            assert_eq!(list.execute(1).await.unwrap().objects_listed, entries, "{}", prefix);
+        }
+        
+        assert!(PutWorkload::new(put_args(&server, &["--key-layout", "tree", "--tree-depth", "40", "--tree-fanout", "10"])).await.is_err());
+    }
     
-    match cli.command {
-        Commands::Put {
-            access_key,
-            secret_key,
-            region,
-            endpoint,
-            bucket,
-            duration_secs,
-            concurrent,
-            object_size,
-            part_size,
-            disable_multipart,
-            prefix,
-        } => {
-            run_put_benchmark(
-                access_key,
-                secret_key,
-                region,
-                endpoint,
-                bucket,
-                duration_secs,
-                concurrent,
-                object_size,
-                part_size,
-                disable_multipart,
-                prefix,
-            )
-            .await?;
+    #[tokio::test]
+    async fn objects_carry_metadata_and_tags() {
+        let server = MockServer::start("127.0.0.1:0").await.unwrap();
+        let metadata = [
+            "--metadata-headers", "4", "--metadata-value-size", "100",
+            "--content-type", "text/plain", "--cache-control", "max-age=60", "--tags", "3",
+        ];
+        for (size, prefix) in [("100", "meta/small-"), ("3072", "meta/large-")] {
+            let mut args = vec!["--ops", "3", "--object-size", size, "--part-size", "1024", "--prefix", prefix];
+            args.extend_from_slice(&metadata);
+            let stats = PutWorkload::new(put_args(&server, &args)).await.unwrap().execute(3).await.unwrap();
+            assert_eq!((stats.operations, stats.errors), (3, 0), "{}", prefix);
         }
-        Commands::Get {
-            access_key,
-            secret_key,
-            region,
-            endpoint,
-            bucket,
-            duration_secs,
-            concurrent,
-            prefix,
-            range_bytes,
-        } => {
-            run_get_benchmark(
-                access_key,
-                secret_key,
-                region,
-                endpoint,
-                bucket,
-                duration_secs,
-                concurrent,
-                prefix,
-                range_bytes,
-            )
-            .await?;
+        
+        let endpoints = connect(&put_args(&server, &[]).conn).await.unwrap();
+        let objects = discover_objects(endpoints.primary(), BUCKET, "meta/").await.unwrap();
+        for object in &objects {
+            let head = endpoints.primary().head_object().bucket(BUCKET).key(&object.key).send().await.unwrap();
+            assert_eq!((head.content_type(), head.cache_control()), (Some("text/plain"), Some("max-age=60")), "{}", object.key);
+            assert_eq!(head.metadata().unwrap().len(), 4);
         }
-        Commands::List {
-            access_key,
-            secret_key,
-            region,
-            endpoint,
-            bucket,
-            duration_secs,
-            concurrent,
-            prefix,
-        } => {
-            run_list_benchmark(
-                access_key,
-                secret_key,
-                region,
-                endpoint,
-                bucket,
-                duration_secs,
-                concurrent,
-                prefix,
-            )
-            .await?;
+        
+        // Every object is visited once; bytes are metadata or tag names plus values
+        let tagging = |extra: &[&str]| {
+            let mut args = vec!["--ops", "6", "--prefix", "meta/"];
+            args.extend_from_slice(extra);
+            let Commands::Tagging(args) = command_args("tagging", &server, &args) else { unreachable!() };
+            args
+        };
+        let expected: [(&[&str], u64); 4] = [
+            (&["--operation", "head"], 4 * (8 + 100)),
+            (&["--operation", "get-tagging"], 3 * (6 + 16)),
+            (&["--operation", "put-tagging", "--tags", "2", "--tag-value-size", "10"], 2 * (6 + 10)),
+            (&["--operation", "get-tagging"], 2 * (6 + 10)),
+        ];
+        for (args, bytes) in expected {
+            let workload = metadata::TaggingWorkload::prepare(tagging(args)).await.unwrap();
+            let stats = workload.execute(2).await.unwrap();
+            assert_eq!((stats.operations, stats.errors, stats.bytes_transferred), (6, 0, 6 * bytes), "{:?}", args);
+        }
+        assert!(metadata::TaggingWorkload::prepare(tagging(&["--operation", "put-tagging"])).await.is_err());
+        for operation in ["head", "get-tagging"] {
+            let err = metadata::TaggingWorkload::prepare(tagging(&["--operation", operation, "--total-bytes", "100"])).await.err().unwrap();
+            assert!(err.to_string().contains("--total-bytes"), "{}", err);
+        }
+        
+        // Over S3's limits of 2 KB of user metadata, 10 tags and 256-byte tag values, refused before the run
+        for limit in [&["--metadata-headers", "20", "--metadata-value-size", "100"], &["--tags", "11", "--tag-value-size", "16"], &["--tags", "1", "--tag-value-size", "300"]] {
+            let mut args = vec!["--ops", "2", "--object-size", "10", "--prefix", "limits/"];
+            args.extend_from_slice(limit);
+            assert!(PutWorkload::new(put_args(&server, &args)).await.is_err(), "{:?}", limit);
+        }
+        for limit in [&["--tags", "11", "--tag-value-size", "16"], &["--tags", "1", "--tag-value-size", "300"]] {
+            let mut args = vec!["--operation", "put-tagging"];
+            args.extend_from_slice(limit);
+            assert!(metadata::TaggingWorkload::prepare(tagging(&args)).await.is_err(), "{:?}", limit);
         }
     }
     
-    Ok(())
+    #[tokio::test]
+    async fn conditional_writes_contend_and_gets_revalidate() {
+        use std::sync::atomic::Ordering;
+        
+        let server = MockServer::start("127.0.0.1:0").await.unwrap();
+        let conditional = |extra: &[&str]| {
+            let Commands::Conditional(args) = command_args("conditional", &server, extra) else { unreachable!() };
+            conditional::ConditionalWorkload::prepare(args)
+        };
+        let counts = |outcomes: &conditional::Outcomes| {
+            [&outcomes.success, &outcomes.not_modified, &outcomes.precondition_failed, &outcomes.conflict].map(|count| count.load(Ordering::Relaxed))
+        };
+        
+        // Each of the three keys is created by exactly one writer
+        let workload = conditional(&["--mode", "create", "--keys", "3", "--ops", "30"]).await.unwrap();
+        let stats = workload.execute(8).await.unwrap();
+        assert_eq!((stats.operations, stats.errors), (30, 0));
+        assert_eq!(counts(&workload.outcomes), [3, 0, 27, 0]);
+        assert_eq!(workload.verify().await.unwrap(), 0);
+        
+        // Swaps that lose the race fail their precondition instead of overwriting
+        let workload = conditional(&["--mode", "cas", "--keys", "2", "--ops", "40"]).await.unwrap();
+        let stats = workload.execute(8).await.unwrap();
+        assert_eq!((stats.operations, stats.errors), (40, 0));
+        let [success, _, failed, _] = counts(&workload.outcomes);
+        assert!(success >= 2 && success + failed == 40, "{} {}", success, failed);
+        assert_eq!(workload.verify().await.unwrap(), 0);
+        
+        for (condition, expected) in [
+            ("if-none-match", [0, 4, 0, 0]),
+            ("if-match", [4, 0, 0, 0]),
+            ("if-match-stale", [0, 0, 4, 0]),
+            ("if-modified-since", [0, 4, 0, 0]),
+            ("if-unmodified-since", [0, 0, 4, 0]),
+        ] {
+            let workload = conditional(&["--mode", "get", "--keys", "2", "--ops", "4", "--get-condition", condition]).await.unwrap();
+            let stats = workload.execute(2).await.unwrap();
+            assert_eq!((stats.operations, stats.errors), (4, 0), "{}", condition);
+            assert_eq!(counts(&workload.outcomes), expected, "{}", condition);
+        }
+        
+        let err = conditional(&["--mode", "cas", "--total-bytes", "100"]).await.err().unwrap();
+        assert!(err.to_string().contains("--total-bytes"), "{}", err);
+    }
+    
+    #[tokio::test]
+    async fn presigned_urls_drive_plain_http_requests() {
+        let server = MockServer::start("127.0.0.1:0").await.unwrap();
+        let presigned = |extra: &[&str]| {
+            let mut args = vec!["--ops", "12", "--prefix", "presigned/"];
+            args.extend_from_slice(extra);
+            let Commands::Presigned(args) = command_args("presigned", &server, &args) else { unreachable!() };
+            presigned::PresignedWorkload::prepare(args)
+        };
+        
+        let workload = presigned(&["--operation", "put", "--keys", "4", "--object-size", "1000"]).await.unwrap();
+        let stats = workload.execute_presigned(3).await.unwrap();
+        assert_eq!((stats.operations, stats.errors, stats.bytes_transferred), (12, 0, 12 * 1000));
+        // The plain client bypasses the SDK, so no signed requests are counted
+        assert!(stats.requests.is_empty());
+        assert_eq!(stats.connections.opened + stats.connections.reused, 12);
+        assert_eq!(server.store.object_count(BUCKET), 4);
+        
+        let workload = presigned(&["--operation", "get"]).await.unwrap();
+        let presigned_stats = workload.execute_presigned(3).await.unwrap();
+        let signed_stats = workload.execute_signed(3).await.unwrap();
+        for stats in [&presigned_stats, &signed_stats] {
+            assert_eq!((stats.operations, stats.errors, stats.bytes_transferred), (12, 0, 12 * 1000));
+        }
+        assert_eq!(signed_stats.requests["GetObject"].requests, 12);
+        assert!(signed_stats.requests["GetObject"].signing > Duration::ZERO);
+        
+        assert!(presigned(&["--endpoint", &server.endpoint]).await.is_err(), "URLs are bound to one endpoint");
+        assert!(presigned(&["--duration-secs", "60", "--expires-in", "1m"]).await.is_err(), "URLs would expire during the run");
+        
+        // An --ops run stops where the URLs expire, 5 seconds before --expires-in
+        let args = ["--ops", "100000000", "--prefix", "presigned/", "--expires-in", "7s"];
+        let Commands::Presigned(args) = command_args("presigned", &server, &args) else { unreachable!() };
+        let workload = presigned::PresignedWorkload::prepare(args).await.unwrap();
+        let started = Instant::now();
+        let stats = workload.execute_presigned(3).await.unwrap();
+        assert!(stats.operations < 100_000_000 && started.elapsed() < Duration::from_secs(4), "{} ops in {:?}", stats.operations, started.elapsed());
+    }
+    
+    #[tokio::test]
+    async fn consistency_check_finds_no_violations() {
+        let server = MockServer::start("127.0.0.1:0").await.unwrap();
+        let args = ["--ops", "200", "--keys", "4", "--writers", "4", "--readers", "8", "--object-size", "100", "--list-fraction", "0.2"];
+        let Commands::Consistency(args) = command_args("consistency", &server, &args) else { unreachable!() };
+        let workload = consistency::ConsistencyWorkload::prepare(args).await.unwrap();
+        let (writes, reads) = workload.execute().await.unwrap();
+        assert_eq!((writes.operations, writes.errors, reads.operations, reads.errors), (200, 0, 200, 0));
+        assert!(workload.violations().is_empty(), "{:?}", workload.violations());
+    }
+    
+    #[tokio::test]
+    async fn consistency_check_reports_stale_reads() {
+        let server = faulty_server("seed: 3\nrules:\n  - operations: [GetObject, HeadObject]\n    stale_read_rate: 0.5\n").await;
+        let args = ["--ops", "200", "--keys", "4", "--writers", "4", "--readers", "8", "--object-size", "100", "--list-fraction", "0"];
+        let Commands::Consistency(args) = command_args("consistency", &server, &args) else { unreachable!() };
+        let workload = consistency::ConsistencyWorkload::prepare(args).await.unwrap();
+        let (_, reads) = workload.execute().await.unwrap();
+        assert_eq!((reads.operations, reads.errors), (200, 0));
+        let violations = workload.violations();
+        assert!(violations.iter().any(|violation| violation.kind == consistency::ViolationKind::StaleRead), "{:?}", violations);
+    }
+    
+    #[tokio::test]
+    async fn versions_workload_builds_reads_and_deletes_stacks() {
+        use std::sync::atomic::Ordering;
+        
+        let server = MockServer::start("127.0.0.1:0").await.unwrap();
+        let versions = |extra: &[&str]| {
+            let mut args = vec!["--ops", "10", "--keys", "6", "--versions", "4", "--object-size", "100", "--page-size", "5"];
+            args.extend_from_slice(extra);
+            let Commands::Versions(args) = command_args("versions", &server, &args) else { unreachable!() };
+            versions::VersionsWorkload::prepare(args)
+        };
+        assert!(versions(&[]).await.is_err(), "versioning is off until enabled");
+        assert!(versions(&["--enable-versioning", "--total-bytes", "1000"]).await.is_err());
+        
+        let workload = versions(&["--enable-versioning"]).await.unwrap();
+        let phases = workload.execute(3).await.unwrap().0;
+        let summary: Vec<_> = phases.iter().map(|(label, stats)| (*label, stats.operations, stats.errors)).collect();
+        assert_eq!(
+            summary,
+            [("PUT-VERSION", 24, 0), ("GET-VERSION", 10, 0), ("LIST-VERSIONS", 10, 0), ("DELETE-MARKER", 6, 0), ("DELETE-VERSION", 30, 0)]
+        );
+        assert_eq!(workload.check_failures.load(Ordering::Relaxed), 0);
+        // 24 versions in pages of 5, so page boundaries fall inside version stacks
+        let (_, list) = &phases[2];
+        assert_eq!((list.objects_listed, list.requests["ListObjectVersions"].requests), (10 * 24, 10 * 5));
+        assert_eq!((server.store.object_count(BUCKET), server.store.version_count(BUCKET)), (0, 0));
+        
+        // Versions left under the prefix would fail every listing check
+        PutWorkload::new(put_args(&server, &["--ops", "1", "--object-size", "100", "--prefix", "versioned/"])).await.unwrap().execute(1).await.unwrap();
+        let err = versions(&[]).await.err().unwrap();
+        assert!(err.to_string().contains("already holds 1 versions"), "{}", err);
+    }
+    
+    #[tokio::test]
+    async fn benchmarks_run_end_to_end() {
+        let server = MockServer::start("127.0.0.1:0").await.unwrap();
+        run_put_benchmark(put_args(&server, &["--ops", "3", "--object-size", "4096"])).await.unwrap();
+        run_get_benchmark(get_args(&server, &["--ops", "3"])).await.unwrap();
+        run_list_benchmark(list_args(&server, &["--ops", "1", "--prefix", "test-object/"])).await.unwrap();
+    }
+    
+    async fn faulty_server(config: &str) -> MockServer {
+        let faults = mock_server::faults::FaultConfig::parse(config).unwrap();
+        MockServer::start_with_faults("127.0.0.1:0", faults).await.unwrap()
+    }
+    
+    #[tokio::test]
+    async fn injected_slow_down_fails_puts() {
+        let server = faulty_server("rules:\n  - operations: [PutObject]\n    slow_down_rate: 1.0\n").await;
+        let stats = PutWorkload::new(put_args(&server, &["--ops", "2", "--object-size", "1024"])).await.unwrap().execute(2).await.unwrap();
+        assert_eq!((stats.operations, stats.errors), (2, 2));
+        assert_eq!(server.store.object_count(BUCKET), 0);
+    }
+    
+    #[tokio::test]
+    async fn retries_are_counted_per_operation() {
+        let server = faulty_server("rules:\n  - operations: [PutObject]\n    slow_down_rate: 1.0\n").await;
+        let put = |extra: &[&str]| {
+            let mut args = vec!["--ops", "2", "--object-size", "1024"];
+            args.extend_from_slice(extra);
+            put_args(&server, &args)
+        };
+        
+        let stats = PutWorkload::new(put(&["--max-attempts", "2"])).await.unwrap().execute(2).await.unwrap();
+        assert_eq!(stats.errors, 2);
+        assert_eq!(without_signing(stats.requests["PutObject"]), RequestCounts { requests: 2, retries: 2, timeouts: 0, ..Default::default() });
+        
+        let stats = PutWorkload::new(put(&["--retry-mode", "none"])).await.unwrap().execute(2).await.unwrap();
+        assert_eq!(without_signing(stats.requests["PutObject"]), RequestCounts { requests: 2, retries: 0, timeouts: 0, ..Default::default() });
+    }
+    
+    #[tokio::test]
+    async fn timeouts_are_counted_per_operation() {
+        let server = faulty_server("rules:\n  - operations: [GetObject]\n    latency_ms: 1000\n").await;
+        PutWorkload::new(put_args(&server, &["--ops", "2", "--object-size", "1024"])).await.unwrap().execute(2).await.unwrap();
+        
+        for timeout in [["--operation-timeout", "100ms"], ["--read-timeout", "100ms"]] {
+            let mut args = vec!["--ops", "2", "--retry-mode", "none"];
+            args.extend_from_slice(&timeout);
+            let stats = GetWorkload::prepare(get_args(&server, &args)).await.unwrap().execute(2).await.unwrap();
+            assert_eq!(stats.errors, 2, "{}", timeout[0]);
+            let counts = without_signing(stats.requests["GetObject"]);
+            assert_eq!(counts, RequestCounts { requests: 2, retries: 0, timeouts: 2, ..Default::default() }, "{}", timeout[0]);
+        }
+    }
+    
+    #[tokio::test]
+    async fn latency_percentiles_reflect_real_latency() {
+        let server = faulty_server("rules:\n  - operations: [PutObject]\n    latency_ms: 20\n").await;
+        let stats = PutWorkload::new(put_args(&server, &["--ops", "4", "--object-size", "1024"])).await.unwrap().execute(2).await.unwrap();
+        assert_eq!((stats.operations, stats.errors), (4, 0));
+        for quantile in [0.5, 0.99] {
+            let ms = stats.percentile_ms(quantile);
+            assert!((20.0..1000.0).contains(&ms), "p{}: {} ms", quantile * 100.0, ms);
+        }
+    }
+    
+    #[tokio::test]
+    async fn autotune_settles_below_saturated_capacity() {
+        // Two requests are served at a time, each taking 200 ms: from three concurrent requests on they queue
+        let server = faulty_server("rules:\n  - operations: [PutObject]\n    latency_ms: 200\n    capacity: 2\n").await;
+        let args = [
+            "autotune", "--target-latency", "300ms", "--interval-secs", "1", "--start", "0", "--max-concurrency", "64",
+            "put", "--endpoint", &server.endpoint, "--bucket", BUCKET, "--access-key", "test", "--secret-key", "test", "--object-size", "10",
+        ];
+        let Commands::Autotune(args) = parse(&args) else { unreachable!() };
+        assert_eq!(autotune::tune(args).await.unwrap(), Some(2));
+    }
+    
+    #[tokio::test]
+    async fn autotune_aimd_backs_off_after_overshooting() {
+        // Slow start passes 1 and 2, overshoots to 4, halves back to 2, then probes 3; only 1 and 2 stay within the SLO
+        let server = faulty_server("rules:\n  - operations: [PutObject]\n    latency_ms: 200\n    capacity: 2\n").await;
+        let autotune = |extra: &[&str]| {
+            let mut args = vec!["autotune", "--target-latency", "300ms", "--strategy", "aimd", "--max-concurrency", "64"];
+            args.extend_from_slice(extra);
+            args.extend_from_slice(&["put", "--endpoint", &server.endpoint, "--bucket", BUCKET, "--anonymous", "--object-size", "10"]);
+            Cli::try_parse_from(std::iter::once("s3-load-gen").chain(args))
+        };
+        assert!(autotune(&["--interval-secs", "0"]).is_err());
+        
+        let Commands::Autotune(args) = autotune(&["--interval-secs", "1", "--max-steps", "5"]).unwrap().command else { unreachable!() };
+        assert_eq!(autotune::tune(args).await.unwrap(), Some(2));
+        let Commands::Autotune(args) = autotune(&["--interval-secs", "1", "--max-error-rate", "2"]).unwrap().command else { unreachable!() };
+        assert!(autotune::tune(args).await.is_err());
+    }
+    
+    #[tokio::test]
+    async fn injected_body_faults_affect_gets() {
+        let server = faulty_server("rules:\n  - operations: [GetObject]\n    latency_ms: 50\n").await;
+        PutWorkload::new(put_args(&server, &["--ops", "2", "--object-size", "4096"])).await.unwrap().execute(2).await.unwrap();
+        let stats = GetWorkload::prepare(get_args(&server, &["--ops", "4"])).await.unwrap().execute(2).await.unwrap();
+        assert_eq!(stats.errors, 0);
+        assert_eq!(stats.bytes_transferred, 4 * 4096);
+        assert!(stats.percentile_ms(0.5) >= 50.0, "{}", stats.percentile_ms(0.5));
+        
+        // Truncated bodies advertise the shortened length, so only the size check catches them
+        let server = faulty_server("rules:\n  - operations: [GetObject]\n    truncate_rate: 1.0\n").await;
+        PutWorkload::new(put_args(&server, &["--ops", "2", "--object-size", "4096"])).await.unwrap().execute(2).await.unwrap();
+        let stats = GetWorkload::prepare(get_args(&server, &["--ops", "4"])).await.unwrap().execute(2).await.unwrap();
+        assert_eq!((stats.operations, stats.errors, stats.bytes_transferred), (4, 4, 0));
+        
+        let server = faulty_server("rules:\n  - operations: [GetObject]\n    reset_rate: 1.0\n").await;
+        PutWorkload::new(put_args(&server, &["--ops", "2", "--object-size", "4096"])).await.unwrap().execute(2).await.unwrap();
+        let stats = GetWorkload::prepare(get_args(&server, &["--ops", "4"])).await.unwrap().execute(2).await.unwrap();
+        assert_eq!((stats.operations, stats.errors), (4, 4));
+    }
+    
+    #[tokio::test]
+    async fn placeholder_credentials_are_refused() {
+        let server = MockServer::start("127.0.0.1:0").await.unwrap();
+        let connect = |credentials: &[&str]| {
+            let mut args = vec!["put", "--endpoint", &server.endpoint, "--bucket", BUCKET, "--ops", "1"];
+            args.extend_from_slice(credentials);
+            match parse(&args) {
+                Commands::Put(args) => PutWorkload::new(args),
+                _ => unreachable!(),
+            }
+        };
+        
+        let err = connect(&["--access-key", "changeme", "--secret-key", "changeme"]).await.err().unwrap();
+        assert!(err.to_string().contains("placeholder"), "{}", err);
+        
+        let stats = connect(&["--anonymous"]).await.unwrap().execute(1).await.unwrap();
+        assert_eq!((stats.operations, stats.errors), (1, 0));
+        assert_eq!(server.store.object_count(BUCKET), 1);
+    }
+    
+    #[tokio::test]
+    async fn stop_conditions_that_cannot_be_reached_are_rejected() {
+        let server = MockServer::start("127.0.0.1:0").await.unwrap();
+        for zero in ["--ops", "--total-bytes", "--duration-secs"] {
+            let args = ["get", "--endpoint", &server.endpoint, "--bucket", BUCKET, zero, "0"];
+            assert!(Cli::try_parse_from(std::iter::once("s3-load-gen").chain(args)).is_err(), "{}", zero);
+        }
+        
+        // Empty objects plan no bytes, so --total-bytes is rejected instead of running forever
+        PutWorkload::new(put_args(&server, &["--ops", "2", "--object-size", "0"])).await.unwrap().execute(2).await.unwrap();
+        let err = GetWorkload::prepare(get_args(&server, &["--total-bytes", "100"])).await.err().unwrap();
+        assert!(err.to_string().contains("empty"), "{}", err);
+        let put = PutWorkload::new(put_args(&server, &["--total-bytes", "100", "--object-size", "0"])).await.unwrap();
+        let err = tokio::time::timeout(Duration::from_secs(10), put.execute(2)).await.unwrap().err().unwrap();
+        assert!(err.to_string().contains("--total-bytes"), "{}", err);
+    }
+    
+    #[tokio::test]
+    async fn empty_measurement_window_is_rejected_up_front() {
+        let server = MockServer::start("127.0.0.1:0").await.unwrap();
+        let put = PutWorkload::new(put_args(&server, &["--duration-secs", "2", "--warmup", "1s", "--cooldown", "1s"])).await.unwrap();
+        let started = Instant::now();
+        let err = put.execute(2).await.err().unwrap();
+        assert!(err.to_string().contains("Measurement window is empty"), "{}", err);
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert_eq!(server.store.object_count(BUCKET), 0);
+        
+        let put = PutWorkload::new(put_args(&server, &["--duration-secs", "1", "--warmup", "100ms", "--object-size", "10"])).await.unwrap();
+        assert!(put.execute(2).await.unwrap().window.is_some());
+    }
+    
+    #[tokio::test]
+    async fn list_pages_honor_the_window_and_reset_per_run() {
+        let server = MockServer::start("127.0.0.1:0").await.unwrap();
+        PutWorkload::new(put_args(&server, &["--ops", "5", "--object-size", "10"])).await.unwrap().execute(5).await.unwrap();
+        let list = ListWorkload::new(list_args(&server, &["--duration-secs", "1", "--warmup", "500ms"])).await.unwrap();
+        
+        // Runs repeat as at the levels of a sweep; each lists one page per operation
+        for _ in 0..2 {
+            let stats = list.execute(1).await.unwrap();
+            assert!(stats.excluded > 0);
+            let pages = list.lister.page_latency(stats.window).len();
+            // Pages and operations are timed from starts a few microseconds apart
+            assert!(pages.abs_diff(stats.operations) <= 1, "{} pages, {} operations", pages, stats.operations);
+        }
+    }
+    
+    #[tokio::test]
+    async fn get_without_objects_fails() {
+        let server = MockServer::start("127.0.0.1:0").await.unwrap();
+        let err = GetWorkload::prepare(get_args(&server, &["--ops", "1"])).await.err().unwrap();
+        assert!(err.to_string().contains("No objects found"));
+    }
 }
-
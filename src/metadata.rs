@@ -0,0 +1,322 @@
+//! Object metadata and tags. PUT can attach user metadata headers, Content-Type,
+//! Cache-Control and tags to every object it writes, and the `tagging` command
+//! measures the metadata operations on existing objects: HeadObject (reading
+//! the metadata back), GetObjectTagging and PutObjectTagging. For small objects
+//! these, rather than the data, dominate the cost of a request.
+
+use super::{connect, discover_objects, drive, ConnectionArgs, ObjectInfo, OpUnit, RunArgs, Stats};
+use crate::endpoints::EndpointPool;
+use anyhow::{Context, Result};
+use aws_sdk_s3::operation::create_multipart_upload::builders::CreateMultipartUploadFluentBuilder;
+use aws_sdk_s3::operation::put_object::builders::PutObjectFluentBuilder;
+use aws_sdk_s3::types::{Tag, Tagging};
+use aws_sdk_s3::Client as S3Client;
+use clap::{Args, ValueEnum};
+use rand::distributions::{Alphanumeric, DistString};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// S3's limits on an object's tags and user metadata
+const MAX_TAGS: usize = 10;
+const MAX_TAG_VALUE_SIZE: usize = 256;
+const MAX_METADATA_SIZE: usize = 2048;
+
+/// Tags set on objects, by PUT or PutObjectTagging
+#[derive(Args, Clone, Debug)]
+pub(crate) struct TagArgs {
+    /// Tags per object (S3 allows at most 10)
+    #[arg(long, default_value = "0")]
+    tags: usize,
+    /// Size of each tag value in bytes (S3 allows at most 256)
+    #[arg(long, default_value = "16")]
+    tag_value_size: usize,
+}
+
+impl TagArgs {
+    pub(crate) fn validate(&self) -> Result<()> {
+        if self.tags > MAX_TAGS {
+            anyhow::bail!("--tags must be at most {}", MAX_TAGS);
+        }
+        if self.tag_value_size > MAX_TAG_VALUE_SIZE {
+            anyhow::bail!("--tag-value-size must be at most {}", MAX_TAG_VALUE_SIZE);
+        }
+        Ok(())
+    }
+}
+
+/// Metadata PUT attaches to every object
+#[derive(Args, Clone, Debug)]
+pub(crate) struct MetadataArgs {
+    /// User metadata headers (x-amz-meta-*) per object
+    #[arg(long, default_value = "0")]
+    metadata_headers: usize,
+    /// Size of each user metadata value in bytes; S3 limits names and values to 2 KB in total
+    #[arg(long, default_value = "32")]
+    metadata_value_size: usize,
+    /// Content-Type of the objects
+    #[arg(long)]
+    content_type: Option<String>,
+    /// Cache-Control of the objects
+    #[arg(long)]
+    cache_control: Option<String>,
+    #[command(flatten)]
+    tags: TagArgs,
+}
+
+impl MetadataArgs {
+    pub(crate) fn validate(&self) -> Result<()> {
+        let size: usize = (0..self.metadata_headers).map(|i| metadata_name(i).len() + self.metadata_value_size).sum();
+        if size > MAX_METADATA_SIZE {
+            anyhow::bail!(
+                "--metadata-headers {} of --metadata-value-size {} make {} bytes of user metadata; S3 allows {}",
+                self.metadata_headers,
+                self.metadata_value_size,
+                size,
+                MAX_METADATA_SIZE
+            );
+        }
+        self.tags.validate()
+    }
+}
+
+impl fmt::Display for MetadataArgs {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = Vec::new();
+        if self.metadata_headers > 0 {
+            parts.push(format!("{} user metadata headers of {} bytes", self.metadata_headers, self.metadata_value_size));
+        }
+        if let Some(content_type) = &self.content_type {
+            parts.push(format!("Content-Type {}", content_type));
+        }
+        if let Some(cache_control) = &self.cache_control {
+            parts.push(format!("Cache-Control {}", cache_control));
+        }
+        if self.tags.tags > 0 {
+            parts.push(format!("{} tags of {} bytes", self.tags.tags, self.tags.tag_value_size));
+        }
+        if parts.is_empty() {
+            write!(f, "none")
+        } else {
+            write!(f, "{}", parts.join(", "))
+        }
+    }
+}
+
+/// Metadata and tags for one run, generated once; cheap to clone into operations
+#[derive(Clone, Debug, Default)]
+pub(crate) struct ObjectAttributes {
+    metadata: Option<HashMap<String, String>>,
+    content_type: Option<String>,
+    cache_control: Option<String>,
+    tags: Vec<(String, String)>,
+}
+
+impl ObjectAttributes {
+    pub(crate) fn new(args: &MetadataArgs) -> Self {
+        let mut rng = rand::thread_rng();
+        let metadata = (0..args.metadata_headers)
+            .map(|i| (metadata_name(i), Alphanumeric.sample_string(&mut rng, args.metadata_value_size)))
+            .collect::<HashMap<_, _>>();
+        ObjectAttributes {
+            metadata: (!metadata.is_empty()).then_some(metadata),
+            content_type: args.content_type.clone(),
+            cache_control: args.cache_control.clone(),
+            tags: generate_tags(&args.tags),
+        }
+    }
+
+    pub(crate) fn apply_put(&self, request: PutObjectFluentBuilder) -> PutObjectFluentBuilder {
+        request
+            .set_metadata(self.metadata.clone())
+            .set_content_type(self.content_type.clone())
+            .set_cache_control(self.cache_control.clone())
+            .set_tagging(self.tagging_header())
+    }
+
+    pub(crate) fn apply_create(&self, request: CreateMultipartUploadFluentBuilder) -> CreateMultipartUploadFluentBuilder {
+        request
+            .set_metadata(self.metadata.clone())
+            .set_content_type(self.content_type.clone())
+            .set_cache_control(self.cache_control.clone())
+            .set_tagging(self.tagging_header())
+    }
+
+    /// Tags as sent in `x-amz-tagging`; names and values are alphanumeric, so nothing needs encoding
+    fn tagging_header(&self) -> Option<String> {
+        if self.tags.is_empty() {
+            return None;
+        }
+        Some(self.tags.iter().map(|(key, value)| format!("{}={}", key, value)).collect::<Vec<_>>().join("&"))
+    }
+}
+
+fn metadata_name(index: usize) -> String {
+    format!("field-{:02}", index)
+}
+
+fn generate_tags(args: &TagArgs) -> Vec<(String, String)> {
+    let mut rng = rand::thread_rng();
+    (0..args.tags)
+        .map(|i| (format!("tag-{:02}", i), Alphanumeric.sample_string(&mut rng, args.tag_value_size)))
+        .collect()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub(crate) enum TaggingOperation {
+    /// HeadObject, which returns the user metadata
+    Head,
+    /// GetObjectTagging
+    GetTagging,
+    /// PutObjectTagging, replacing the tag set with --tags generated tags
+    PutTagging,
+}
+
+#[derive(Args)]
+pub(crate) struct TaggingArgs {
+    #[command(flatten)]
+    conn: ConnectionArgs,
+    #[command(flatten)]
+    run: RunArgs,
+    #[arg(long, default_value = "10")]
+    concurrent: usize,
+    /// Prefix of the objects operated on
+    #[arg(long, default_value = "test-object/")]
+    prefix: String,
+    /// Metadata operation every request performs
+    #[arg(long, value_enum, default_value = "get-tagging")]
+    operation: TaggingOperation,
+    #[command(flatten)]
+    tags: TagArgs,
+}
+
+/// Metadata operation benchmark with the objects discovered up front
+pub(crate) struct TaggingWorkload {
+    endpoints: Arc<EndpointPool>,
+    bucket: String,
+    run: RunArgs,
+    operation: TaggingOperation,
+    tags: Vec<(String, String)>,
+    objects: Vec<ObjectInfo>,
+}
+
+impl TaggingWorkload {
+    pub(crate) async fn prepare(args: TaggingArgs) -> Result<Self> {
+        if args.operation == TaggingOperation::PutTagging && args.tags.tags == 0 {
+            anyhow::bail!("--operation put-tagging needs --tags");
+        }
+        args.tags.validate()?;
+        // The size of what head and get-tagging read is unknown up front, so they plan no bytes
+        if args.operation != TaggingOperation::PutTagging && args.run.total_bytes.is_some() {
+            anyhow::bail!("--total-bytes is not supported for --operation head or get-tagging; use --ops or --duration-secs");
+        }
+        let endpoints = connect(&args.conn).await?;
+        let bucket = args.conn.bucket;
+
+        println!("Listing objects with prefix '{}'...", args.prefix);
+        let objects = discover_objects(endpoints.primary(), &bucket, &args.prefix).await?;
+        if objects.is_empty() {
+            anyhow::bail!("No objects found with prefix '{}'. Please run PUT benchmark first.", args.prefix);
+        }
+        println!("Found {} objects", objects.len());
+
+        Ok(TaggingWorkload {
+            endpoints,
+            bucket,
+            run: args.run,
+            operation: args.operation,
+            tags: generate_tags(&args.tags),
+            objects,
+        })
+    }
+
+    fn label(&self) -> &'static str {
+        match self.operation {
+            TaggingOperation::Head => "HEAD",
+            TaggingOperation::GetTagging => "GET-TAGGING",
+            TaggingOperation::PutTagging => "PUT-TAGGING",
+        }
+    }
+
+    pub(crate) fn print_config(&self) {
+        println!("Operation: {}", self.label());
+        if self.operation == TaggingOperation::PutTagging {
+            println!("Tags per request: {}", self.tags.len());
+        }
+    }
+
+    /// Each operation counts the metadata or tag bytes it read or wrote, names included
+    pub(crate) async fn execute(&self, concurrent: usize) -> Result<Stats> {
+        let label = self.label();
+        let tag_bytes = self.tags.iter().map(|(key, value)| key.len() + value.len()).sum::<usize>() as u64;
+
+        drive(label, concurrent, self.run, Duration::from_millis(10), OpUnit::Bytes, &self.endpoints, |operation_count, client| {
+            let bucket = self.bucket.clone();
+            let key = self.objects[operation_count as usize % self.objects.len()].key.clone();
+            let operation = self.operation;
+            let tags = self.tags.clone();
+
+            println!("[BENCH] Spawning {} task {} for key: {}", label, operation_count, key);
+            let op = async move {
+                match operation {
+                    TaggingOperation::Head => head_metadata(&client, &bucket, &key).await,
+                    TaggingOperation::GetTagging => get_tagging(&client, &bucket, &key).await,
+                    TaggingOperation::PutTagging => put_tagging(&client, &bucket, &key, tags).await,
+                }
+            };
+            let planned = if operation == TaggingOperation::PutTagging { tag_bytes } else { 0 };
+            (planned, op)
+        })
+        .await
+    }
+}
+
+async fn head_metadata(client: &S3Client, bucket: &str, key: &str) -> Result<usize> {
+    let resp = client.head_object().bucket(bucket).key(key).send().await.context("Failed to head object")?;
+    Ok(resp.metadata().map_or(0, |metadata| metadata.iter().map(|(name, value)| name.len() + value.len()).sum()))
+}
+
+async fn get_tagging(client: &S3Client, bucket: &str, key: &str) -> Result<usize> {
+    let resp = client.get_object_tagging().bucket(bucket).key(key).send().await.context("Failed to get object tagging")?;
+    Ok(resp.tag_set().iter().map(|tag| tag.key().len() + tag.value().len()).sum())
+}
+
+async fn put_tagging(client: &S3Client, bucket: &str, key: &str, tags: Vec<(String, String)>) -> Result<usize> {
+    let bytes = tags.iter().map(|(key, value)| key.len() + value.len()).sum();
+    let tag_set = tags
+        .into_iter()
+        .map(|(key, value)| Tag::builder().key(key).value(value).build())
+        .collect::<Result<Vec<_>, _>>()?;
+    let tagging = Tagging::builder().set_tag_set(Some(tag_set)).build()?;
+    client
+        .put_object_tagging()
+        .bucket(bucket)
+        .key(key)
+        .tagging(tagging)
+        .send()
+        .await
+        .context("Failed to put object tagging")?;
+    Ok(bytes)
+}
+
+pub(crate) async fn run_tagging_benchmark(args: TaggingArgs) -> Result<()> {
+    let concurrent = args.concurrent;
+
+    println!("Starting TAGGING benchmark...");
+    println!("Endpoint: {}", args.conn.describe_endpoints());
+    println!("Client: {}", args.conn.client);
+    println!("HTTP: {}", args.conn.http);
+    println!("Credentials: {}", args.conn.credentials);
+    println!("Bucket: {}", args.conn.bucket);
+    println!("Stop after: {}", args.run.resolve());
+    println!("Concurrent operations: {}", concurrent);
+
+    let workload = TaggingWorkload::prepare(args).await?;
+    workload.print_config();
+
+    let stats = workload.execute(concurrent).await?;
+    stats.print(workload.label());
+
+    Ok(())
+}
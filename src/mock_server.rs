@@ -0,0 +1,1492 @@
+//! In-memory S3-compatible server for offline testing and for benchmarking the
+//! client itself. Serves HTTP/1.1 and HTTP/2 with prior knowledge (h2c), or
+//! over TLS (optionally requiring client certificates) with ALPN. Path-style
+//! requests, plus virtual-hosted-style ones under `--domain`; no
+//! authentication: signatures are accepted without being checked. Supports
+//! PutObject, multipart uploads, CopyObject and UploadPartCopy, ListParts and
+//! ListMultipartUploads, GetObject with ranges, HeadObject, DeleteObject,
+//! conditional PUTs and GETs, object metadata and tagging, ListObjectsV2 and
+//! ListObjects (v1) with pagination and delimiters, and bucket versioning with
+//! version IDs, delete markers and ListObjectVersions; every bucket implicitly
+//! exists. Faults (latency, SlowDown, connection resets, truncated bodies,
+//! stale reads, stalls) can be injected per operation, see [`faults`].
+
+use anyhow::{Context, Result};
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use crate::transport::read_certs;
+use clap::Args;
+use faults::{Fault, FaultConfig, Faults, Operation};
+use http_body_util::combinators::BoxBody;
+use http_body_util::{BodyExt, Full, StreamBody};
+use hyper::body::{Frame, Incoming};
+use hyper::header::{HeaderMap, HeaderValue, CACHE_CONTROL, CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::service::service_fn;
+use hyper::{Method, Request, Response, StatusCode};
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use hyper_util::server::conn::auto;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::PrivateKeyDer;
+use rustls::server::WebPkiClientVerifier;
+use rustls::{RootCertStore, ServerConfig};
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::io;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::net::TcpListener;
+use tokio::task::JoinHandle;
+use tokio_rustls::TlsAcceptor;
+
+pub(crate) mod faults;
+
+/// Page size used when the client does not send max-keys, as in S3
+const DEFAULT_MAX_KEYS: usize = 1000;
+
+/// S3's limit on the combined size of user metadata names and values
+const MAX_USER_METADATA: usize = 2048;
+
+/// S3's limit on tags per object
+const MAX_TAGS: usize = 10;
+
+#[derive(Args)]
+pub(crate) struct MockServerArgs {
+    /// Address to serve on
+    #[arg(long, default_value = "127.0.0.1:9000")]
+    listen: String,
+    /// YAML file describing faults to inject (latency, SlowDown, resets, truncation, stalls)
+    #[arg(long)]
+    faults: Option<PathBuf>,
+    /// Reject completing multipart uploads with a part other than the last below
+    /// this size (EntityTooSmall), as S3 does below 5 MiB; 0 accepts any size
+    #[arg(long, default_value = "0")]
+    min_part_size: u64,
+    #[command(flatten)]
+    frontend: FrontendArgs,
+}
+
+/// How clients reach the mock server
+#[derive(Args, Default)]
+pub(crate) struct FrontendArgs {
+    /// PEM certificate chain to serve HTTPS with instead of plain HTTP
+    #[arg(long, requires = "tls_key")]
+    tls_cert: Option<PathBuf>,
+    /// PEM private key of --tls-cert
+    #[arg(long, requires = "tls_cert")]
+    tls_key: Option<PathBuf>,
+    /// PEM CA certificates that client certificates must chain to; makes mutual TLS mandatory
+    #[arg(long, requires = "tls_cert")]
+    client_ca: Option<PathBuf>,
+    /// Also serve virtual-hosted-style requests addressed to `<bucket>.<domain>`
+    #[arg(long)]
+    domain: Option<String>,
+}
+
+impl FrontendArgs {
+    fn tls_acceptor(&self) -> Result<Option<TlsAcceptor>> {
+        let (Some(cert), Some(key)) = (&self.tls_cert, &self.tls_key) else {
+            return Ok(None);
+        };
+        let provider = Arc::new(rustls::crypto::aws_lc_rs::default_provider());
+        let builder = ServerConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()
+            .context("Failed to set up TLS")?;
+        let builder = match &self.client_ca {
+            Some(path) => {
+                let mut roots = RootCertStore::empty();
+                for cert in read_certs(path)? {
+                    roots.add(cert).with_context(|| format!("Invalid CA certificate in {}", path.display()))?;
+                }
+                let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider)
+                    .build()
+                    .context("Invalid --client-ca")?;
+                builder.with_client_cert_verifier(verifier)
+            }
+            None => builder.with_no_client_auth(),
+        };
+        let key = PrivateKeyDer::from_pem_file(key)
+            .with_context(|| format!("Failed to read private key from {}", key.display()))?;
+        let mut config = builder
+            .with_single_cert(read_certs(cert)?, key)
+            .context("Invalid --tls-cert or --tls-key")?;
+        config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+        Ok(Some(TlsAcceptor::from(Arc::new(config))))
+    }
+}
+
+#[derive(Clone)]
+struct StoredObject {
+    data: Bytes,
+    etag: String,
+    last_modified: DateTime<Utc>,
+    attributes: Attributes,
+}
+
+/// What an object is created with besides its data: user metadata, Content-Type
+/// and Cache-Control headers returned on GET and HEAD, and tags
+#[derive(Clone, Default)]
+struct Attributes {
+    headers: HeaderMap,
+    tags: BTreeMap<String, String>,
+}
+
+/// One entry of a key's version history; no object means a delete marker
+#[derive(Clone)]
+struct Version {
+    id: String,
+    last_modified: DateTime<Utc>,
+    object: Option<StoredObject>,
+}
+
+struct Upload {
+    bucket: String,
+    key: String,
+    initiated: DateTime<Utc>,
+    parts: BTreeMap<i32, Bytes>,
+    attributes: Attributes,
+}
+
+/// Objects keyed by (bucket, key) so listings come out in key order. `objects`
+/// holds the current object of every key; in buckets with versioning enabled
+/// `versions` also keeps each key's history, oldest first. When both are
+/// needed, `objects` is locked first.
+#[derive(Default)]
+pub(crate) struct Store {
+    objects: Mutex<BTreeMap<(String, String), StoredObject>>,
+    versions: Mutex<BTreeMap<(String, String), Vec<Version>>>,
+    /// What each key held before its current object replaced it, `None` if it
+    /// was absent; served by injected stale reads
+    previous: Mutex<HashMap<(String, String), Option<StoredObject>>>,
+    /// Versioning status by bucket, `Enabled` or `Suspended`
+    versioning: Mutex<HashMap<String, String>>,
+    uploads: Mutex<HashMap<String, Upload>>,
+    next_upload_id: AtomicU64,
+    next_version_id: AtomicU64,
+    /// Smallest part other than the last that completing an upload accepts
+    min_part_size: AtomicU64,
+    payload_signing: Mutex<BTreeMap<String, u64>>,
+}
+
+impl Store {
+    /// Number of objects currently stored in `bucket`
+    #[cfg(test)]
+    pub(crate) fn object_count(&self, bucket: &str) -> usize {
+        self.objects.lock().unwrap().keys().filter(|(b, _)| b == bucket).count()
+    }
+
+    fn total_objects(&self) -> usize {
+        self.objects.lock().unwrap().len()
+    }
+
+    /// Number of multipart uploads that were created but neither completed nor aborted
+    pub(crate) fn pending_uploads(&self) -> usize {
+        self.uploads.lock().unwrap().len()
+    }
+
+    /// Number of versions and delete markers kept for keys in `bucket`
+    #[cfg(test)]
+    pub(crate) fn version_count(&self, bucket: &str) -> usize {
+        let versions = self.versions.lock().unwrap();
+        versions.iter().filter(|((b, _), _)| b == bucket).map(|(_, stack)| stack.len()).sum()
+    }
+
+    fn versioning_enabled(&self, bucket: &str) -> bool {
+        self.versioning.lock().unwrap().get(bucket).is_some_and(|status| status == "Enabled")
+    }
+
+    fn new_version_id(&self) -> String {
+        format!("mock-version-{}", self.next_version_id.fetch_add(1, Ordering::Relaxed))
+    }
+
+    /// Make `object` the current object of the key, returning its version ID
+    /// if the bucket has versioning enabled
+    fn store_object(&self, bucket: &str, key: &str, object: StoredObject) -> Option<String> {
+        let mut objects = self.objects.lock().unwrap();
+        self.replace_current(&mut objects, bucket, key, object)
+    }
+
+    /// Like [`Store::store_object`], but only if the key's current object meets the
+    /// request's write conditions; checked and written under one lock, so of
+    /// concurrent conditional writers exactly one can win
+    fn store_object_if(&self, bucket: &str, key: &str, object: StoredObject, headers: &HeaderMap) -> Result<Option<String>, Box<MockResponse>> {
+        let mut objects = self.objects.lock().unwrap();
+        write_preconditions(headers, objects.get(&(bucket.to_string(), key.to_string())))?;
+        Ok(self.replace_current(&mut objects, bucket, key, object))
+    }
+
+    fn replace_current(&self, objects: &mut BTreeMap<(String, String), StoredObject>, bucket: &str, key: &str, object: StoredObject) -> Option<String> {
+        let version_id = self.versioning_enabled(bucket).then(|| self.new_version_id());
+        let name = (bucket.to_string(), key.to_string());
+        if let Some(id) = &version_id {
+            self.versions.lock().unwrap().entry(name.clone()).or_default().push(Version {
+                id: id.clone(),
+                last_modified: object.last_modified,
+                object: Some(object.clone()),
+            });
+        }
+        self.set_current(objects, &name, Some(object));
+        version_id
+    }
+
+    /// Make `object` the current object of the key, or remove it, remembering what it replaced
+    fn set_current(&self, objects: &mut BTreeMap<(String, String), StoredObject>, name: &(String, String), object: Option<StoredObject>) {
+        let replaced = match object {
+            Some(object) => objects.insert(name.clone(), object),
+            None => objects.remove(name),
+        };
+        self.previous.lock().unwrap().insert(name.clone(), replaced);
+    }
+
+    /// The current object of the key, or the requested version of it, with its version ID if it has one
+    fn find_object(&self, bucket: &str, key: &str, version_id: Option<&str>) -> Result<(StoredObject, Option<String>), Box<MockResponse>> {
+        let name = (bucket.to_string(), key.to_string());
+        let objects = self.objects.lock().unwrap();
+        let versions = self.versions.lock().unwrap();
+        let stack = versions.get(&name);
+        let Some(version_id) = version_id else {
+            let Some(object) = objects.get(&name) else {
+                return Err(Box::new(error_response(StatusCode::NOT_FOUND, "NoSuchKey", "The specified key does not exist.")));
+            };
+            let current = stack.and_then(|stack| stack.last()).map(|version| version.id.clone());
+            return Ok((object.clone(), current));
+        };
+        match stack.and_then(|stack| stack.iter().find(|version| version.id == version_id)) {
+            Some(Version { object: Some(object), .. }) => Ok((object.clone(), Some(version_id.to_string()))),
+            Some(_) => {
+                let response = error_response(StatusCode::METHOD_NOT_ALLOWED, "MethodNotAllowed", "The specified method is not allowed against a delete marker.");
+                Err(Box::new(with_header(response, "x-amz-delete-marker", "true")))
+            }
+            None => Err(Box::new(error_response(StatusCode::NOT_FOUND, "NoSuchVersion", "The specified version does not exist."))),
+        }
+    }
+
+    /// The object the key held before its current one replaced it, for a stale
+    /// read; the current object if the key was never written
+    fn find_stale_object(&self, bucket: &str, key: &str) -> Result<(StoredObject, Option<String>), Box<MockResponse>> {
+        let previous = self.previous.lock().unwrap().get(&(bucket.to_string(), key.to_string())).cloned();
+        match previous {
+            Some(Some(object)) => Ok((object, None)),
+            Some(None) => Err(Box::new(error_response(StatusCode::NOT_FOUND, "NoSuchKey", "The specified key does not exist."))),
+            None => self.find_object(bucket, key, None),
+        }
+    }
+
+    pub(crate) fn set_min_part_size(&self, bytes: u64) {
+        self.min_part_size.store(bytes, Ordering::Relaxed);
+    }
+
+    /// PUT requests by payload signing scheme, see [`payload_signing`]
+    pub(crate) fn payload_signing(&self) -> BTreeMap<String, u64> {
+        self.payload_signing.lock().unwrap().clone()
+    }
+}
+
+/// A running mock server; stops when dropped
+pub(crate) struct MockServer {
+    pub(crate) endpoint: String,
+    pub(crate) store: Arc<Store>,
+    task: JoinHandle<()>,
+}
+
+impl MockServer {
+    /// Bind `addr` (use port 0 for an ephemeral port) and serve in the background
+    #[cfg(test)]
+    pub(crate) async fn start(addr: &str) -> Result<Self> {
+        Self::start_with_faults(addr, FaultConfig::default()).await
+    }
+
+    /// Like [`MockServer::start`], injecting the faults described by `faults`
+    #[cfg(test)]
+    pub(crate) async fn start_with_faults(addr: &str, faults: FaultConfig) -> Result<Self> {
+        Self::start_with_frontend(addr, faults, &FrontendArgs::default()).await
+    }
+
+    /// Serve as the `mock-server` command line describes
+    pub(crate) async fn launch(args: &MockServerArgs) -> Result<Self> {
+        let faults = match &args.faults {
+            Some(path) => FaultConfig::load(path)?,
+            None => FaultConfig::default(),
+        };
+        let server = Self::start_with_frontend(&args.listen, faults, &args.frontend).await?;
+        server.store.set_min_part_size(args.min_part_size);
+        Ok(server)
+    }
+
+    async fn start_with_frontend(addr: &str, faults: FaultConfig, frontend: &FrontendArgs) -> Result<Self> {
+        let tls = frontend.tls_acceptor()?;
+        let listener = TcpListener::bind(addr)
+            .await
+            .with_context(|| format!("Failed to bind mock server on {}", addr))?;
+        let local_addr = listener.local_addr()?;
+        let scheme = if tls.is_some() { "https" } else { "http" };
+        let store = Arc::new(Store::default());
+        let domain = frontend.domain.as_deref().map(Arc::from);
+        let task = tokio::spawn(serve(listener, tls, store.clone(), Arc::new(Faults::new(faults)), domain));
+        Ok(MockServer {
+            endpoint: format!("{}://{}", scheme, local_addr),
+            store,
+            task,
+        })
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+pub(crate) async fn run_mock_server(args: MockServerArgs) -> Result<()> {
+    let server = MockServer::launch(&args).await?;
+    println!("Mock S3 server listening on {}", server.endpoint);
+    if let Some(path) = &args.faults {
+        println!("Injecting faults from {}", path.display());
+    }
+    if args.frontend.client_ca.is_some() {
+        println!("Requiring client certificates issued by the --client-ca authorities");
+    }
+    if let Some(domain) = &args.frontend.domain {
+        println!("Serving virtual-hosted-style requests to <bucket>.{}", domain);
+    }
+    println!("Any bucket name is accepted and credentials are not checked. Press Ctrl-C to stop.");
+    tokio::signal::ctrl_c().await?;
+    println!(
+        "Stopping mock server ({} objects stored, {} multipart uploads pending)",
+        server.store.total_objects(),
+        server.store.pending_uploads()
+    );
+    for (scheme, requests) in server.store.payload_signing() {
+        println!("  {} PUT requests with {}", requests, scheme);
+    }
+    Ok(())
+}
+
+async fn serve(listener: TcpListener, tls: Option<TlsAcceptor>, store: Arc<Store>, faults: Arc<Faults>, domain: Option<Arc<str>>) {
+    loop {
+        let (stream, peer) = match listener.accept().await {
+            Ok(conn) => conn,
+            Err(e) => {
+                println!("[MOCK] Accept failed: {:?}", e);
+                continue;
+            }
+        };
+        let tls = tls.clone();
+        let store = store.clone();
+        let faults = faults.clone();
+        let domain = domain.clone();
+        tokio::spawn(async move {
+            let service = service_fn(move |req| handle(store.clone(), faults.clone(), domain.clone(), req));
+            let builder = auto::Builder::new(TokioExecutor::new());
+            let result = match tls {
+                Some(tls) => match tls.accept(stream).await {
+                    Ok(stream) => builder.serve_connection(TokioIo::new(stream), service).await,
+                    Err(e) => {
+                        println!("[MOCK] TLS handshake with {} failed: {}", peer, e);
+                        return;
+                    }
+                },
+                None => builder.serve_connection(TokioIo::new(stream), service).await,
+            };
+            if let Err(e) = result {
+                println!("[MOCK] Connection from {} ended with error: {:?}", peer, e);
+            }
+        });
+    }
+}
+
+/// Responses are built fully buffered; faults may turn the body into a failing stream on the way out
+type MockResponse = Response<Bytes>;
+type ServiceBody = BoxBody<Bytes, io::Error>;
+
+async fn handle(
+    store: Arc<Store>,
+    faults: Arc<Faults>,
+    domain: Option<Arc<str>>,
+    req: Request<Incoming>,
+) -> Result<Response<ServiceBody>, io::Error> {
+    let (parts, body) = req.into_parts();
+    if parts.method == Method::PUT {
+        *store.payload_signing.lock().unwrap().entry(payload_signing(&parts.headers)).or_default() += 1;
+    }
+    let body = match body.collect().await {
+        Ok(collected) => collected.to_bytes(),
+        Err(e) => return Ok(finish(error_response(StatusCode::BAD_REQUEST, "IncompleteBody", &e.to_string()))),
+    };
+    let body = if is_aws_chunked(&parts.headers) {
+        match decode_aws_chunked(&body) {
+            Some(decoded) => decoded,
+            None => {
+                return Ok(finish(error_response(StatusCode::BAD_REQUEST, "InvalidRequest", "Malformed aws-chunked body")));
+            }
+        }
+    } else {
+        body
+    };
+
+    let path = parts.uri.path().trim_start_matches('/');
+    let (bucket, key) = match (virtual_host_bucket(&parts, domain.as_deref()), path.split_once('/')) {
+        (Some(bucket), _) => (bucket, percent_decode(path)),
+        (None, Some((bucket, key))) => (percent_decode(bucket), percent_decode(key)),
+        (None, None) => (percent_decode(path), String::new()),
+    };
+    let query = parse_query(parts.uri.query().unwrap_or(""));
+    let copy_source = parts.headers.get("x-amz-copy-source").and_then(|v| v.to_str().ok());
+    let copy_range = parts.headers.get("x-amz-copy-source-range").and_then(|v| v.to_str().ok());
+
+    let Some(operation) = classify(&parts.method, &bucket, &key, &query, copy_source.is_some()) else {
+        return Ok(finish(error_response(
+            StatusCode::NOT_IMPLEMENTED,
+            "NotImplemented",
+            &format!("{} {} is not supported by the mock server", parts.method, parts.uri),
+        )));
+    };
+
+    let _slots = faults.admit(operation).await;
+    let decision = faults.decide(operation);
+    if !decision.delay.is_zero() {
+        tokio::time::sleep(decision.delay).await;
+    }
+    match decision.fault {
+        Some(Fault::SlowDown) => {
+            return Ok(finish(error_response(StatusCode::SERVICE_UNAVAILABLE, "SlowDown", "Please reduce your request rate.")));
+        }
+        // Only object downloads have a body worth cutting; everything else is reset before it takes effect
+        Some(Fault::Reset) if operation != Operation::GetObject => return Err(injected_reset()),
+        _ => {}
+    }
+
+    let response = match operation {
+        Operation::ListObjectsV2 => list_objects_v2(&store, &bucket, &query),
+        Operation::ListObjects => list_objects_v1(&store, &bucket, &query),
+        Operation::ListObjectVersions => list_object_versions(&store, &bucket, &query),
+        Operation::CreateBucket | Operation::HeadBucket => empty_response(StatusCode::OK),
+        Operation::PutBucketVersioning => put_bucket_versioning(&store, &bucket, &body),
+        Operation::GetBucketVersioning => get_bucket_versioning(&store, &bucket),
+        Operation::CreateMultipartUpload => create_multipart_upload(&store, &bucket, &key, &parts.headers),
+        Operation::CompleteMultipartUpload => complete_multipart_upload(&store, &bucket, &key, &query["uploadId"], &body),
+        Operation::UploadPart => upload_part(&store, &query, body),
+        Operation::UploadPartCopy => upload_part_copy(&store, &query, copy_source.unwrap_or_default(), copy_range),
+        Operation::PutObject => put_object(&store, &bucket, &key, &parts.headers, body),
+        Operation::CopyObject => copy_object(&store, &bucket, &key, copy_source.unwrap_or_default()),
+        Operation::GetObject | Operation::HeadObject => {
+            let stale = decision.fault == Some(Fault::StaleRead);
+            get_object(&store, &bucket, &key, query.get("versionId"), &parts.headers, operation == Operation::HeadObject, stale)
+        }
+        Operation::AbortMultipartUpload => abort_multipart_upload(&store, &query["uploadId"]),
+        Operation::ListParts => list_parts(&store, &bucket, &key, &query),
+        Operation::ListMultipartUploads => list_multipart_uploads(&store, &bucket, &query),
+        Operation::DeleteObject => delete_object(&store, &bucket, &key, query.get("versionId")),
+        Operation::GetObjectTagging => get_object_tagging(&store, &bucket, &key, query.get("versionId")),
+        Operation::PutObjectTagging => put_object_tagging(&store, &bucket, &key, &body),
+    };
+
+    match decision.fault {
+        Some(Fault::Reset) => reset_mid_body(response),
+        Some(Fault::Truncate) => Ok(finish(truncate_body(response))),
+        _ => Ok(finish(response)),
+    }
+}
+
+/// Map a request onto the S3 operation it invokes, or `None` if the mock does not support it.
+/// `copy` is whether the request names a copy source.
+fn classify(method: &Method, bucket: &str, key: &str, query: &HashMap<String, String>, copy: bool) -> Option<Operation> {
+    if bucket.is_empty() {
+        return None;
+    }
+    let operation = match (method, key.is_empty()) {
+        (&Method::GET, true) if query.get("list-type").map(String::as_str) == Some("2") => Operation::ListObjectsV2,
+        (&Method::GET, true) if query.contains_key("uploads") => Operation::ListMultipartUploads,
+        (&Method::GET, true) if query.contains_key("versions") => Operation::ListObjectVersions,
+        (&Method::GET, true) if query.contains_key("versioning") => Operation::GetBucketVersioning,
+        (&Method::GET, true) => Operation::ListObjects,
+        (&Method::PUT, true) if query.contains_key("versioning") => Operation::PutBucketVersioning,
+        (&Method::PUT, true) => Operation::CreateBucket,
+        (&Method::HEAD, true) => Operation::HeadBucket,
+        (&Method::POST, false) if query.contains_key("uploads") => Operation::CreateMultipartUpload,
+        (&Method::POST, false) if query.contains_key("uploadId") => Operation::CompleteMultipartUpload,
+        (&Method::PUT, false) if query.contains_key("tagging") => Operation::PutObjectTagging,
+        (&Method::PUT, false) if copy && query.contains_key("uploadId") => Operation::UploadPartCopy,
+        (&Method::PUT, false) if copy => Operation::CopyObject,
+        (&Method::PUT, false) if query.contains_key("uploadId") => Operation::UploadPart,
+        (&Method::PUT, false) => Operation::PutObject,
+        (&Method::GET, false) if query.contains_key("uploadId") => Operation::ListParts,
+        (&Method::GET, false) if query.contains_key("tagging") => Operation::GetObjectTagging,
+        (&Method::GET, false) => Operation::GetObject,
+        (&Method::HEAD, false) => Operation::HeadObject,
+        (&Method::DELETE, false) if query.contains_key("uploadId") => Operation::AbortMultipartUpload,
+        (&Method::DELETE, false) => Operation::DeleteObject,
+        _ => return None,
+    };
+    Some(operation)
+}
+
+/// The bucket named by a `<bucket>.<domain>` host, from the HTTP/2 authority or the Host header
+fn virtual_host_bucket(parts: &hyper::http::request::Parts, domain: Option<&str>) -> Option<String> {
+    let domain = domain?;
+    let host = match parts.uri.host() {
+        Some(host) => host,
+        None => parts.headers.get(hyper::header::HOST)?.to_str().ok()?,
+    };
+    let host = host.rsplit_once(':').map_or(host, |(host, _)| host).to_ascii_lowercase();
+    let bucket = host.strip_suffix(&domain.to_ascii_lowercase())?.strip_suffix('.')?;
+    (!bucket.is_empty()).then(|| bucket.to_string())
+}
+
+fn finish(response: MockResponse) -> Response<ServiceBody> {
+    response.map(|body| Full::new(body).map_err(|never| match never {}).boxed())
+}
+
+fn injected_reset() -> io::Error {
+    io::Error::new(io::ErrorKind::ConnectionReset, "injected connection reset")
+}
+
+/// Advertise the full Content-Length but fail the body stream halfway, which
+/// makes hyper abort the connection mid-transfer
+fn reset_mid_body(response: MockResponse) -> Result<Response<ServiceBody>, io::Error> {
+    let (parts, body) = response.into_parts();
+    if body.is_empty() {
+        return Err(injected_reset());
+    }
+    let half = body.slice(..body.len() / 2);
+    let frames = futures::stream::iter([Ok(Frame::data(half)), Err(injected_reset())]);
+    Ok(Response::from_parts(parts, StreamBody::new(frames).boxed()))
+}
+
+/// Keep only the first half of the body and advertise that length, as a proxy that
+/// cuts responses short would; headers such as Content-Range still describe the full
+/// payload, and only a client that knows the expected size notices
+fn truncate_body(response: MockResponse) -> MockResponse {
+    let (mut parts, body) = response.into_parts();
+    let half = body.slice(..body.len() / 2);
+    parts.headers.insert(CONTENT_LENGTH, HeaderValue::from(half.len()));
+    Response::from_parts(parts, half)
+}
+
+/// Add `x-amz-version-id` if the request created or named a version
+fn with_version(response: MockResponse, version_id: Option<&str>) -> MockResponse {
+    match version_id {
+        Some(id) => with_header(response, "x-amz-version-id", id),
+        None => response,
+    }
+}
+
+/// Attributes a new object is created with, taken from the headers of its
+/// PutObject or CreateMultipartUpload request
+fn request_attributes(headers: &HeaderMap) -> Result<Attributes, Box<MockResponse>> {
+    let mut attributes = Attributes::default();
+    let mut metadata_size = 0;
+    for (name, value) in headers {
+        if let Some(metadata_name) = name.as_str().strip_prefix("x-amz-meta-") {
+            metadata_size += metadata_name.len() + value.len();
+        } else if name != CONTENT_TYPE && name != CACHE_CONTROL {
+            continue;
+        }
+        attributes.headers.append(name.clone(), value.clone());
+    }
+    if metadata_size > MAX_USER_METADATA {
+        return Err(Box::new(error_response(
+            StatusCode::BAD_REQUEST,
+            "MetadataTooLarge",
+            "Your metadata headers exceed the maximum allowed metadata size.",
+        )));
+    }
+    if let Some(tagging) = headers.get("x-amz-tagging").and_then(|v| v.to_str().ok()) {
+        attributes.tags = parse_query(tagging).into_iter().collect();
+    }
+    check_tags(&attributes.tags)?;
+    Ok(attributes)
+}
+
+fn check_tags(tags: &BTreeMap<String, String>) -> Result<(), Box<MockResponse>> {
+    if tags.len() > MAX_TAGS {
+        return Err(Box::new(error_response(StatusCode::BAD_REQUEST, "BadRequest", "Object tags cannot be greater than 10")));
+    }
+    if tags.iter().any(|(key, value)| key.is_empty() || key.len() > 128 || value.len() > 256) {
+        return Err(Box::new(error_response(StatusCode::BAD_REQUEST, "InvalidTag", "The TagKey or TagValue you have provided is invalid")));
+    }
+    Ok(())
+}
+
+fn put_object(store: &Store, bucket: &str, key: &str, headers: &HeaderMap, body: Bytes) -> MockResponse {
+    let attributes = match request_attributes(headers) {
+        Ok(attributes) => attributes,
+        Err(response) => return *response,
+    };
+    let etag = etag_of(&body);
+    let object = StoredObject {
+        data: body,
+        etag: etag.clone(),
+        last_modified: Utc::now(),
+        attributes,
+    };
+    let version_id = match store.store_object_if(bucket, key, object, headers) {
+        Ok(version_id) => version_id,
+        Err(response) => return *response,
+    };
+    with_version(with_header(empty_response(StatusCode::OK), "etag", &etag), version_id.as_deref())
+}
+
+/// The object named by `x-amz-copy-source` (`[/]bucket/key[?versionId=...]`, URL-encoded)
+fn read_copy_source(store: &Store, source: &str) -> Result<StoredObject, Box<MockResponse>> {
+    let (path, query) = source.split_once('?').unwrap_or((source, ""));
+    let Some((bucket, key)) = path.trim_start_matches('/').split_once('/') else {
+        return Err(Box::new(error_response(StatusCode::BAD_REQUEST, "InvalidArgument", "Invalid copy source")));
+    };
+    let version_id = parse_query(query).remove("versionId");
+    let (object, _) = store.find_object(&percent_decode(bucket), &percent_decode(key), version_id.as_deref())?;
+    Ok(object)
+}
+
+/// Whether an `If-Match` or `If-None-Match` value names `etag`; `*` matches any object
+fn etag_matches(condition: &str, etag: &str) -> bool {
+    condition
+        .split(',')
+        .map(str::trim)
+        .any(|candidate| candidate == "*" || candidate.trim_matches('"') == etag.trim_matches('"'))
+}
+
+fn header_str<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers.get(name).and_then(|value| value.to_str().ok())
+}
+
+/// Check a conditional write (`If-None-Match: *` to create only, `If-Match` to
+/// replace only a given ETag) against the key's current object
+fn write_preconditions(headers: &HeaderMap, current: Option<&StoredObject>) -> Result<(), Box<MockResponse>> {
+    if let Some(condition) = header_str(headers, "if-none-match") {
+        if current.is_some_and(|object| etag_matches(condition, &object.etag)) {
+            return Err(Box::new(precondition_failed()));
+        }
+    }
+    if let Some(condition) = header_str(headers, "if-match") {
+        match current {
+            None => return Err(Box::new(error_response(StatusCode::NOT_FOUND, "NoSuchKey", "The specified key does not exist."))),
+            Some(object) if !etag_matches(condition, &object.etag) => return Err(Box::new(precondition_failed())),
+            Some(_) => {}
+        }
+    }
+    Ok(())
+}
+
+/// Response to a conditional GET or HEAD whose conditions `object` does not meet.
+/// As in RFC 9110, If-Unmodified-Since is ignored when If-Match is present and
+/// If-Modified-Since when If-None-Match is; dates have one-second resolution.
+fn read_preconditions(headers: &HeaderMap, object: &StoredObject) -> Option<MockResponse> {
+    let last_modified = object.last_modified.timestamp();
+    let date = |name| header_str(headers, name).and_then(|value| DateTime::parse_from_rfc2822(value).ok()).map(|date| date.timestamp());
+    let failed = match header_str(headers, "if-match") {
+        Some(condition) => !etag_matches(condition, &object.etag),
+        None => date("if-unmodified-since").is_some_and(|since| last_modified > since),
+    };
+    if failed {
+        return Some(precondition_failed());
+    }
+    let not_modified = match header_str(headers, "if-none-match") {
+        Some(condition) => etag_matches(condition, &object.etag),
+        None => date("if-modified-since").is_some_and(|since| last_modified <= since),
+    };
+    not_modified.then(|| with_header(empty_response(StatusCode::NOT_MODIFIED), "etag", &object.etag))
+}
+
+fn precondition_failed() -> MockResponse {
+    error_response(StatusCode::PRECONDITION_FAILED, "PreconditionFailed", "At least one of the pre-conditions you specified did not hold")
+}
+
+/// Copies keep the source's metadata and tags, as with S3's default COPY directives
+fn copy_object(store: &Store, bucket: &str, key: &str, source: &str) -> MockResponse {
+    let source = match read_copy_source(store, source) {
+        Ok(source) => source,
+        Err(response) => return *response,
+    };
+    let etag = etag_of(&source.data);
+    let last_modified = Utc::now();
+    let version_id = store.store_object(
+        bucket,
+        key,
+        StoredObject {
+            data: source.data,
+            etag: etag.clone(),
+            last_modified,
+            attributes: source.attributes,
+        },
+    );
+    let response = xml_response(format!(
+        "<CopyObjectResult><ETag>{}</ETag><LastModified>{}</LastModified></CopyObjectResult>",
+        xml_escape(&etag),
+        last_modified.format("%Y-%m-%dT%H:%M:%S%.3fZ")
+    ));
+    with_version(response, version_id.as_deref())
+}
+
+/// `stale` serves the key as it was before its last write, unless a version is requested
+fn get_object(store: &Store, bucket: &str, key: &str, version_id: Option<&String>, headers: &HeaderMap, head: bool, stale: bool) -> MockResponse {
+    let found = match version_id {
+        None if stale => store.find_stale_object(bucket, key),
+        _ => store.find_object(bucket, key, version_id.map(String::as_str)),
+    };
+    let (object, version_id) = match found {
+        Ok(found) => found,
+        // HEAD responses carry no error body
+        Err(response) if head => return response.map(|_| Bytes::new()),
+        Err(response) => return *response,
+    };
+    if let Some(response) = read_preconditions(headers, &object) {
+        return if head { response.map(|_| Bytes::new()) } else { response };
+    }
+    let len = object.data.len() as u64;
+    let range = header_str(headers, "range");
+
+    let (status, body, content_range) = match range.map(|r| parse_range(r, len)) {
+        None | Some(RangeRequest::Ignored) => (StatusCode::OK, object.data.clone(), None),
+        Some(RangeRequest::Satisfiable(start, end)) => (
+            StatusCode::PARTIAL_CONTENT,
+            object.data.slice(start as usize..=end as usize),
+            Some(format!("bytes {}-{}/{}", start, end, len)),
+        ),
+        Some(RangeRequest::Unsatisfiable) => {
+            let response = error_response(
+                StatusCode::RANGE_NOT_SATISFIABLE,
+                "InvalidRange",
+                "The requested range is not satisfiable",
+            );
+            return with_header(response, "content-range", &format!("bytes */{}", len));
+        }
+    };
+
+    let content_length = body.len();
+    let mut response = Response::new(if head { Bytes::new() } else { body });
+    *response.status_mut() = status;
+    let mut response = with_header(response, "etag", &object.etag);
+    response = with_header(response, "last-modified", &object.last_modified.format("%a, %d %b %Y %H:%M:%S GMT").to_string());
+    response = with_header(response, "content-length", &content_length.to_string());
+    response = with_header(response, "accept-ranges", "bytes");
+    response.headers_mut().extend(object.attributes.headers.clone());
+    if !object.attributes.tags.is_empty() {
+        response = with_header(response, "x-amz-tagging-count", &object.attributes.tags.len().to_string());
+    }
+    if let Some(content_range) = content_range {
+        response = with_header(response, "content-range", &content_range);
+    }
+    with_version(response, version_id.as_deref())
+}
+
+fn delete_object(store: &Store, bucket: &str, key: &str, version_id: Option<&String>) -> MockResponse {
+    let name = (bucket.to_string(), key.to_string());
+    let mut objects = store.objects.lock().unwrap();
+    let mut versions = store.versions.lock().unwrap();
+    let response = empty_response(StatusCode::NO_CONTENT);
+    let Some(version_id) = version_id else {
+        store.set_current(&mut objects, &name, None);
+        if !store.versioning_enabled(bucket) {
+            return response;
+        }
+        let marker = store.new_version_id();
+        versions.entry(name).or_default().push(Version {
+            id: marker.clone(),
+            last_modified: Utc::now(),
+            object: None,
+        });
+        return with_version(with_header(response, "x-amz-delete-marker", "true"), Some(&marker));
+    };
+
+    // Deleting a version that does not exist succeeds without effect, as in S3
+    let Some(stack) = versions.get_mut(&name) else {
+        return with_version(response, Some(version_id));
+    };
+    let Some(position) = stack.iter().position(|version| &version.id == version_id) else {
+        return with_version(response, Some(version_id));
+    };
+    let removed = stack.remove(position);
+    // Removing the latest version makes the one below it current again
+    if position == stack.len() {
+        store.set_current(&mut objects, &name, stack.last().and_then(|version| version.object.clone()));
+    }
+    if stack.is_empty() {
+        versions.remove(&name);
+    }
+    let response = with_version(response, Some(version_id));
+    if removed.object.is_none() {
+        with_header(response, "x-amz-delete-marker", "true")
+    } else {
+        response
+    }
+}
+
+fn get_object_tagging(store: &Store, bucket: &str, key: &str, version_id: Option<&String>) -> MockResponse {
+    let (object, version_id) = match store.find_object(bucket, key, version_id.map(String::as_str)) {
+        Ok(found) => found,
+        Err(response) => return *response,
+    };
+    let tags: String = object
+        .attributes
+        .tags
+        .iter()
+        .map(|(key, value)| format!("<Tag><Key>{}</Key><Value>{}</Value></Tag>", xml_escape(key), xml_escape(value)))
+        .collect();
+    let response = xml_response(format!(
+        "<Tagging xmlns=\"http://s3.amazonaws.com/doc/2006-03-01/\"><TagSet>{}</TagSet></Tagging>",
+        tags
+    ));
+    with_version(response, version_id.as_deref())
+}
+
+/// Replace the tags of the current object, and of its entry in the version history if it has one
+fn put_object_tagging(store: &Store, bucket: &str, key: &str, body: &[u8]) -> MockResponse {
+    let xml = String::from_utf8_lossy(body);
+    let tags: BTreeMap<String, String> = xml_values(&xml, "Key").into_iter().zip(xml_values(&xml, "Value")).collect();
+    if let Err(response) = check_tags(&tags) {
+        return *response;
+    }
+    let name = (bucket.to_string(), key.to_string());
+    let mut objects = store.objects.lock().unwrap();
+    let Some(object) = objects.get_mut(&name) else {
+        return error_response(StatusCode::NOT_FOUND, "NoSuchKey", "The specified key does not exist.");
+    };
+    object.attributes.tags = tags.clone();
+    let mut versions = store.versions.lock().unwrap();
+    match versions.get_mut(&name).and_then(|stack| stack.last_mut()) {
+        Some(Version { id, object: Some(object), .. }) => {
+            object.attributes.tags = tags;
+            with_version(empty_response(StatusCode::OK), Some(id))
+        }
+        _ => empty_response(StatusCode::OK),
+    }
+}
+
+fn put_bucket_versioning(store: &Store, bucket: &str, body: &[u8]) -> MockResponse {
+    let status = xml_values(&String::from_utf8_lossy(body), "Status").into_iter().next().unwrap_or_default();
+    if status != "Enabled" && status != "Suspended" {
+        return error_response(StatusCode::BAD_REQUEST, "MalformedXML", "Status must be Enabled or Suspended");
+    }
+    store.versioning.lock().unwrap().insert(bucket.to_string(), status);
+    empty_response(StatusCode::OK)
+}
+
+fn get_bucket_versioning(store: &Store, bucket: &str) -> MockResponse {
+    let status = match store.versioning.lock().unwrap().get(bucket) {
+        Some(status) => format!("<Status>{}</Status>", status),
+        None => String::new(),
+    };
+    xml_response(format!(
+        "<VersioningConfiguration xmlns=\"http://s3.amazonaws.com/doc/2006-03-01/\">{}</VersioningConfiguration>",
+        status
+    ))
+}
+
+fn create_multipart_upload(store: &Store, bucket: &str, key: &str, headers: &HeaderMap) -> MockResponse {
+    let attributes = match request_attributes(headers) {
+        Ok(attributes) => attributes,
+        Err(response) => return *response,
+    };
+    let upload_id = format!("mock-upload-{}", store.next_upload_id.fetch_add(1, Ordering::Relaxed));
+    store.uploads.lock().unwrap().insert(
+        upload_id.clone(),
+        Upload {
+            bucket: bucket.to_string(),
+            key: key.to_string(),
+            initiated: Utc::now(),
+            parts: BTreeMap::new(),
+            attributes,
+        },
+    );
+    xml_response(format!(
+        "<InitiateMultipartUploadResult><Bucket>{}</Bucket><Key>{}</Key><UploadId>{}</UploadId></InitiateMultipartUploadResult>",
+        xml_escape(bucket),
+        xml_escape(key),
+        upload_id
+    ))
+}
+
+/// Store one part of a multipart upload, returning its ETag
+fn add_part(store: &Store, query: &HashMap<String, String>, data: Bytes) -> Result<String, Box<MockResponse>> {
+    let Some(part_number) = query.get("partNumber").and_then(|n| n.parse::<i32>().ok()) else {
+        return Err(Box::new(error_response(StatusCode::BAD_REQUEST, "InvalidArgument", "Missing or invalid partNumber")));
+    };
+    let mut uploads = store.uploads.lock().unwrap();
+    let Some(upload) = uploads.get_mut(&query["uploadId"]) else {
+        return Err(Box::new(error_response(StatusCode::NOT_FOUND, "NoSuchUpload", "The specified upload does not exist.")));
+    };
+    let etag = etag_of(&data);
+    upload.parts.insert(part_number, data);
+    Ok(etag)
+}
+
+fn upload_part(store: &Store, query: &HashMap<String, String>, body: Bytes) -> MockResponse {
+    match add_part(store, query, body) {
+        Ok(etag) => with_header(empty_response(StatusCode::OK), "etag", &etag),
+        Err(response) => *response,
+    }
+}
+
+fn upload_part_copy(store: &Store, query: &HashMap<String, String>, source: &str, range: Option<&str>) -> MockResponse {
+    let data = match read_copy_source(store, source) {
+        Ok(source) => source.data,
+        Err(response) => return *response,
+    };
+    let data = match range.map(|r| parse_range(r, data.len() as u64)) {
+        None => data,
+        Some(RangeRequest::Satisfiable(start, end)) => data.slice(start as usize..=end as usize),
+        Some(_) => {
+            return error_response(StatusCode::BAD_REQUEST, "InvalidArgument", "The x-amz-copy-source-range value is invalid");
+        }
+    };
+    match add_part(store, query, data) {
+        Ok(etag) => xml_response(format!(
+            "<CopyPartResult><ETag>{}</ETag><LastModified>{}</LastModified></CopyPartResult>",
+            xml_escape(&etag),
+            Utc::now().format("%Y-%m-%dT%H:%M:%S%.3fZ")
+        )),
+        Err(response) => *response,
+    }
+}
+
+fn complete_multipart_upload(store: &Store, bucket: &str, key: &str, upload_id: &str, body: &[u8]) -> MockResponse {
+    let mut uploads = store.uploads.lock().unwrap();
+    let Some(upload) = uploads.get(upload_id) else {
+        return error_response(StatusCode::NOT_FOUND, "NoSuchUpload", "The specified upload does not exist.");
+    };
+    if upload.bucket != bucket || upload.key != key {
+        return error_response(StatusCode::NOT_FOUND, "NoSuchUpload", "The upload belongs to a different key.");
+    }
+
+    let requested = xml_values(&String::from_utf8_lossy(body), "PartNumber");
+    let min_part_size = store.min_part_size.load(Ordering::Relaxed);
+    let mut data = Vec::new();
+    for (index, part) in requested.iter().enumerate() {
+        match part.parse::<i32>().ok().and_then(|n| upload.parts.get(&n)) {
+            Some(part_data) if (part_data.len() as u64) < min_part_size && index + 1 < requested.len() => {
+                return error_response(StatusCode::BAD_REQUEST, "EntityTooSmall", "Your proposed upload is smaller than the minimum allowed object size.");
+            }
+            Some(part_data) => data.extend_from_slice(part_data),
+            None => {
+                return error_response(StatusCode::BAD_REQUEST, "InvalidPart", &format!("Part {} was not uploaded", part));
+            }
+        }
+    }
+    let attributes = uploads.remove(upload_id).map(|upload| upload.attributes).unwrap_or_default();
+    drop(uploads);
+
+    let data = Bytes::from(data);
+    let etag = format!("\"{}-{}\"", etag_of(&data).trim_matches('"'), requested.len());
+    let version_id = store.store_object(
+        bucket,
+        key,
+        StoredObject {
+            data,
+            etag: etag.clone(),
+            last_modified: Utc::now(),
+            attributes,
+        },
+    );
+    let response = xml_response(format!(
+        "<CompleteMultipartUploadResult><Bucket>{}</Bucket><Key>{}</Key><ETag>{}</ETag></CompleteMultipartUploadResult>",
+        xml_escape(bucket),
+        xml_escape(key),
+        xml_escape(&etag)
+    ));
+    with_version(response, version_id.as_deref())
+}
+
+fn abort_multipart_upload(store: &Store, upload_id: &str) -> MockResponse {
+    match store.uploads.lock().unwrap().remove(upload_id) {
+        Some(_) => empty_response(StatusCode::NO_CONTENT),
+        None => error_response(StatusCode::NOT_FOUND, "NoSuchUpload", "The specified upload does not exist."),
+    }
+}
+
+/// Value of a numeric query parameter, `default` if it is absent or malformed
+fn query_number(query: &HashMap<String, String>, name: &str, default: usize) -> usize {
+    query.get(name).and_then(|n| n.parse().ok()).unwrap_or(default)
+}
+
+fn list_parts(store: &Store, bucket: &str, key: &str, query: &HashMap<String, String>) -> MockResponse {
+    let upload_id = &query["uploadId"];
+    let uploads = store.uploads.lock().unwrap();
+    let upload = match uploads.get(upload_id) {
+        Some(upload) if upload.bucket == bucket && upload.key == key => upload,
+        _ => return error_response(StatusCode::NOT_FOUND, "NoSuchUpload", "The specified upload does not exist."),
+    };
+    let max_parts = query_number(query, "max-parts", DEFAULT_MAX_KEYS);
+    let marker = query_number(query, "part-number-marker", 0) as i32;
+
+    let mut remaining = upload.parts.range(marker + 1..);
+    let mut parts = String::new();
+    let mut last_part = None;
+    for (number, data) in remaining.by_ref().take(max_parts) {
+        parts.push_str(&format!(
+            "<Part><PartNumber>{}</PartNumber><LastModified>{}</LastModified><ETag>{}</ETag><Size>{}</Size></Part>",
+            number,
+            upload.initiated.format("%Y-%m-%dT%H:%M:%S%.3fZ"),
+            xml_escape(&etag_of(data)),
+            data.len()
+        ));
+        last_part = Some(*number);
+    }
+    let truncated = remaining.next().is_some();
+
+    let mut xml = format!(
+        "<ListPartsResult><Bucket>{}</Bucket><Key>{}</Key><UploadId>{}</UploadId><PartNumberMarker>{}</PartNumberMarker><MaxParts>{}</MaxParts><IsTruncated>{}</IsTruncated>",
+        xml_escape(bucket),
+        xml_escape(key),
+        xml_escape(upload_id),
+        marker,
+        max_parts,
+        truncated
+    );
+    if let (true, Some(last_part)) = (truncated, last_part) {
+        xml.push_str(&format!("<NextPartNumberMarker>{}</NextPartNumberMarker>", last_part));
+    }
+    xml.push_str(&parts);
+    xml.push_str("</ListPartsResult>");
+    xml_response(xml)
+}
+
+fn list_multipart_uploads(store: &Store, bucket: &str, query: &HashMap<String, String>) -> MockResponse {
+    let prefix = query.get("prefix").cloned().unwrap_or_default();
+    let max_uploads = query_number(query, "max-uploads", DEFAULT_MAX_KEYS);
+    let key_marker = query.get("key-marker").cloned().unwrap_or_default();
+    let upload_id_marker = query.get("upload-id-marker").cloned().unwrap_or_default();
+
+    let uploads = store.uploads.lock().unwrap();
+    // Listed in key order, uploads of one key by upload ID; a key marker without
+    // an upload ID marker skips every upload of that key
+    let mut matching: Vec<(&String, &Upload)> = uploads
+        .iter()
+        .filter(|(_, upload)| upload.bucket == bucket && upload.key.starts_with(&prefix))
+        .filter(|(id, upload)| {
+            upload.key > key_marker || (upload.key == key_marker && !upload_id_marker.is_empty() && **id > upload_id_marker)
+        })
+        .collect();
+    matching.sort_by(|(id_a, a), (id_b, b)| (&a.key, id_a).cmp(&(&b.key, id_b)));
+    let truncated = matching.len() > max_uploads;
+    matching.truncate(max_uploads);
+
+    let mut xml = format!(
+        "<ListMultipartUploadsResult><Bucket>{}</Bucket><KeyMarker>{}</KeyMarker><UploadIdMarker>{}</UploadIdMarker><Prefix>{}</Prefix><MaxUploads>{}</MaxUploads><IsTruncated>{}</IsTruncated>",
+        xml_escape(bucket),
+        xml_escape(&key_marker),
+        xml_escape(&upload_id_marker),
+        xml_escape(&prefix),
+        max_uploads,
+        truncated
+    );
+    if let (true, Some((id, upload))) = (truncated, matching.last()) {
+        xml.push_str(&format!(
+            "<NextKeyMarker>{}</NextKeyMarker><NextUploadIdMarker>{}</NextUploadIdMarker>",
+            xml_escape(&upload.key),
+            xml_escape(id)
+        ));
+    }
+    for (id, upload) in matching {
+        xml.push_str(&format!(
+            "<Upload><Key>{}</Key><UploadId>{}</UploadId><Initiated>{}</Initiated><StorageClass>STANDARD</StorageClass></Upload>",
+            xml_escape(&upload.key),
+            xml_escape(id),
+            upload.initiated.format("%Y-%m-%dT%H:%M:%S%.3fZ")
+        ));
+    }
+    xml.push_str("</ListMultipartUploadsResult>");
+    xml_response(xml)
+}
+
+/// One page of a bucket listing: keys after `after` under `prefix`, with keys that
+/// contain `delimiter` past the prefix rolled up into common prefixes
+#[derive(Default)]
+struct ListPage {
+    contents: String,
+    common_prefixes: String,
+    count: usize,
+    truncated: bool,
+    /// Last key or common prefix on the page, where the next page starts
+    last: Option<String>,
+}
+
+fn list_page(store: &Store, bucket: &str, prefix: &str, delimiter: &str, after: &str, max_keys: usize) -> ListPage {
+    let objects = store.objects.lock().unwrap();
+    let matching = objects
+        .range((bucket.to_string(), after.to_string())..)
+        .take_while(|((b, _), _)| b == bucket)
+        .filter(|((_, key), _)| key.starts_with(prefix) && key.as_str() > after);
+
+    let mut page = ListPage::default();
+    for ((_, key), object) in matching {
+        let rolled_up = match key[prefix.len()..].find(delimiter) {
+            Some(at) if !delimiter.is_empty() => Some(&key[..prefix.len() + at + delimiter.len()]),
+            _ => None,
+        };
+        // A common prefix is listed once, and not again on the page it was the marker for
+        if rolled_up.is_some() && (rolled_up == Some(after) || rolled_up == page.last.as_deref()) {
+            continue;
+        }
+        if page.count == max_keys {
+            page.truncated = true;
+            break;
+        }
+        match rolled_up {
+            Some(common_prefix) => {
+                page.common_prefixes.push_str(&format!("<CommonPrefixes><Prefix>{}</Prefix></CommonPrefixes>", xml_escape(common_prefix)));
+                page.last = Some(common_prefix.to_string());
+            }
+            None => {
+                page.contents.push_str(&format!(
+                    "<Contents><Key>{}</Key><LastModified>{}</LastModified><ETag>{}</ETag><Size>{}</Size><StorageClass>STANDARD</StorageClass></Contents>",
+                    xml_escape(key),
+                    object.last_modified.format("%Y-%m-%dT%H:%M:%S%.3fZ"),
+                    xml_escape(&object.etag),
+                    object.data.len()
+                ));
+                page.last = Some(key.clone());
+            }
+        }
+        page.count += 1;
+    }
+    page
+}
+
+fn list_objects_v2(store: &Store, bucket: &str, query: &HashMap<String, String>) -> MockResponse {
+    let prefix = query.get("prefix").cloned().unwrap_or_default();
+    let delimiter = query.get("delimiter").cloned().unwrap_or_default();
+    let max_keys = query_number(query, "max-keys", DEFAULT_MAX_KEYS);
+    // Continuation tokens are simply the last key or common prefix of the previous page
+    let after = query
+        .get("continuation-token")
+        .or_else(|| query.get("start-after"))
+        .cloned()
+        .unwrap_or_default();
+    let page = list_page(store, bucket, &prefix, &delimiter, &after, max_keys);
+
+    let mut xml = format!(
+        "<ListBucketResult xmlns=\"http://s3.amazonaws.com/doc/2006-03-01/\"><Name>{}</Name><Prefix>{}</Prefix><KeyCount>{}</KeyCount><MaxKeys>{}</MaxKeys><IsTruncated>{}</IsTruncated>",
+        xml_escape(bucket),
+        xml_escape(&prefix),
+        page.count,
+        max_keys,
+        page.truncated
+    );
+    if !delimiter.is_empty() {
+        xml.push_str(&format!("<Delimiter>{}</Delimiter>", xml_escape(&delimiter)));
+    }
+    if let Some(start_after) = query.get("start-after") {
+        xml.push_str(&format!("<StartAfter>{}</StartAfter>", xml_escape(start_after)));
+    }
+    if let Some(token) = query.get("continuation-token") {
+        xml.push_str(&format!("<ContinuationToken>{}</ContinuationToken>", xml_escape(token)));
+    }
+    if let (true, Some(last)) = (page.truncated, &page.last) {
+        xml.push_str(&format!("<NextContinuationToken>{}</NextContinuationToken>", xml_escape(last)));
+    }
+    xml.push_str(&page.contents);
+    xml.push_str(&page.common_prefixes);
+    xml.push_str("</ListBucketResult>");
+    xml_response(xml)
+}
+
+/// The legacy ListObjects (v1) API: pages are resumed from a marker, and like S3 the
+/// mock only returns NextMarker when a delimiter is given
+fn list_objects_v1(store: &Store, bucket: &str, query: &HashMap<String, String>) -> MockResponse {
+    let prefix = query.get("prefix").cloned().unwrap_or_default();
+    let delimiter = query.get("delimiter").cloned().unwrap_or_default();
+    let max_keys = query_number(query, "max-keys", DEFAULT_MAX_KEYS);
+    let marker = query.get("marker").cloned().unwrap_or_default();
+    let page = list_page(store, bucket, &prefix, &delimiter, &marker, max_keys);
+
+    let mut xml = format!(
+        "<ListBucketResult xmlns=\"http://s3.amazonaws.com/doc/2006-03-01/\"><Name>{}</Name><Prefix>{}</Prefix><Marker>{}</Marker><MaxKeys>{}</MaxKeys><IsTruncated>{}</IsTruncated>",
+        xml_escape(bucket),
+        xml_escape(&prefix),
+        xml_escape(&marker),
+        max_keys,
+        page.truncated
+    );
+    if !delimiter.is_empty() {
+        xml.push_str(&format!("<Delimiter>{}</Delimiter>", xml_escape(&delimiter)));
+        if let (true, Some(last)) = (page.truncated, &page.last) {
+            xml.push_str(&format!("<NextMarker>{}</NextMarker>", xml_escape(last)));
+        }
+    }
+    xml.push_str(&page.contents);
+    xml.push_str(&page.common_prefixes);
+    xml.push_str("</ListBucketResult>");
+    xml_response(xml)
+}
+
+fn list_object_versions(store: &Store, bucket: &str, query: &HashMap<String, String>) -> MockResponse {
+    let prefix = query.get("prefix").cloned().unwrap_or_default();
+    let max_keys = query_number(query, "max-keys", DEFAULT_MAX_KEYS);
+    let key_marker = query.get("key-marker").cloned().unwrap_or_default();
+    let version_id_marker = query.get("version-id-marker").cloned().unwrap_or_default();
+
+    let objects = store.objects.lock().unwrap();
+    let versions = store.versions.lock().unwrap();
+    // Every key with a history lists it newest first; objects written while
+    // versioning was off have no history and appear as version "null"
+    let start = (bucket.to_string(), prefix.clone().max(key_marker.clone()));
+    let mut keys: BTreeMap<&String, Vec<Version>> = BTreeMap::new();
+    for ((_, key), stack) in versions.range(start.clone()..).take_while(|((b, key), _)| b == bucket && key.starts_with(&prefix)) {
+        keys.insert(key, stack.iter().rev().cloned().collect());
+    }
+    for ((_, key), object) in objects.range(start..).take_while(|((b, key), _)| b == bucket && key.starts_with(&prefix)) {
+        keys.entry(key).or_insert_with(|| {
+            vec![Version {
+                id: "null".to_string(),
+                last_modified: object.last_modified,
+                object: Some(object.clone()),
+            }]
+        });
+    }
+
+    // A key marker alone skips the whole key; with a version ID marker, the key's versions after that one follow
+    let mut entries = keys
+        .into_iter()
+        .filter(|(key, _)| **key > key_marker || (**key == key_marker && !version_id_marker.is_empty()))
+        .flat_map(|(key, history)| {
+            let skip = if *key == key_marker {
+                history.iter().position(|version| version.id == version_id_marker).map_or(history.len(), |p| p + 1)
+            } else {
+                0
+            };
+            history.into_iter().enumerate().skip(skip).map(move |(position, version)| (key, version, position == 0))
+        });
+
+    let mut listing = String::new();
+    let mut last = None;
+    for (key, version, latest) in entries.by_ref().take(max_keys) {
+        let modified = version.last_modified.format("%Y-%m-%dT%H:%M:%S%.3fZ");
+        let id = &version.id;
+        match &version.object {
+            Some(object) => listing.push_str(&format!(
+                "<Version><Key>{}</Key><VersionId>{}</VersionId><IsLatest>{}</IsLatest><LastModified>{}</LastModified><ETag>{}</ETag><Size>{}</Size><StorageClass>STANDARD</StorageClass></Version>",
+                xml_escape(key),
+                xml_escape(id),
+                latest,
+                modified,
+                xml_escape(&object.etag),
+                object.data.len()
+            )),
+            None => listing.push_str(&format!(
+                "<DeleteMarker><Key>{}</Key><VersionId>{}</VersionId><IsLatest>{}</IsLatest><LastModified>{}</LastModified></DeleteMarker>",
+                xml_escape(key),
+                xml_escape(id),
+                latest,
+                modified
+            )),
+        }
+        last = Some((key, version.id));
+    }
+    let truncated = entries.next().is_some();
+
+    let mut xml = format!(
+        "<ListVersionsResult xmlns=\"http://s3.amazonaws.com/doc/2006-03-01/\"><Name>{}</Name><Prefix>{}</Prefix><KeyMarker>{}</KeyMarker><VersionIdMarker>{}</VersionIdMarker><MaxKeys>{}</MaxKeys><IsTruncated>{}</IsTruncated>",
+        xml_escape(bucket),
+        xml_escape(&prefix),
+        xml_escape(&key_marker),
+        xml_escape(&version_id_marker),
+        max_keys,
+        truncated
+    );
+    if let (true, Some((key, id))) = (truncated, last) {
+        xml.push_str(&format!(
+            "<NextKeyMarker>{}</NextKeyMarker><NextVersionIdMarker>{}</NextVersionIdMarker>",
+            xml_escape(key),
+            xml_escape(&id)
+        ));
+    }
+    xml.push_str(&listing);
+    xml.push_str("</ListVersionsResult>");
+    xml_response(xml)
+}
+
+enum RangeRequest {
+    /// Inclusive byte offsets
+    Satisfiable(u64, u64),
+    Unsatisfiable,
+    /// Not a single byte range; S3 then returns the whole object
+    Ignored,
+}
+
+fn parse_range(header: &str, len: u64) -> RangeRequest {
+    let Some(spec) = header.trim().strip_prefix("bytes=") else {
+        return RangeRequest::Ignored;
+    };
+    if spec.contains(',') {
+        return RangeRequest::Ignored;
+    }
+    let Some((start, end)) = spec.split_once('-') else {
+        return RangeRequest::Ignored;
+    };
+    let (start, end) = match (start.trim().parse::<u64>().ok(), end.trim().parse::<u64>().ok()) {
+        (Some(start), Some(end)) if start <= end => (start, end.min(len.saturating_sub(1))),
+        (Some(start), None) if end.trim().is_empty() => (start, len.saturating_sub(1)),
+        (None, Some(suffix)) if start.trim().is_empty() && suffix > 0 => (len.saturating_sub(suffix), len.saturating_sub(1)),
+        _ => return RangeRequest::Ignored,
+    };
+    if start >= len {
+        RangeRequest::Unsatisfiable
+    } else {
+        RangeRequest::Satisfiable(start, end)
+    }
+}
+
+/// How a request's payload was signed: the `x-amz-content-sha256` value, with a
+/// literal hash shown as `SHA-256`, and whether the body came without a Content-Length
+fn payload_signing(headers: &hyper::HeaderMap) -> String {
+    let scheme = match headers.get("x-amz-content-sha256").and_then(|v| v.to_str().ok()) {
+        Some(hash) if hash.len() == 64 && hash.bytes().all(|b| b.is_ascii_hexdigit()) => "SHA-256",
+        Some(scheme) => scheme,
+        None => "no x-amz-content-sha256",
+    };
+    if headers.contains_key(CONTENT_LENGTH) {
+        scheme.to_string()
+    } else {
+        format!("{} without Content-Length", scheme)
+    }
+}
+
+fn is_aws_chunked(headers: &hyper::HeaderMap) -> bool {
+    let encoded = headers
+        .get(hyper::header::CONTENT_ENCODING)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.split(',').any(|e| e.trim() == "aws-chunked"));
+    let streaming = headers
+        .get("x-amz-content-sha256")
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("STREAMING-"));
+    encoded || streaming
+}
+
+/// Strip aws-chunked framing (`<hex-size>[;chunk-signature=...]\r\n<data>\r\n`,
+/// terminated by a zero-size chunk and optional trailers)
+fn decode_aws_chunked(body: &[u8]) -> Option<Bytes> {
+    let mut decoded = Vec::with_capacity(body.len());
+    let mut rest = body;
+    loop {
+        let line_end = rest.windows(2).position(|w| w == b"\r\n")?;
+        let header = std::str::from_utf8(&rest[..line_end]).ok()?;
+        let size = usize::from_str_radix(header.split(';').next()?.trim(), 16).ok()?;
+        rest = &rest[line_end + 2..];
+        if size == 0 {
+            return Some(Bytes::from(decoded));
+        }
+        decoded.extend_from_slice(rest.get(..size)?);
+        rest = rest.get(size + 2..)?;
+    }
+}
+
+fn parse_query(query: &str) -> HashMap<String, String> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| match pair.split_once('=') {
+            Some((name, value)) => (percent_decode(name), percent_decode(value)),
+            None => (percent_decode(pair), String::new()),
+        })
+        .collect()
+}
+
+fn percent_decode(input: &str) -> String {
+    let bytes = input.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            if let Some(byte) = input.get(i + 1..i + 3).and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+                decoded.push(byte);
+                i += 3;
+                continue;
+            }
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn xml_escape(input: &str) -> String {
+    input
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// Text of every `<tag>...</tag>` element, in document order
+fn xml_values(xml: &str, tag: &str) -> Vec<String> {
+    let open = format!("<{}>", tag);
+    let close = format!("</{}>", tag);
+    let mut values = Vec::new();
+    let mut rest = xml;
+    while let Some(start) = rest.find(&open) {
+        rest = &rest[start + open.len()..];
+        let Some(end) = rest.find(&close) else { break };
+        values.push(rest[..end].trim().to_string());
+        rest = &rest[end + close.len()..];
+    }
+    values
+}
+
+/// Quoted content hash; stands in for the MD5 ETag S3 would return
+fn etag_of(data: &[u8]) -> String {
+    let mut hasher = DefaultHasher::new();
+    data.hash(&mut hasher);
+    format!("\"{:016x}{:016x}\"", hasher.finish(), data.len())
+}
+
+fn with_header(mut response: MockResponse, name: &'static str, value: &str) -> MockResponse {
+    if let Ok(value) = HeaderValue::from_str(value) {
+        response.headers_mut().insert(name, value);
+    }
+    response
+}
+
+fn empty_response(status: StatusCode) -> MockResponse {
+    let mut response = Response::new(Bytes::new());
+    *response.status_mut() = status;
+    response
+}
+
+fn xml_response(body: String) -> MockResponse {
+    let response = Response::new(Bytes::from(format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n{}",
+        body
+    )));
+    with_header(response, "content-type", "application/xml")
+}
+
+fn error_response(status: StatusCode, code: &str, message: &str) -> MockResponse {
+    let mut response = xml_response(format!(
+        "<Error><Code>{}</Code><Message>{}</Message></Error>",
+        code,
+        xml_escape(message)
+    ));
+    *response.status_mut() = status;
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_range_forms() {
+        assert!(matches!(parse_range("bytes=0-99", 1000), RangeRequest::Satisfiable(0, 99)));
+        assert!(matches!(parse_range("bytes=900-", 1000), RangeRequest::Satisfiable(900, 999)));
+        assert!(matches!(parse_range("bytes=-100", 1000), RangeRequest::Satisfiable(900, 999)));
+        assert!(matches!(parse_range("bytes=990-2000", 1000), RangeRequest::Satisfiable(990, 999)));
+        assert!(matches!(parse_range("bytes=1000-1001", 1000), RangeRequest::Unsatisfiable));
+        assert!(matches!(parse_range("bytes=0-1,5-6", 1000), RangeRequest::Ignored));
+    }
+
+    #[test]
+    fn decodes_aws_chunked_body() {
+        let body = b"5;chunk-signature=abc\r\nhello\r\n6;chunk-signature=def\r\n world\r\n0;chunk-signature=0\r\nx-amz-checksum-crc32:AAAA\r\n\r\n";
+        assert_eq!(decode_aws_chunked(body).unwrap(), Bytes::from_static(b"hello world"));
+        assert!(decode_aws_chunked(b"5\r\nhel").is_none());
+    }
+
+    #[test]
+    fn percent_decodes_keys() {
+        assert_eq!(percent_decode("dir%2Fa%20b%2Bc"), "dir/a b+c");
+        assert_eq!(percent_decode("100%"), "100%");
+    }
+}
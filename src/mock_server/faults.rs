@@ -0,0 +1,251 @@
+//! Fault injection for the mock server, configured from a YAML file:
+//!
+//! ```yaml
+//! seed: 42                      # fixed seed makes the fault sequence reproducible
+//! rules:
+//!   - operations: [GetObject]   # omit to match every operation
+//!     latency_ms: 20            # fixed delay added before responding
+//!     jitter_ms: 30             # plus a uniform random delay up to this
+//!     capacity: 8               # requests served at once; the rest queue for a slot
+//!     slow_down_rate: 0.05      # fraction answered with 503 SlowDown
+//!     reset_rate: 0.01          # fraction whose connection is reset mid-body
+//!     truncate_rate: 0.01       # fraction whose body is cut in half
+//!     stale_read_rate: 0.01     # fraction of GETs and HEADs served from before the key's last write
+//!   - operations: [CompleteMultipartUpload]
+//!     stall_ms: 30000           # stall this long...
+//!     stall_rate: 1.0           # ...for this fraction of requests
+//! ```
+//!
+//! Every matching rule adds its delays; the first rule that triggers a
+//! SlowDown, reset, truncation or stale read decides the fault. A request holds a slot of
+//! every matching rule with a capacity while its delays run, so latency grows
+//! with concurrency once the capacity is exceeded, as on a saturated server.
+
+use anyhow::{Context, Result};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::Deserialize;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// S3 operations the mock server distinguishes, named as in the S3 API
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+pub(crate) enum Operation {
+    PutObject,
+    UploadPart,
+    CopyObject,
+    UploadPartCopy,
+    CreateMultipartUpload,
+    CompleteMultipartUpload,
+    AbortMultipartUpload,
+    ListParts,
+    ListMultipartUploads,
+    GetObject,
+    HeadObject,
+    DeleteObject,
+    GetObjectTagging,
+    PutObjectTagging,
+    ListObjectsV2,
+    ListObjects,
+    ListObjectVersions,
+    CreateBucket,
+    HeadBucket,
+    PutBucketVersioning,
+    GetBucketVersioning,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct FaultConfig {
+    /// RNG seed; omit for a different fault sequence on every run
+    #[serde(default)]
+    seed: Option<u64>,
+    #[serde(default)]
+    rules: Vec<FaultRule>,
+}
+
+impl FaultConfig {
+    pub(crate) fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read fault config {}", path.display()))?;
+        Self::parse(&text).with_context(|| format!("Invalid fault config {}", path.display()))
+    }
+
+    pub(crate) fn parse(text: &str) -> Result<Self> {
+        let config: FaultConfig = serde_yaml::from_str(text)?;
+        for rule in &config.rules {
+            for (name, rate) in [
+                ("slow_down_rate", rule.slow_down_rate),
+                ("reset_rate", rule.reset_rate),
+                ("truncate_rate", rule.truncate_rate),
+                ("stale_read_rate", rule.stale_read_rate),
+                ("stall_rate", rule.stall_rate),
+            ] {
+                if !(0.0..=1.0).contains(&rate) {
+                    anyhow::bail!("{} must be between 0 and 1, got {}", name, rate);
+                }
+            }
+            if rule.capacity == Some(0) {
+                anyhow::bail!("capacity must be positive");
+            }
+        }
+        Ok(config)
+    }
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct FaultRule {
+    operations: Vec<Operation>,
+    latency_ms: u64,
+    jitter_ms: u64,
+    slow_down_rate: f64,
+    reset_rate: f64,
+    truncate_rate: f64,
+    stale_read_rate: f64,
+    stall_ms: u64,
+    stall_rate: f64,
+    capacity: Option<usize>,
+}
+
+impl FaultRule {
+    fn matches(&self, operation: Operation) -> bool {
+        self.operations.is_empty() || self.operations.contains(&operation)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Fault {
+    /// Respond 503 SlowDown without performing the operation
+    SlowDown,
+    /// Drop the connection; responses with a body are cut off midway
+    Reset,
+    /// Send only the first half of the body, with a matching Content-Length
+    Truncate,
+    /// Serve GetObject and HeadObject from the key's state before its last
+    /// write, as an eventually consistent store might
+    StaleRead,
+}
+
+/// What to do to one request
+pub(crate) struct Decision {
+    pub(crate) delay: Duration,
+    pub(crate) fault: Option<Fault>,
+}
+
+pub(crate) struct Faults {
+    rules: Vec<FaultRule>,
+    /// Slots of each rule with a capacity, by rule index
+    slots: Vec<Option<Arc<Semaphore>>>,
+    rng: Mutex<StdRng>,
+}
+
+impl Faults {
+    pub(crate) fn new(config: FaultConfig) -> Self {
+        let rng = match config.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+        Faults {
+            slots: config.rules.iter().map(|rule| rule.capacity.map(|slots| Arc::new(Semaphore::new(slots)))).collect(),
+            rules: config.rules,
+            rng: Mutex::new(rng),
+        }
+    }
+
+    /// Wait for a slot of every matching rule with a capacity; the request holds them until the permits are dropped
+    pub(crate) async fn admit(&self, operation: Operation) -> Vec<OwnedSemaphorePermit> {
+        let mut permits = Vec::new();
+        for (rule, slots) in self.rules.iter().zip(&self.slots) {
+            if let Some(slots) = slots.as_ref().filter(|_| rule.matches(operation)) {
+                permits.push(slots.clone().acquire_owned().await.expect("fault slots are never closed"));
+            }
+        }
+        permits
+    }
+
+    pub(crate) fn decide(&self, operation: Operation) -> Decision {
+        let mut decision = Decision {
+            delay: Duration::ZERO,
+            fault: None,
+        };
+        let mut rng = self.rng.lock().unwrap();
+        for rule in self.rules.iter().filter(|rule| rule.matches(operation)) {
+            // Draw every value in a fixed order so a seed always yields the same sequence
+            let jitter = rng.gen_range(0..=rule.jitter_ms);
+            let stall = rng.gen_bool(rule.stall_rate);
+            let slow_down = rng.gen_bool(rule.slow_down_rate);
+            let reset = rng.gen_bool(rule.reset_rate);
+            let truncate = rng.gen_bool(rule.truncate_rate);
+            let stale_read = rng.gen_bool(rule.stale_read_rate);
+
+            decision.delay += Duration::from_millis(rule.latency_ms + jitter);
+            if stall {
+                decision.delay += Duration::from_millis(rule.stall_ms);
+            }
+            if decision.fault.is_none() {
+                decision.fault = if slow_down {
+                    Some(Fault::SlowDown)
+                } else if reset {
+                    Some(Fault::Reset)
+                } else if truncate {
+                    Some(Fault::Truncate)
+                } else if stale_read {
+                    Some(Fault::StaleRead)
+                } else {
+                    None
+                };
+            }
+        }
+        decision
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rules_apply_to_matching_operations() {
+        let faults = Faults::new(
+            FaultConfig::parse(
+                "seed: 1\nrules:\n  - operations: [GetObject]\n    latency_ms: 5\n    slow_down_rate: 1.0\n  - stall_ms: 100\n    stall_rate: 1.0\n",
+            )
+            .unwrap(),
+        );
+        let get = faults.decide(Operation::GetObject);
+        assert_eq!(get.delay, Duration::from_millis(105));
+        assert_eq!(get.fault, Some(Fault::SlowDown));
+        let put = faults.decide(Operation::PutObject);
+        assert_eq!(put.delay, Duration::from_millis(100));
+        assert_eq!(put.fault, None);
+    }
+
+    #[test]
+    fn seed_makes_faults_reproducible() {
+        let config = "seed: 7\nrules:\n  - jitter_ms: 1000\n    reset_rate: 0.5\n";
+        let sequence = |faults: Faults| {
+            (0..50)
+                .map(|_| {
+                    let d = faults.decide(Operation::PutObject);
+                    (d.delay, d.fault)
+                })
+                .collect::<Vec<_>>()
+        };
+        let first = sequence(Faults::new(FaultConfig::parse(config).unwrap()));
+        let second = sequence(Faults::new(FaultConfig::parse(config).unwrap()));
+        assert_eq!(first, second);
+        assert!(first.iter().any(|(_, fault)| fault.is_some()));
+        assert!(first.iter().any(|(_, fault)| fault.is_none()));
+    }
+
+    #[test]
+    fn rejects_invalid_config() {
+        assert!(FaultConfig::parse("rules:\n  - slow_down_rate: 1.5\n").is_err());
+        assert!(FaultConfig::parse("rules:\n  - operations: [Nope]\n").is_err());
+        assert!(FaultConfig::parse("rules:\n  - latncy_ms: 5\n").is_err());
+        assert!(FaultConfig::parse("rules:\n  - capacity: 0\n").is_err());
+    }
+}
@@ -0,0 +1,413 @@
+//! Multipart lifecycle stress: every operation is one multipart upload with many
+//! small parts, uploaded in shuffled order with some parts overwritten, checked
+//! through ListParts and ListMultipartUploads, and then either aborted or
+//! completed and checked for its final size. Aimed at the upload metadata a
+//! backend keeps, rather than at data throughput.
+
+use super::{connect, drive, generate_random_data, ConnectionArgs, OpUnit, RunArgs, Stats};
+use crate::endpoints::EndpointPool;
+use anyhow::{Context, Result};
+use aws_sdk_s3::error::ProvideErrorMetadata;
+use aws_sdk_s3::types::{CompletedMultipartUpload, CompletedPart};
+use aws_sdk_s3::Client as S3Client;
+use bytes::Bytes;
+use clap::Args;
+use futures::{StreamExt, TryStreamExt};
+use rand::seq::SliceRandom;
+use rand::Rng;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// S3's limit on parts per upload
+const MAX_PARTS: usize = 10_000;
+
+/// Smallest part other than the last that S3 accepts when completing an upload
+const MIN_PART_SIZE: usize = 5 * 1024 * 1024;
+
+#[derive(Args)]
+pub(crate) struct MultipartStressArgs {
+    #[command(flatten)]
+    conn: ConnectionArgs,
+    #[command(flatten)]
+    run: RunArgs,
+    /// Uploads in flight
+    #[arg(long, default_value = "4")]
+    concurrent: usize,
+    /// Parts per upload (at most 10000)
+    #[arg(long, default_value = "1000")]
+    parts: usize,
+    /// Size of each part; S3 refuses to complete uploads with parts below 5 MiB
+    /// other than the last, which is counted as its own outcome
+    #[arg(long, default_value = "1024")]
+    part_size: usize,
+    /// Parts of one upload in flight
+    #[arg(long, default_value = "16")]
+    part_concurrency: usize,
+    /// Fraction of uploads aborted instead of completed
+    #[arg(long, default_value = "0.1")]
+    abort_rate: f64,
+    /// Fraction of parts uploaded a second time with different data
+    #[arg(long, default_value = "0.05")]
+    overwrite_rate: f64,
+    #[arg(long, default_value = "multipart-stress/")]
+    prefix: String,
+}
+
+/// Outcomes of the uploads, beyond the S3 requests counted per operation type
+#[derive(Debug, Default)]
+pub(crate) struct StressCounters {
+    pub(crate) completed: AtomicU64,
+    pub(crate) aborted: AtomicU64,
+    /// Completions refused with EntityTooSmall, then aborted
+    pub(crate) too_small: AtomicU64,
+    pub(crate) overwrites: AtomicU64,
+    pub(crate) check_failures: AtomicU64,
+}
+
+/// What one upload does, drawn up front so the operation itself needs no RNG
+struct UploadPlan {
+    /// Part numbers in upload order
+    order: Vec<i32>,
+    /// Part numbers uploaded again once every part is in
+    overwrites: Vec<i32>,
+    abort: bool,
+}
+
+pub(crate) struct MultipartStressWorkload {
+    endpoints: Arc<EndpointPool>,
+    bucket: String,
+    run: RunArgs,
+    parts: usize,
+    part_size: usize,
+    part_concurrency: usize,
+    abort_rate: f64,
+    overwrite_rate: f64,
+    prefix: String,
+    /// Random data every part is derived from
+    pattern: Bytes,
+    pub(crate) counters: Arc<StressCounters>,
+}
+
+impl MultipartStressWorkload {
+    pub(crate) async fn new(args: MultipartStressArgs) -> Result<Self> {
+        if args.parts == 0 || args.parts > MAX_PARTS {
+            anyhow::bail!("--parts must be between 1 and {}", MAX_PARTS);
+        }
+        if args.part_size < 16 {
+            anyhow::bail!("--part-size must be at least 16 bytes");
+        }
+        for (name, rate) in [("--abort-rate", args.abort_rate), ("--overwrite-rate", args.overwrite_rate)] {
+            if !(0.0..=1.0).contains(&rate) {
+                anyhow::bail!("{} must be between 0 and 1", name);
+            }
+        }
+        Ok(MultipartStressWorkload {
+            endpoints: connect(&args.conn).await?,
+            bucket: args.conn.bucket,
+            run: args.run,
+            parts: args.parts,
+            part_size: args.part_size,
+            part_concurrency: args.part_concurrency.max(1),
+            abort_rate: args.abort_rate,
+            overwrite_rate: args.overwrite_rate,
+            prefix: args.prefix,
+            pattern: Bytes::from(generate_random_data(args.part_size)),
+            counters: Arc::default(),
+        })
+    }
+
+    fn print_config(&self) {
+        println!("Parts per upload: {} x {} bytes", self.parts, self.part_size);
+        println!("Parts in flight per upload: {}", self.part_concurrency);
+        println!("Abort rate: {}, overwrite rate: {}", self.abort_rate, self.overwrite_rate);
+        if self.parts > 1 && self.part_size < MIN_PART_SIZE {
+            println!("Parts are below 5 MiB: S3 refuses to complete these uploads (EntityTooSmall); they are aborted and counted separately");
+        }
+    }
+
+    fn plan(&self, rng: &mut impl Rng) -> UploadPlan {
+        let mut order: Vec<i32> = (1..=self.parts as i32).collect();
+        order.shuffle(rng);
+        let mut overwrites: Vec<i32> = order.iter().copied().filter(|_| rng.gen_bool(self.overwrite_rate)).collect();
+        overwrites.shuffle(rng);
+        UploadPlan {
+            order,
+            overwrites,
+            abort: rng.gen_bool(self.abort_rate),
+        }
+    }
+
+    pub(crate) async fn execute(&self, concurrent: usize) -> Result<Stats> {
+        drive("MULTIPART", concurrent, self.run, Duration::from_millis(10), OpUnit::Bytes, &self.endpoints, |operation_count, client| {
+            let plan = self.plan(&mut rand::thread_rng());
+            let upload = Upload {
+                client,
+                bucket: self.bucket.clone(),
+                key: format!("{}{}-{}", self.prefix, operation_count, chrono::Utc::now().timestamp_nanos_opt().unwrap_or(0)),
+                part_size: self.part_size,
+                part_concurrency: self.part_concurrency,
+                pattern: self.pattern.clone(),
+                counters: self.counters.clone(),
+            };
+            let expected_bytes = ((plan.order.len() + plan.overwrites.len()) * self.part_size) as u64;
+
+            println!(
+                "[BENCH] Spawning MULTIPART task {} for key: {} ({} parts, {} overwrites, {})",
+                operation_count,
+                upload.key,
+                plan.order.len(),
+                plan.overwrites.len(),
+                if plan.abort { "abort" } else { "complete" }
+            );
+            (expected_bytes, async move { upload.run(plan).await })
+        })
+        .await
+    }
+
+    fn print_summary(&self) {
+        let counters = &self.counters;
+        println!(
+            "Uploads completed: {}, aborted: {}, refused as too small: {}",
+            counters.completed.load(Ordering::Relaxed),
+            counters.aborted.load(Ordering::Relaxed),
+            counters.too_small.load(Ordering::Relaxed)
+        );
+        println!("Parts overwritten: {}", counters.overwrites.load(Ordering::Relaxed));
+        println!("Check failures: {}", counters.check_failures.load(Ordering::Relaxed));
+    }
+}
+
+/// One upload's lifecycle
+struct Upload {
+    client: S3Client,
+    bucket: String,
+    key: String,
+    part_size: usize,
+    part_concurrency: usize,
+    pattern: Bytes,
+    counters: Arc<StressCounters>,
+}
+
+impl Upload {
+    /// Data of one version of a part: the shared pattern stamped with the part
+    /// number and version, so every part and every overwrite has its own ETag
+    fn part_data(&self, part_number: i32, version: u32) -> Bytes {
+        let mut data = self.pattern.to_vec();
+        data[..4].copy_from_slice(&part_number.to_be_bytes());
+        data[4..8].copy_from_slice(&version.to_be_bytes());
+        Bytes::from(data)
+    }
+
+    /// Fail the operation and count the failure when an invariant does not hold
+    fn check(&self, holds: bool, what: impl FnOnce() -> String) -> Result<()> {
+        if holds {
+            return Ok(());
+        }
+        self.counters.check_failures.fetch_add(1, Ordering::Relaxed);
+        let message = format!("Check failed for key {}: {}", self.key, what());
+        println!("[MP-STRESS] {}", message);
+        anyhow::bail!(message)
+    }
+
+    /// Upload one version of each part, `part_concurrency` at a time, returning their ETags
+    async fn upload_parts(&self, upload_id: &str, parts: &[i32], version: u32) -> Result<Vec<(i32, String)>> {
+        futures::stream::iter(parts.iter().copied())
+            .map(|part_number| async move {
+                let output = self
+                    .client
+                    .upload_part()
+                    .bucket(&self.bucket)
+                    .key(&self.key)
+                    .upload_id(upload_id)
+                    .part_number(part_number)
+                    .body(self.part_data(part_number, version).into())
+                    .send()
+                    .await
+                    .with_context(|| format!("Failed to upload part {}", part_number))?;
+                Ok::<_, anyhow::Error>((part_number, output.e_tag().unwrap_or_default().to_string()))
+            })
+            .buffer_unordered(self.part_concurrency)
+            .try_collect()
+            .await
+    }
+
+    /// Every part listed by ListParts, following pagination
+    async fn list_parts(&self, upload_id: &str) -> Result<BTreeMap<i32, (String, i64)>> {
+        let mut listed = BTreeMap::new();
+        let mut marker: Option<String> = None;
+        loop {
+            let page = self
+                .client
+                .list_parts()
+                .bucket(&self.bucket)
+                .key(&self.key)
+                .upload_id(upload_id)
+                .set_part_number_marker(marker.take())
+                .send()
+                .await
+                .context("Failed to list parts")?;
+            for part in page.parts() {
+                let number = part.part_number().unwrap_or_default();
+                listed.insert(number, (part.e_tag().unwrap_or_default().to_string(), part.size().unwrap_or_default()));
+            }
+            match page.next_part_number_marker() {
+                Some(next) if page.is_truncated() == Some(true) => marker = Some(next.to_string()),
+                _ => return Ok(listed),
+            }
+        }
+    }
+
+    /// Whether ListMultipartUploads shows the upload, following pagination
+    async fn upload_is_listed(&self, upload_id: &str) -> Result<bool> {
+        let mut markers: (Option<String>, Option<String>) = (None, None);
+        loop {
+            let page = self
+                .client
+                .list_multipart_uploads()
+                .bucket(&self.bucket)
+                .prefix(&self.key)
+                .set_key_marker(markers.0.take())
+                .set_upload_id_marker(markers.1.take())
+                .send()
+                .await
+                .context("Failed to list multipart uploads")?;
+            if page.uploads().iter().any(|upload| upload.upload_id() == Some(upload_id)) {
+                return Ok(true);
+            }
+            if page.is_truncated() != Some(true) {
+                return Ok(false);
+            }
+            markers = (page.next_key_marker().map(str::to_string), page.next_upload_id_marker().map(str::to_string));
+        }
+    }
+
+    async fn run(self, plan: UploadPlan) -> Result<usize> {
+        let created = self
+            .client
+            .create_multipart_upload()
+            .bucket(&self.bucket)
+            .key(&self.key)
+            .send()
+            .await
+            .context("Failed to create multipart upload")?;
+        let upload_id = created.upload_id().context("No upload ID")?;
+
+        let result = self.exercise(upload_id, plan).await;
+        if result.is_err() {
+            // Best effort, so a failed upload does not leave its parts stored; the upload
+            // may already be completed or aborted, and the original error is what counts
+            if let Err(e) = self.client.abort_multipart_upload().bucket(&self.bucket).key(&self.key).upload_id(upload_id).send().await {
+                println!("[MP-STRESS] Failed to abort upload {} after an error: {}", upload_id, e);
+            }
+        }
+        result
+    }
+
+    /// Upload, overwrite and verify the parts, then abort or complete as planned
+    async fn exercise(&self, upload_id: &str, plan: UploadPlan) -> Result<usize> {
+        let mut etags: BTreeMap<i32, String> = self.upload_parts(upload_id, &plan.order, 0).await?.into_iter().collect();
+        let listed = self.upload_is_listed(upload_id).await?;
+        self.check(listed, || format!("upload {} missing from ListMultipartUploads", upload_id))?;
+
+        etags.extend(self.upload_parts(upload_id, &plan.overwrites, 1).await?);
+        self.counters.overwrites.fetch_add(plan.overwrites.len() as u64, Ordering::Relaxed);
+        let uploaded = (plan.order.len() + plan.overwrites.len()) * self.part_size;
+
+        let parts = self.list_parts(upload_id).await?;
+        self.check(parts.len() == etags.len(), || format!("ListParts shows {} parts, {} were uploaded", parts.len(), etags.len()))?;
+        for (number, etag) in &etags {
+            let listed = parts.get(number);
+            let expected = (etag.clone(), self.part_size as i64);
+            self.check(listed == Some(&expected), || format!("part {} listed as {:?}, expected {:?}", number, listed, expected))?;
+        }
+
+        if plan.abort {
+            self.client
+                .abort_multipart_upload()
+                .bucket(&self.bucket)
+                .key(&self.key)
+                .upload_id(upload_id)
+                .send()
+                .await
+                .context("Failed to abort multipart upload")?;
+            let after_abort = self.client.list_parts().bucket(&self.bucket).key(&self.key).upload_id(upload_id).send().await;
+            let gone = matches!(&after_abort, Err(e) if e.code() == Some("NoSuchUpload"));
+            self.check(gone, || format!("ListParts after abort returned {:?}", after_abort.map(|page| page.parts().len())))?;
+            self.counters.aborted.fetch_add(1, Ordering::Relaxed);
+            println!("[MP-STRESS] Aborted upload for key: {}", self.key);
+            return Ok(uploaded);
+        }
+
+        let completed_parts = etags
+            .iter()
+            .map(|(number, etag)| CompletedPart::builder().part_number(*number).e_tag(etag).build())
+            .collect();
+        let completed = self
+            .client
+            .complete_multipart_upload()
+            .bucket(&self.bucket)
+            .key(&self.key)
+            .upload_id(upload_id)
+            .multipart_upload(CompletedMultipartUpload::builder().set_parts(Some(completed_parts)).build())
+            .send()
+            .await;
+        match completed {
+            // Expected with parts below S3's minimum; the parts were still exercised, so it is not an error
+            Err(e) if e.code() == Some("EntityTooSmall") => {
+                self.client
+                    .abort_multipart_upload()
+                    .bucket(&self.bucket)
+                    .key(&self.key)
+                    .upload_id(upload_id)
+                    .send()
+                    .await
+                    .context("Failed to abort multipart upload refused as too small")?;
+                self.counters.too_small.fetch_add(1, Ordering::Relaxed);
+                println!("[MP-STRESS] Upload for key {} refused as too small, aborted", self.key);
+                return Ok(uploaded);
+            }
+            completed => {
+                completed.context("Failed to complete multipart upload")?;
+            }
+        }
+
+        let head = self
+            .client
+            .head_object()
+            .bucket(&self.bucket)
+            .key(&self.key)
+            .send()
+            .await
+            .context("Failed to head completed object")?;
+        let expected_size = (etags.len() * self.part_size) as i64;
+        let size = head.content_length().unwrap_or_default();
+        self.check(size == expected_size, || format!("completed object is {} bytes, expected {}", size, expected_size))?;
+        self.counters.completed.fetch_add(1, Ordering::Relaxed);
+        println!("[MP-STRESS] Completed upload for key: {} ({} bytes)", self.key, size);
+        Ok(uploaded)
+    }
+}
+
+pub(crate) async fn run_multipart_stress(args: MultipartStressArgs) -> Result<()> {
+    let concurrent = args.concurrent;
+
+    println!("Starting MULTIPART stress benchmark...");
+    println!("Endpoint: {}", args.conn.describe_endpoints());
+    println!("Client: {}", args.conn.client);
+    println!("HTTP: {}", args.conn.http);
+    println!("Credentials: {}", args.conn.credentials);
+    println!("Bucket: {}", args.conn.bucket);
+    println!("Stop after: {}", args.run.resolve());
+    println!("Concurrent uploads: {}", concurrent);
+
+    let workload = MultipartStressWorkload::new(args).await?;
+    workload.print_config();
+
+    let stats = workload.execute(concurrent).await?;
+    stats.print("MULTIPART");
+    workload.print_summary();
+
+    Ok(())
+}
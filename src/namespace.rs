@@ -0,0 +1,191 @@
+//! Key layouts for the objects PUT writes. Listing cost depends on how keys are
+//! spread over prefixes, so besides the flat `prefix{n}-{nanos}` names objects
+//! can be sharded under short hash prefixes, partitioned by hour the way Hive
+//! and log pipelines lay out data (`yyyy/mm/dd/hh/`), or placed in the leaf
+//! directories of a tree with a given depth and fan-out.
+
+use anyhow::Result;
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use clap::{Args, ValueEnum};
+use std::fmt;
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+/// Hourly partitions in a century, far more than any run writes and well within chrono's date range
+const MAX_DATE_PARTITIONS: u32 = 100 * 366 * 24;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub(crate) enum KeyLayout {
+    /// prefix{n}-{nanos}
+    Flat,
+    /// prefix{hash}/{n}-{nanos}, sharded by the leading hex digits of a hash of the name
+    Hashed,
+    /// prefix yyyy/mm/dd/hh/{n}-{nanos}, spread over hourly partitions ending at the current hour
+    Date,
+    /// prefix{d1}/{d2}/.../{n}-{nanos}, spread over the leaf directories of a tree
+    Tree,
+}
+
+/// How PUT names the objects it writes under the prefix
+#[derive(Args, Clone, Debug)]
+pub(crate) struct NamespaceArgs {
+    /// Key layout under the prefix
+    #[arg(long, value_enum, default_value = "flat")]
+    key_layout: KeyLayout,
+    /// Hex digits of the shard prefix in the hashed layout (2 gives 256 shards)
+    #[arg(long, default_value = "2")]
+    hash_chars: usize,
+    /// Hourly partitions the date layout spreads objects over
+    #[arg(long, default_value = "24")]
+    date_partitions: u32,
+    /// Directory levels above the objects in the tree layout
+    #[arg(long, default_value = "3")]
+    tree_depth: u32,
+    /// Subdirectories per directory in the tree layout
+    #[arg(long, default_value = "10")]
+    tree_fanout: u64,
+}
+
+impl NamespaceArgs {
+    pub(crate) fn validate(&self) -> Result<()> {
+        if !(1..=16).contains(&self.hash_chars) {
+            anyhow::bail!("--hash-chars must be between 1 and 16");
+        }
+        if !(1..=MAX_DATE_PARTITIONS).contains(&self.date_partitions) {
+            anyhow::bail!("--date-partitions must be between 1 and {}", MAX_DATE_PARTITIONS);
+        }
+        if self.tree_depth == 0 || self.tree_fanout == 0 {
+            anyhow::bail!("--tree-depth and --tree-fanout must be positive");
+        }
+        if self.tree_fanout.checked_pow(self.tree_depth).is_none() {
+            anyhow::bail!("--tree-fanout {} to the power of --tree-depth {} overflows", self.tree_fanout, self.tree_depth);
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for NamespaceArgs {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.key_layout {
+            KeyLayout::Flat => write!(f, "flat"),
+            KeyLayout::Hashed => write!(f, "hashed, {} shards", 16u128.pow(self.hash_chars as u32)),
+            KeyLayout::Date => write!(f, "date-partitioned, {} hourly partitions", self.date_partitions),
+            KeyLayout::Tree => write!(
+                f,
+                "tree, depth {}, fan-out {} ({} leaf directories)",
+                self.tree_depth,
+                self.tree_fanout,
+                self.tree_fanout.pow(self.tree_depth)
+            ),
+        }
+    }
+}
+
+/// Names objects for one run; the date layout's partitions are fixed when it is created
+#[derive(Clone, Debug)]
+pub(crate) struct Namespace {
+    args: NamespaceArgs,
+    now: DateTime<Utc>,
+}
+
+impl Namespace {
+    pub(crate) fn new(args: &NamespaceArgs) -> Self {
+        Namespace {
+            args: args.clone(),
+            now: Utc::now(),
+        }
+    }
+
+    /// Key of the `n`th object written under `prefix`
+    pub(crate) fn key(&self, prefix: &str, n: u64) -> String {
+        let name = format!("{}-{}", n, Utc::now().timestamp_nanos_opt().unwrap_or(0));
+        format!("{}{}{}", prefix, self.directory(n, &name), name)
+    }
+
+    /// Directory part of the key, empty or ending in `/`
+    fn directory(&self, n: u64, name: &str) -> String {
+        match self.args.key_layout {
+            KeyLayout::Flat => String::new(),
+            KeyLayout::Hashed => {
+                let mut hasher = DefaultHasher::new();
+                name.hash(&mut hasher);
+                let hex = format!("{:016x}", hasher.finish());
+                format!("{}/", &hex[..self.args.hash_chars])
+            }
+            KeyLayout::Date => {
+                let hours_back = n % self.args.date_partitions as u64;
+                (self.now - ChronoDuration::hours(hours_back as i64)).format("%Y/%m/%d/%H/").to_string()
+            }
+            KeyLayout::Tree => {
+                // Consecutive objects go to consecutive leaves, as base-fanout digits of n
+                let fanout = self.args.tree_fanout;
+                let width = (fanout - 1).to_string().len();
+                let mut leaf = n % fanout.pow(self.args.tree_depth);
+                let mut levels = vec![0; self.args.tree_depth as usize];
+                for level in levels.iter_mut().rev() {
+                    *level = leaf % fanout;
+                    leaf /= fanout;
+                }
+                levels.iter().map(|digit| format!("{:0width$}/", digit, width = width)).collect()
+            }
+        }
+    }
+}
+
+impl fmt::Display for Namespace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.args.fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn namespace(key_layout: KeyLayout) -> Namespace {
+        let args = NamespaceArgs {
+            key_layout,
+            hash_chars: 3,
+            date_partitions: 3,
+            tree_depth: 3,
+            tree_fanout: 12,
+        };
+        args.validate().unwrap();
+        Namespace::new(&args)
+    }
+
+    /// Key with the unique `{n}-{nanos}` name stripped
+    fn directory(namespace: &Namespace, n: u64) -> String {
+        let key = namespace.key("p/", n);
+        key[..key.rfind('/').unwrap() + 1].to_string()
+    }
+
+    #[test]
+    fn lays_out_keys() {
+        assert!(namespace(KeyLayout::Flat).key("p/", 7).starts_with("p/7-"));
+
+        let shard = directory(&namespace(KeyLayout::Hashed), 0);
+        assert_eq!(shard.len(), "p/abc/".len());
+        assert!(shard[2..5].chars().all(|c| c.is_ascii_hexdigit()));
+
+        let date = namespace(KeyLayout::Date);
+        let now = date.now.format("p/%Y/%m/%d/%H/").to_string();
+        let earlier = (date.now - ChronoDuration::hours(2)).format("p/%Y/%m/%d/%H/").to_string();
+        assert_eq!([directory(&date, 0), directory(&date, 2), directory(&date, 3)], [now.clone(), earlier, now]);
+
+        let tree = namespace(KeyLayout::Tree);
+        assert_eq!(directory(&tree, 0), "p/00/00/00/");
+        assert_eq!(directory(&tree, 13), "p/00/01/01/");
+        assert_eq!(directory(&tree, 12 * 12 * 12 + 11), "p/00/00/11/");
+    }
+
+    #[test]
+    fn rejects_partitions_beyond_the_date_range() {
+        let mut args = namespace(KeyLayout::Date).args;
+        args.date_partitions = MAX_DATE_PARTITIONS;
+        let date = Namespace::new(&args);
+        let oldest = date.now - ChronoDuration::hours(MAX_DATE_PARTITIONS as i64 - 1);
+        assert_eq!(directory(&date, MAX_DATE_PARTITIONS as u64 - 1), oldest.format("p/%Y/%m/%d/%H/").to_string());
+        args.date_partitions = 4_000_000_000;
+        assert!(args.validate().is_err());
+    }
+}
@@ -0,0 +1,332 @@
+//! Presigned URLs. Browsers and CDNs fetch objects through URLs presigned
+//! elsewhere rather than signing requests themselves, so the `presigned` command
+//! presigns a GET or PUT URL per key through the SDK up front and then drives
+//! load with a plain HTTP client against those URLs. With `--compare-signed` the
+//! same operations are then repeated as signed SDK requests, so the throughput
+//! and the signing cost of the two paths can be put side by side.
+
+use super::{connect, discover_objects, drive, generate_random_data, ConnectionArgs, OpUnit, RunArgs, Stats};
+use crate::endpoints::EndpointPool;
+use crate::transport::PlainClient;
+use anyhow::{Context, Result};
+use aws_sdk_s3::presigning::{PresignedRequest, PresigningConfig};
+use aws_sdk_s3::primitives::ByteStream;
+use aws_sdk_s3::Client as S3Client;
+use bytes::Bytes;
+use clap::{Args, ValueEnum};
+use http_body_util::{BodyExt, Full};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub(crate) enum PresignedOperation {
+    /// GET the existing objects under the prefix
+    Get,
+    /// PUT --object-size objects to --keys keys under the prefix
+    Put,
+}
+
+#[derive(Args)]
+pub(crate) struct PresignedArgs {
+    #[command(flatten)]
+    conn: ConnectionArgs,
+    /// Stop condition, applied to each of the presigned and signed runs
+    #[command(flatten)]
+    run: RunArgs,
+    #[arg(long, default_value = "10")]
+    concurrent: usize,
+    #[arg(long, value_enum, default_value = "get")]
+    operation: PresignedOperation,
+    /// Prefix of the objects read, or of the keys written
+    #[arg(long, default_value = "test-object/")]
+    prefix: String,
+    /// Keys PUT writes to in turn, one presigned URL each
+    #[arg(long, default_value = "100")]
+    keys: usize,
+    #[arg(long, default_value = "1048576")]
+    object_size: usize,
+    /// How long the URLs stay valid; S3 allows at most 7 days
+    #[arg(long, default_value = "1h", value_parser = humantime::parse_duration)]
+    expires_in: Duration,
+    /// Repeat the operations as signed SDK requests afterwards and compare
+    #[arg(long)]
+    compare_signed: bool,
+}
+
+/// Requests are not issued this close to the URLs' expiry, so that the ones in
+/// flight when issuing stops still arrive while the URLs are valid
+const EXPIRY_MARGIN: Duration = Duration::from_secs(5);
+
+/// Time spent presigning the URLs of a run
+struct Presigning {
+    urls: usize,
+    elapsed: Duration,
+    /// SigV4 signing alone, as measured by the client's request counters
+    signing: Duration,
+}
+
+impl Presigning {
+    fn per_url(&self, total: Duration) -> f64 {
+        total.as_secs_f64() * 1e6 / self.urls.max(1) as f64
+    }
+}
+
+/// One URL per object, presigned for the single endpoint
+pub(crate) struct PresignedWorkload {
+    endpoints: Arc<EndpointPool>,
+    plain: PlainClient,
+    bucket: String,
+    run: RunArgs,
+    operation: PresignedOperation,
+    /// Keys with the size GET expects, or the size PUT writes
+    keys: Vec<(String, u64)>,
+    data: Bytes,
+    requests: Vec<PresignedRequest>,
+    presigning: Presigning,
+    /// When the first URL presigned stops being valid
+    expires_at: Instant,
+}
+
+impl PresignedWorkload {
+    pub(crate) async fn prepare(args: PresignedArgs) -> Result<Self> {
+        if args.conn.endpoints()?.len() > 1 {
+            anyhow::bail!("Presigned URLs are bound to one host; give a single --endpoint");
+        }
+        if args.operation == PresignedOperation::Put && args.keys == 0 {
+            anyhow::bail!("--keys must be positive");
+        }
+        if let Some(duration) = args.run.resolve().duration {
+            if duration + EXPIRY_MARGIN >= args.expires_in {
+                anyhow::bail!(
+                    "URLs valid for {} would expire during the {}s run; raise --expires-in or shorten --duration-secs",
+                    humantime::format_duration(args.expires_in),
+                    duration.as_secs()
+                );
+            }
+        }
+        let config = PresigningConfig::expires_in(args.expires_in).context("Invalid --expires-in")?;
+        let endpoints = connect(&args.conn).await?;
+        let connect_timeout = args.conn.client.timeout_config().connect_timeout();
+        let plain = PlainClient::new(&args.conn.tls, &args.conn.http, connect_timeout, endpoints.connections.clone())?;
+        let bucket = args.conn.bucket;
+        let client = endpoints.primary();
+
+        let (keys, data) = match args.operation {
+            PresignedOperation::Get => {
+                println!("Listing objects with prefix '{}'...", args.prefix);
+                let objects = discover_objects(client, &bucket, &args.prefix).await?;
+                if objects.is_empty() {
+                    anyhow::bail!("No objects found with prefix '{}'. Please run PUT benchmark first.", args.prefix);
+                }
+                println!("Found {} objects", objects.len());
+                (objects.into_iter().map(|object| (object.key, object.size)).collect::<Vec<_>>(), Bytes::new())
+            }
+            PresignedOperation::Put => {
+                let keys = (0..args.keys)
+                    .map(|i| (format!("{}presigned-{:05}", args.prefix, i), args.object_size as u64))
+                    .collect();
+                (keys, Bytes::from(generate_random_data(args.object_size)))
+            }
+        };
+
+        // One at a time, so the timings are the CPU cost of presigning
+        println!("Presigning {} URLs...", keys.len());
+        let signing_before = endpoints.requests.snapshot();
+        let start = Instant::now();
+        let mut requests = Vec::with_capacity(keys.len());
+        for (key, _) in &keys {
+            let request = match args.operation {
+                PresignedOperation::Get => client.get_object().bucket(&bucket).key(key).presigned(config.clone()).await.map_err(anyhow::Error::from),
+                PresignedOperation::Put => client.put_object().bucket(&bucket).key(key).presigned(config.clone()).await.map_err(anyhow::Error::from),
+            };
+            requests.push(request.with_context(|| format!("Failed to presign {}", key))?);
+        }
+        let expires_at = start + args.expires_in;
+        let presigning = Presigning {
+            urls: requests.len(),
+            elapsed: start.elapsed(),
+            signing: endpoints.requests.since(&signing_before).values().map(|counts| counts.signing).sum(),
+        };
+
+        Ok(PresignedWorkload {
+            endpoints,
+            plain,
+            bucket,
+            run: args.run,
+            operation: args.operation,
+            keys,
+            data,
+            requests,
+            presigning,
+            expires_at,
+        })
+    }
+
+    /// The stop condition cut short where the URLs expire: a run limited only by
+    /// --ops or --total-bytes gets a time limit, and one whose time limit would
+    /// outlast the URLs, given the time spent since presigning, is refused
+    fn presigned_run(&self) -> Result<RunArgs> {
+        let lifetime = self.expires_at.saturating_duration_since(Instant::now()).saturating_sub(EXPIRY_MARGIN).as_secs();
+        if lifetime == 0 {
+            anyhow::bail!("The presigned URLs have expired; raise --expires-in");
+        }
+        match self.run.resolve().duration {
+            Some(duration) if duration.as_secs() > lifetime => {
+                anyhow::bail!("The URLs expire in {}s, before the {}s run would end; raise --expires-in", lifetime, duration.as_secs())
+            }
+            Some(_) => Ok(self.run),
+            None => {
+                println!("Stopping after at most {}s, when the URLs expire", lifetime);
+                Ok(RunArgs { duration_secs: Some(lifetime), ..self.run })
+            }
+        }
+    }
+
+    fn label(&self, presigned: bool) -> &'static str {
+        match (self.operation, presigned) {
+            (PresignedOperation::Get, true) => "PRESIGNED-GET",
+            (PresignedOperation::Put, true) => "PRESIGNED-PUT",
+            (PresignedOperation::Get, false) => "SIGNED-GET",
+            (PresignedOperation::Put, false) => "SIGNED-PUT",
+        }
+    }
+
+    pub(crate) fn print_config(&self) {
+        let presigning = &self.presigning;
+        println!("Operation: {}", self.label(true));
+        println!(
+            "Presigned {} URLs in {:.1} ms: {:.1} µs per URL, {:.1} µs of it signing",
+            presigning.urls,
+            presigning.elapsed.as_secs_f64() * 1000.0,
+            presigning.per_url(presigning.elapsed),
+            presigning.per_url(presigning.signing)
+        );
+    }
+
+    /// Cycle through the presigned URLs with the plain HTTP client
+    pub(crate) async fn execute_presigned(&self, concurrent: usize) -> Result<Stats> {
+        let label = self.label(true);
+        drive(label, concurrent, self.presigned_run()?, Duration::from_millis(10), OpUnit::Bytes, &self.endpoints, |operation_count, _| {
+            let index = operation_count as usize % self.requests.len();
+            let (key, size) = self.keys[index].clone();
+            let request = self.requests[index].make_http_1x_request(Full::new(self.data.clone()));
+            let plain = self.plain.clone();
+            let operation = self.operation;
+
+            println!("[BENCH] Spawning {} task {} for key: {}", label, operation_count, key);
+            let op = async move {
+                let response = plain.send(request).await.with_context(|| format!("Failed to send request for {}", key))?;
+                let status = response.status();
+                let mut body = response.into_body();
+                let mut received = 0;
+                while let Some(frame) = body.frame().await {
+                    received += frame?.data_ref().map_or(0, Bytes::len);
+                }
+                if !status.is_success() {
+                    anyhow::bail!("Presigned request for {} failed with status {}", key, status);
+                }
+                match operation {
+                    PresignedOperation::Get => Ok(received),
+                    PresignedOperation::Put => Ok(size as usize),
+                }
+            };
+            (size, op)
+        })
+        .await
+    }
+
+    /// The same operations as signed SDK requests
+    pub(crate) async fn execute_signed(&self, concurrent: usize) -> Result<Stats> {
+        let label = self.label(false);
+        drive(label, concurrent, self.run, Duration::from_millis(10), OpUnit::Bytes, &self.endpoints, |operation_count, client| {
+            let (key, size) = self.keys[operation_count as usize % self.keys.len()].clone();
+            let bucket = self.bucket.clone();
+            let operation = self.operation;
+            let data = self.data.clone();
+
+            println!("[BENCH] Spawning {} task {} for key: {}", label, operation_count, key);
+            let op = async move {
+                match operation {
+                    PresignedOperation::Get => signed_get(&client, &bucket, &key).await,
+                    PresignedOperation::Put => signed_put(&client, &bucket, &key, data).await,
+                }
+            };
+            (size, op)
+        })
+        .await
+    }
+
+    /// Throughput, latency and signing cost per operation of the two runs. Only
+    /// the signed requests are retried and bounded by the client's timeouts.
+    pub(crate) fn print_comparison(&self, presigned: &Stats, signed: &Stats) {
+        let presigning = &self.presigning;
+        // URLs are reused, so presigning is spread over every request sent with them
+        let presigned_signing = presigning.signing.as_secs_f64() * 1e6 / presigned.operations.max(1) as f64;
+        let signed_requests: u64 = signed.requests.values().map(|counts| counts.requests + counts.retries).sum();
+        let signed_signing = signed.requests.values().map(|counts| counts.signing).sum::<Duration>().as_secs_f64() * 1e6
+            / signed_requests.max(1) as f64;
+
+        println!("\n=== Presigned vs Signed ===");
+        println!("{:<14} {:>10} {:>10} {:>10} {:>10} {:>14}", "Mode", "Ops/sec", "MB/s", "p50 ms", "p99 ms", "Signing µs/op");
+        for (label, stats, signing) in [(self.label(true), presigned, presigned_signing), (self.label(false), signed, signed_signing)] {
+            println!(
+                "{:<14} {:>10.2} {:>10.2} {:>10.2} {:>10.2} {:>14.1}",
+                label,
+                stats.ops_per_sec(),
+                stats.mb_per_sec(),
+                stats.percentile_ms(0.5),
+                stats.percentile_ms(0.99),
+                signing
+            );
+        }
+        println!("{} requests were sent once, with no retries and no timeouts but --connect-timeout", self.label(true));
+    }
+}
+
+async fn signed_get(client: &S3Client, bucket: &str, key: &str) -> Result<usize> {
+    let resp = client.get_object().bucket(bucket).key(key).send().await.context("Failed to get object")?;
+    let data = resp.body.collect().await.context("Failed to read object body")?;
+    Ok(data.into_bytes().len())
+}
+
+async fn signed_put(client: &S3Client, bucket: &str, key: &str, data: Bytes) -> Result<usize> {
+    let size = data.len();
+    client
+        .put_object()
+        .bucket(bucket)
+        .key(key)
+        .body(ByteStream::from(data))
+        .send()
+        .await
+        .context("Failed to put object")?;
+    Ok(size)
+}
+
+pub(crate) async fn run_presigned_benchmark(args: PresignedArgs) -> Result<()> {
+    let concurrent = args.concurrent;
+    let compare_signed = args.compare_signed;
+
+    println!("Starting PRESIGNED benchmark...");
+    println!("Endpoint: {}", args.conn.describe_endpoints());
+    println!("Client: {} (signed requests only)", args.conn.client);
+    println!("Presigned requests: sent once, no retries, no timeouts but --connect-timeout");
+    println!("HTTP: {}", args.conn.http);
+    println!("Credentials: {}", args.conn.credentials);
+    println!("Bucket: {}", args.conn.bucket);
+    println!("Stop after: {}", args.run.resolve());
+    println!("Concurrent operations: {}", concurrent);
+
+    let workload = PresignedWorkload::prepare(args).await?;
+    workload.print_config();
+
+    let presigned = workload.execute_presigned(concurrent).await?;
+    presigned.print(workload.label(true));
+
+    if compare_signed {
+        let signed = workload.execute_signed(concurrent).await?;
+        signed.print(workload.label(false));
+        workload.print_comparison(&presigned, &signed);
+    }
+
+    Ok(())
+}
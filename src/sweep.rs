@@ -0,0 +1,240 @@
+//! Concurrency sweep: run one workload at a series of concurrency levels for a
+//! fixed duration each and tabulate throughput and tail latency per level, so the
+//! knee of the throughput curve can be read off a single run.
+
+use super::{Stats, Workload};
+use anyhow::{Context, Result};
+use clap::{Args, ValueEnum};
+use std::fs::File;
+use std::io::Write;
+use std::path::PathBuf;
+
+/// How the next concurrency level is derived from the previous one
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub(crate) enum StepMode {
+    /// Double the concurrency at each step
+    Exponential,
+    /// Add --step to the concurrency at each step
+    Linear,
+}
+
+#[derive(Args)]
+pub(crate) struct SweepArgs {
+    /// Explicit concurrency levels, e.g. 1,8,64 (overrides --start/--max/--step-mode)
+    #[arg(long, value_delimiter = ',')]
+    levels: Vec<usize>,
+    /// First concurrency level
+    #[arg(long, default_value = "1")]
+    start: usize,
+    /// Highest concurrency level
+    #[arg(long, default_value = "512")]
+    max: usize,
+    #[arg(long, value_enum, default_value = "exponential")]
+    step_mode: StepMode,
+    /// Increment for the linear step mode
+    #[arg(long, default_value = "8")]
+    step: usize,
+    /// Write the per-level results to this CSV file
+    #[arg(long)]
+    csv: Option<PathBuf>,
+    /// Stop once ops/sec improves by less than this fraction over the best level so far (e.g. 0.05)
+    #[arg(long)]
+    min_improvement: Option<f64>,
+    /// Consecutive non-improving levels tolerated before --min-improvement stops the sweep
+    #[arg(long, default_value = "1")]
+    patience: usize,
+    /// Stop once a level's error rate exceeds this fraction (e.g. 0.01)
+    #[arg(long)]
+    max_error_rate: Option<f64>,
+    /// Workload to run at each level; its --duration-secs/--ops apply per level and --concurrent is ignored
+    #[command(subcommand)]
+    workload: Workload,
+}
+
+impl SweepArgs {
+    fn levels(&self) -> Vec<usize> {
+        if !self.levels.is_empty() {
+            return self.levels.clone();
+        }
+        let mut levels = Vec::new();
+        let mut next = Some(self.start.max(1));
+        while let Some(level) = next.filter(|&level| level <= self.max) {
+            levels.push(level);
+            // A step past usize::MAX is necessarily past --max too
+            next = match self.step_mode {
+                StepMode::Exponential => level.checked_mul(2),
+                StepMode::Linear => level.checked_add(self.step.max(1)),
+            };
+        }
+        levels
+    }
+}
+
+/// Result of running the workload at one concurrency level
+struct LevelResult {
+    concurrency: usize,
+    stats: Stats,
+}
+
+const CSV_HEADER: &str = "concurrency,operations,errors,error_rate,ops_per_sec,mb_per_sec,avg_ms,p50_ms,p90_ms,p99_ms,max_ms";
+
+impl LevelResult {
+    fn csv_row(&self) -> String {
+        let s = &self.stats;
+        let successful = s.operations - s.errors;
+        let avg_ms = if successful > 0 { s.total_latency_ms / successful as f64 } else { 0.0 };
+        format!(
+            "{},{},{},{:.4},{:.2},{:.2},{:.2},{:.2},{:.2},{:.2},{:.2}",
+            self.concurrency,
+            s.operations,
+            s.errors,
+            s.error_rate(),
+            s.ops_per_sec(),
+            s.mb_per_sec(),
+            avg_ms,
+            s.percentile_ms(0.5),
+            s.percentile_ms(0.9),
+            s.percentile_ms(0.99),
+            s.latency.max() as f64 / 1000.0
+        )
+    }
+}
+
+fn print_table(label: &str, results: &[LevelResult]) {
+    println!("\n=== {} Concurrency Sweep Results ===", label);
+    println!(
+        "{:>11} {:>10} {:>10} {:>12} {:>10} {:>10} {:>10}",
+        "concurrency", "ops/sec", "MB/s", "p50 ms", "p99 ms", "errors", "err rate"
+    );
+    for result in results {
+        let s = &result.stats;
+        println!(
+            "{:>11} {:>10.2} {:>10.2} {:>12.2} {:>10.2} {:>10} {:>9.2}%",
+            result.concurrency,
+            s.ops_per_sec(),
+            s.mb_per_sec(),
+            s.percentile_ms(0.5),
+            s.percentile_ms(0.99),
+            s.errors,
+            s.error_rate() * 100.0
+        );
+    }
+    if let Some(best) = results
+        .iter()
+        .max_by(|a, b| a.stats.ops_per_sec().total_cmp(&b.stats.ops_per_sec()))
+    {
+        println!(
+            "Peak throughput: {:.2} ops/sec at concurrency {} (p99 {:.2} ms)",
+            best.stats.ops_per_sec(),
+            best.concurrency,
+            best.stats.percentile_ms(0.99)
+        );
+    }
+}
+
+fn write_csv(path: &PathBuf, results: &[LevelResult]) -> Result<()> {
+    let mut file = File::create(path).with_context(|| format!("Failed to create {}", path.display()))?;
+    writeln!(file, "{}", CSV_HEADER)?;
+    for result in results {
+        writeln!(file, "{}", result.csv_row())?;
+    }
+    Ok(())
+}
+
+pub(crate) async fn run_sweep(args: SweepArgs) -> Result<()> {
+    let levels = args.levels();
+    if levels.is_empty() {
+        anyhow::bail!("No concurrency levels to sweep: --start {} exceeds --max {}", args.start, args.max);
+    }
+
+    println!("Starting concurrency sweep...");
+    println!("Endpoint: {}", args.workload.endpoint());
+    println!("Levels: {:?}", levels);
+
+    let SweepArgs {
+        csv,
+        min_improvement,
+        patience,
+        max_error_rate,
+        workload,
+        ..
+    } = args;
+    let workload = workload.prepare().await?;
+    let label = workload.label();
+    workload.print_config();
+
+    let mut results: Vec<LevelResult> = Vec::new();
+    let mut best_ops_per_sec = 0.0f64;
+    let mut stalled_levels = 0;
+
+    for (step, &concurrency) in levels.iter().enumerate() {
+        println!("\n[SWEEP] Level {} of {}: {} concurrent {} operations", step + 1, levels.len(), concurrency, label);
+        let stats = workload.execute(concurrency).await?;
+        stats.print(&format!("{} @ {} concurrent", label, concurrency));
+
+        let ops_per_sec = stats.ops_per_sec();
+        let error_rate = stats.error_rate();
+        results.push(LevelResult { concurrency, stats });
+
+        if let Some(limit) = max_error_rate {
+            if error_rate > limit {
+                println!("[SWEEP] Error rate {:.2}% exceeds {:.2}%, stopping", error_rate * 100.0, limit * 100.0);
+                break;
+            }
+        }
+
+        if let Some(fraction) = min_improvement {
+            if best_ops_per_sec > 0.0 && ops_per_sec < best_ops_per_sec * (1.0 + fraction) {
+                stalled_levels += 1;
+                if stalled_levels >= patience {
+                    println!(
+                        "[SWEEP] Throughput improved by less than {:.1}% for {} level(s), stopping",
+                        fraction * 100.0,
+                        stalled_levels
+                    );
+                    break;
+                }
+            } else {
+                stalled_levels = 0;
+            }
+        }
+        best_ops_per_sec = best_ops_per_sec.max(ops_per_sec);
+    }
+
+    print_table(label, &results);
+
+    if let Some(path) = csv {
+        write_csv(&path, &results)?;
+        println!("Wrote sweep results to {}", path.display());
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Cli, Commands};
+    use clap::Parser;
+
+    fn sweep_args(args: &[&str]) -> SweepArgs {
+        let workload = ["list", "--endpoint", "http://localhost:9000", "--bucket", "bench", "--anonymous"];
+        let args = ["s3-load-gen", "sweep"].iter().chain(args).chain(&workload);
+        match Cli::try_parse_from(args).unwrap().command {
+            Commands::Sweep(args) => args,
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn steps_through_levels_without_overflowing() {
+        assert_eq!(sweep_args(&["--start", "3", "--max", "30"]).levels(), [3, 6, 12, 24]);
+        assert_eq!(sweep_args(&["--step-mode", "linear", "--step", "10", "--max", "25"]).levels(), [1, 11, 21]);
+        assert_eq!(sweep_args(&["--levels", "4,2"]).levels(), [4, 2]);
+
+        let max = usize::MAX.to_string();
+        assert_eq!(sweep_args(&["--max", &max]).levels().len(), usize::BITS as usize);
+        let start = (usize::MAX - 5).to_string();
+        assert_eq!(sweep_args(&["--step-mode", "linear", "--start", &start, "--max", &max]).levels().len(), 1);
+    }
+}
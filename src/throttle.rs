@@ -0,0 +1,248 @@
+//! Bandwidth limits for emulating slow clients. Object bodies are paced with
+//! token buckets: one shared by every operation of the run and/or one per
+//! request stream, i.e. per connection while the request holds it. Uploads are
+//! paced as the body is sent, downloads as the response body is consumed, so a
+//! limited request keeps its connection open for as long as a slow client would.
+
+use anyhow::{Context, Result};
+use aws_sdk_s3::primitives::ByteStream;
+use aws_smithy_types::body::SdkBody;
+use bytes::Bytes;
+use clap::Args;
+use hyper::body::{Body, Frame, SizeHint};
+use std::convert::Infallible;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::task::{ready, Context as TaskContext, Poll};
+use std::time::Duration;
+use tokio::time::{Instant, Sleep};
+
+/// Largest piece of an upload body sent at once; also the burst a bucket allows
+const CHUNK_SIZE: usize = 16 * 1024;
+
+/// Bytes per second, parsed from a number with an optional K, M or G suffix
+/// (powers of 1024, as elsewhere in this tool) and an optional `B/s`, e.g. `10M` or `10MB/s`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct ByteRate(pub(crate) u64);
+
+impl FromStr for ByteRate {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let value = s.trim();
+        let value = value.strip_suffix("/s").unwrap_or(value);
+        let value = value.strip_suffix(['B', 'b']).unwrap_or(value);
+        let (number, multiplier) = match value.chars().last().map(|c| c.to_ascii_uppercase()) {
+            Some('K') => (&value[..value.len() - 1], 1024),
+            Some('M') => (&value[..value.len() - 1], 1024 * 1024),
+            Some('G') => (&value[..value.len() - 1], 1024 * 1024 * 1024),
+            _ => (value, 1),
+        };
+        let number: f64 = number.trim().parse().map_err(|_| format!("invalid bandwidth '{}'", s))?;
+        let rate = (number * multiplier as f64) as u64;
+        if rate == 0 {
+            return Err(format!("bandwidth must be positive, got '{}'", s));
+        }
+        Ok(ByteRate(rate))
+    }
+}
+
+impl fmt::Display for ByteRate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:.2} MB/s", self.0 as f64 / 1_048_576.0)
+    }
+}
+
+/// Bandwidth limits applied to object bodies
+#[derive(Args, Clone, Debug)]
+pub(crate) struct BandwidthArgs {
+    /// Limit on all object data sent and received together (e.g. 100M for 100 MiB/s)
+    #[arg(long)]
+    bandwidth_limit: Option<ByteRate>,
+    /// Limit on each request's body stream, like a client on a slow link (e.g. 10M)
+    #[arg(long)]
+    bandwidth_limit_per_connection: Option<ByteRate>,
+}
+
+impl fmt::Display for BandwidthArgs {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.bandwidth_limit, self.bandwidth_limit_per_connection) {
+            (None, None) => write!(f, "unlimited"),
+            (Some(global), None) => write!(f, "{} total", global),
+            (None, Some(connection)) => write!(f, "{} per connection", connection),
+            (Some(global), Some(connection)) => write!(f, "{} total, {} per connection", global, connection),
+        }
+    }
+}
+
+/// Token bucket that lets a caller go into debt: a reservation always succeeds
+/// and returns how long to wait before the reserved bytes are due
+#[derive(Debug)]
+struct TokenBucket {
+    rate: f64,
+    state: Mutex<(f64, Instant)>,
+}
+
+impl TokenBucket {
+    fn new(rate: ByteRate) -> Self {
+        TokenBucket {
+            rate: rate.0 as f64,
+            state: Mutex::new((CHUNK_SIZE as f64, Instant::now())),
+        }
+    }
+
+    fn reserve(&self, bytes: usize) -> Duration {
+        let mut state = self.state.lock().unwrap();
+        let (tokens, refilled_at) = &mut *state;
+        let now = Instant::now();
+        *tokens = (*tokens + now.duration_since(*refilled_at).as_secs_f64() * self.rate).min(CHUNK_SIZE as f64);
+        *refilled_at = now;
+        *tokens -= bytes as f64;
+        if *tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-*tokens / self.rate)
+        }
+    }
+}
+
+/// Limits of one run; cheap to clone into operations
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Throttle {
+    global: Option<Arc<TokenBucket>>,
+    per_connection: Option<ByteRate>,
+}
+
+impl Throttle {
+    pub(crate) fn new(args: &BandwidthArgs) -> Self {
+        Throttle {
+            global: args.bandwidth_limit.map(|rate| Arc::new(TokenBucket::new(rate))),
+            per_connection: args.bandwidth_limit_per_connection,
+        }
+    }
+
+    pub(crate) fn is_unlimited(&self) -> bool {
+        self.global.is_none() && self.per_connection.is_none()
+    }
+
+    /// Buckets for one request stream: the shared one plus a fresh per-connection one
+    pub(crate) fn stream(&self) -> StreamThrottle {
+        StreamThrottle {
+            global: self.global.clone(),
+            own: self.per_connection.map(TokenBucket::new),
+        }
+    }
+
+    /// Upload body for `data`, paced while it is sent; every retry attempt is paced again
+    pub(crate) fn upload_body(&self, data: Bytes) -> ByteStream {
+        if self.is_unlimited() {
+            return ByteStream::from(data);
+        }
+        let throttle = self.clone();
+        ByteStream::new(SdkBody::retryable(move || {
+            SdkBody::from_body_1_x(ThrottledBody {
+                data: data.clone(),
+                throttle: throttle.stream(),
+                delay: None,
+            })
+        }))
+    }
+
+    /// Consume a download body at the allowed pace, returning its length
+    pub(crate) async fn read_body(&self, mut body: ByteStream) -> Result<usize> {
+        if self.is_unlimited() {
+            let data = body.collect().await.context("Failed to read body")?;
+            return Ok(data.into_bytes().len());
+        }
+        let throttle = self.stream();
+        let mut size = 0;
+        while let Some(chunk) = body.try_next().await.context("Failed to read body")? {
+            size += chunk.len();
+            let wait = throttle.reserve(chunk.len());
+            if !wait.is_zero() {
+                tokio::time::sleep(wait).await;
+            }
+        }
+        Ok(size)
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct StreamThrottle {
+    global: Option<Arc<TokenBucket>>,
+    own: Option<TokenBucket>,
+}
+
+impl StreamThrottle {
+    /// Wait owed for `bytes` under the stricter of the limits
+    pub(crate) fn reserve(&self, bytes: usize) -> Duration {
+        let global = self.global.as_ref().map_or(Duration::ZERO, |bucket| bucket.reserve(bytes));
+        let own = self.own.as_ref().map_or(Duration::ZERO, |bucket| bucket.reserve(bytes));
+        global.max(own)
+    }
+}
+
+/// Upload body handing out `CHUNK_SIZE` pieces no faster than the limits allow
+struct ThrottledBody {
+    data: Bytes,
+    throttle: StreamThrottle,
+    delay: Option<Pin<Box<Sleep>>>,
+}
+
+impl Body for ThrottledBody {
+    type Data = Bytes;
+    type Error = Infallible;
+
+    fn poll_frame(mut self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<Option<Result<Frame<Bytes>, Infallible>>> {
+        if let Some(delay) = &mut self.delay {
+            ready!(delay.as_mut().poll(cx));
+            self.delay = None;
+        }
+        if self.data.is_empty() {
+            return Poll::Ready(None);
+        }
+        let length = self.data.len().min(CHUNK_SIZE);
+        let chunk = self.data.split_to(length);
+        let wait = self.throttle.reserve(length);
+        if !wait.is_zero() {
+            self.delay = Some(Box::pin(tokio::time::sleep(wait)));
+        }
+        Poll::Ready(Some(Ok(Frame::data(chunk))))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        SizeHint::with_exact(self.data.len() as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_byte_rates() {
+        assert_eq!("1048576".parse(), Ok(ByteRate(1_048_576)));
+        assert_eq!("10M".parse(), Ok(ByteRate(10 * 1_048_576)));
+        assert_eq!("10MB/s".parse(), Ok(ByteRate(10 * 1_048_576)));
+        assert_eq!("512k".parse(), Ok(ByteRate(512 * 1024)));
+        assert_eq!("1.5G".parse(), Ok(ByteRate(1536 * 1_048_576)));
+        for invalid in ["", "0", "fast", "10X"] {
+            assert!(invalid.parse::<ByteRate>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn bucket_paces_reservations() {
+        let bucket = TokenBucket::new(ByteRate(1024 * 1024));
+        assert_eq!(bucket.reserve(CHUNK_SIZE), Duration::ZERO);
+        let wait = bucket.reserve(512 * 1024);
+        assert!(wait > Duration::from_millis(490) && wait <= Duration::from_millis(500), "{:?}", wait);
+    }
+}
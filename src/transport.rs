@@ -0,0 +1,693 @@
+//! HTTP client the SDK sends requests through. It replaces the SDK's default
+//! hyper client so TLS can be configured beyond what the SDK exposes: a custom
+//! CA bundle, a client certificate for mutual TLS, and skipping verification.
+//! It also exposes the connection pool and protocol settings, can pin hostnames
+//! to addresses and bind source addresses, and counts the connections it opens
+//! so connection reuse shows up in the results. The same settings back a plain
+//! client for requests the SDK does not sign, such as presigned URLs.
+
+use crate::client::BodySigning;
+use crate::upload::generation_time;
+use anyhow::{Context, Result};
+use aws_sdk_s3::config::{HttpClient, RuntimeComponents, SharedHttpClient};
+use aws_smithy_runtime_api::client::http::{HttpConnector, HttpConnectorFuture, HttpConnectorSettings, SharedHttpConnector};
+use aws_smithy_runtime_api::client::orchestrator::{HttpRequest, HttpResponse};
+use aws_smithy_runtime_api::client::result::ConnectorError;
+use aws_smithy_types::body::SdkBody;
+use bytes::Bytes;
+use clap::{Args, ValueEnum};
+use http_body_util::Full;
+use hyper::body::{Body, Frame, Incoming, SizeHint};
+use hyper::{Request, Response, Uri};
+use hyper_rustls::{HttpsConnector, HttpsConnectorBuilder};
+use hyper_util::client::legacy::connect::HttpConnector as TcpConnector;
+use hyper_util::client::legacy::Client;
+use hyper_util::rt::{TokioExecutor, TokioTimer};
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{verify_tls12_signature, verify_tls13_signature, CryptoProvider};
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName, UnixTime};
+use rustls::{ClientConfig, DigitallySignedStruct, RootCertStore, SignatureScheme};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::future::Future;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context as TaskContext, Poll};
+use std::time::{Duration, Instant};
+use tokio::time::Sleep;
+use tower_service::Service;
+
+#[derive(Args, Clone)]
+pub(crate) struct TlsArgs {
+    /// PEM file with the CA certificates to trust instead of the system roots
+    #[arg(long)]
+    ca_bundle: Option<PathBuf>,
+    /// PEM client certificate chain presented for mutual TLS
+    #[arg(long, requires = "client_key")]
+    client_cert: Option<PathBuf>,
+    /// PEM private key of --client-cert
+    #[arg(long, requires = "client_cert")]
+    client_key: Option<PathBuf>,
+    /// Accept any server certificate; only for test clusters with self-signed certificates
+    #[arg(long, conflicts_with = "ca_bundle")]
+    insecure_skip_verify: bool,
+}
+
+impl TlsArgs {
+    fn client_config(&self) -> Result<ClientConfig> {
+        let provider = Arc::new(rustls::crypto::aws_lc_rs::default_provider());
+        let builder = ClientConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()
+            .context("Failed to set up TLS")?;
+
+        let builder = if self.insecure_skip_verify {
+            println!("Warning: TLS certificate verification is disabled (--insecure-skip-verify)");
+            builder
+                .dangerous()
+                .with_custom_certificate_verifier(Arc::new(SkipServerVerification(provider)))
+        } else {
+            let mut roots = RootCertStore::empty();
+            match &self.ca_bundle {
+                Some(path) => {
+                    for cert in read_certs(path)? {
+                        roots.add(cert).with_context(|| format!("Invalid CA certificate in {}", path.display()))?;
+                    }
+                }
+                None => {
+                    let native = rustls_native_certs::load_native_certs();
+                    roots.add_parsable_certificates(native.certs);
+                }
+            }
+            builder.with_root_certificates(roots)
+        };
+
+        let config = match (&self.client_cert, &self.client_key) {
+            (Some(cert), Some(key)) => {
+                let key = PrivateKeyDer::from_pem_file(key)
+                    .with_context(|| format!("Failed to read private key from {}", key.display()))?;
+                builder
+                    .with_client_auth_cert(read_certs(cert)?, key)
+                    .context("Invalid client certificate or key")?
+            }
+            _ => builder.with_no_client_auth(),
+        };
+        Ok(config)
+    }
+}
+
+/// HTTP protocol spoken to the endpoints
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub(crate) enum HttpVersion {
+    /// HTTP/1.1, one request at a time per connection
+    Http1,
+    /// HTTP/2 only: negotiated via ALPN over TLS, prior knowledge (h2c) over plain HTTP
+    Http2,
+}
+
+/// Addresses to connect to for one host and port instead of resolving the
+/// name, parsed from curl's `HOST:PORT:ADDR[,ADDR...]` form
+#[derive(Clone, Debug)]
+pub(crate) struct ResolveOverride {
+    host: String,
+    port: u16,
+    addrs: Vec<SocketAddr>,
+}
+
+impl FromStr for ResolveOverride {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let mut fields = s.splitn(3, ':');
+        let (Some(host), Some(port), Some(addrs)) = (fields.next(), fields.next(), fields.next()) else {
+            return Err(format!("expected HOST:PORT:ADDR[,ADDR...], got '{}'", s));
+        };
+        if host.is_empty() {
+            return Err(format!("missing host in '{}'", s));
+        }
+        let port = port.parse::<u16>().map_err(|_| format!("invalid port '{}'", port))?;
+        let addrs = addrs
+            .split(',')
+            .map(|addr| {
+                let addr = addr.trim().trim_start_matches('[').trim_end_matches(']');
+                addr.parse::<IpAddr>()
+                    .map(|ip| SocketAddr::new(ip, port))
+                    .map_err(|_| format!("invalid IP address '{}'", addr))
+            })
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        Ok(ResolveOverride {
+            host: host.to_ascii_lowercase(),
+            port,
+            addrs,
+        })
+    }
+}
+
+impl fmt::Display for ResolveOverride {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let addrs: Vec<String> = self.addrs.iter().map(|addr| addr.ip().to_string()).collect();
+        write!(f, "{}:{} -> {}", self.host, self.port, addrs.join(","))
+    }
+}
+
+/// Connection pool and socket settings of the HTTP client
+#[derive(Args, Clone, Debug)]
+pub(crate) struct HttpArgs {
+    /// Idle connections kept per host for reuse; 0 opens a new connection for every request (default: unlimited)
+    #[arg(long)]
+    max_idle_per_host: Option<usize>,
+    /// How long an idle connection stays in the pool before it is closed
+    #[arg(long, default_value = "90s", value_parser = humantime::parse_duration)]
+    idle_timeout: Duration,
+    /// Leave Nagle's algorithm on instead of setting TCP_NODELAY
+    #[arg(long)]
+    no_tcp_nodelay: bool,
+    /// Send TCP keepalive probes after this much idle time (e.g. 30s; default: off)
+    #[arg(long, value_parser = humantime::parse_duration)]
+    tcp_keepalive: Option<Duration>,
+    /// Protocol spoken to the endpoints; http2 multiplexes requests over fewer connections
+    #[arg(long, value_enum, default_value = "http1")]
+    http_version: HttpVersion,
+    /// Connect to these addresses for HOST:PORT instead of resolving it, e.g. s3.example.com:443:10.0.0.5;
+    /// several addresses are used in turn for new connections. Repeatable
+    #[arg(long, value_name = "HOST:PORT:ADDR[,ADDR...]")]
+    resolve: Vec<ResolveOverride>,
+    /// Source IP to bind outgoing connections to; repeat to spread connections over several
+    #[arg(long)]
+    local_address: Vec<IpAddr>,
+}
+
+impl fmt::Display for HttpArgs {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.http_version {
+            HttpVersion::Http1 => write!(f, "HTTP/1.1")?,
+            HttpVersion::Http2 => write!(f, "HTTP/2")?,
+        }
+        match self.max_idle_per_host {
+            Some(max) => write!(f, ", max {} idle connections per host", max)?,
+            None => write!(f, ", unlimited idle connections per host")?,
+        }
+        write!(f, ", idle timeout {}", humantime::format_duration(self.idle_timeout))?;
+        write!(f, ", TCP_NODELAY {}", if self.no_tcp_nodelay { "off" } else { "on" })?;
+        if let Some(keepalive) = self.tcp_keepalive {
+            write!(f, ", TCP keepalive {}", humantime::format_duration(keepalive))?;
+        }
+        for resolve in &self.resolve {
+            write!(f, ", resolve {}", resolve)?;
+        }
+        if !self.local_address.is_empty() {
+            let addrs: Vec<String> = self.local_address.iter().map(IpAddr::to_string).collect();
+            write!(f, ", source {}", addrs.join(","))?;
+        }
+        Ok(())
+    }
+}
+
+/// Connections opened and requests sent by one HTTP client
+#[derive(Debug, Default)]
+pub(crate) struct ConnectionCounters {
+    opened: AtomicU64,
+    requests: AtomicU64,
+}
+
+/// Connection usage over a run. Requests that did not need a new connection
+/// count as reused; with HTTP/2 that includes requests multiplexed onto a
+/// connection that was busy at the time.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct ConnectionCounts {
+    pub(crate) opened: u64,
+    pub(crate) reused: u64,
+}
+
+impl ConnectionCounts {
+    pub(crate) fn add(&mut self, other: &ConnectionCounts) {
+        self.opened += other.opened;
+        self.reused += other.reused;
+    }
+}
+
+impl ConnectionCounters {
+    /// Raw (opened, requests) totals
+    pub(crate) fn snapshot(&self) -> (u64, u64) {
+        (self.opened.load(Ordering::Relaxed), self.requests.load(Ordering::Relaxed))
+    }
+
+    /// Connection usage since `earlier` was taken
+    pub(crate) fn since(&self, earlier: (u64, u64)) -> ConnectionCounts {
+        let (opened, requests) = self.snapshot();
+        let opened = opened - earlier.0;
+        ConnectionCounts {
+            opened,
+            reused: (requests - earlier.1).saturating_sub(opened),
+        }
+    }
+}
+
+pub(crate) fn read_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>> {
+    let certs = CertificateDer::pem_file_iter(path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .with_context(|| format!("Failed to read certificates from {}", path.display()))?;
+    if certs.is_empty() {
+        anyhow::bail!("No certificates found in {}", path.display());
+    }
+    Ok(certs)
+}
+
+/// Verifier for --insecure-skip-verify: accepts any certificate but still checks
+/// handshake signatures so the connection is encrypted with the presented key
+#[derive(Debug)]
+struct SkipServerVerification(Arc<CryptoProvider>);
+
+impl ServerCertVerifier for SkipServerVerification {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(message, cert, dss, &self.0.signature_verification_algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(message, cert, dss, &self.0.signature_verification_algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+/// Connect and read timeout a connector was built for
+type TimeoutKey = (Option<Duration>, Option<Duration>);
+
+/// SDK `HttpClient` backed by hyper with our TLS and pool configuration. The SDK
+/// asks for a connector per timeout setting; each gets its own connection pool.
+#[derive(Debug)]
+struct HyperClient {
+    tls: ClientConfig,
+    http: HttpArgs,
+    counters: Arc<ConnectionCounters>,
+    connectors: Mutex<HashMap<TimeoutKey, SharedHttpConnector>>,
+}
+
+pub(crate) fn http_client(tls: &TlsArgs, http: &HttpArgs, counters: Arc<ConnectionCounters>) -> Result<SharedHttpClient> {
+    Ok(SharedHttpClient::new(HyperClient {
+        tls: tls.client_config()?,
+        http: http.clone(),
+        counters,
+        connectors: Mutex::new(HashMap::new()),
+    }))
+}
+
+impl HyperClient {
+    fn connector(&self, connect_timeout: Option<Duration>, read_timeout: Option<Duration>) -> HyperConnector {
+        HyperConnector {
+            client: hyper_client(&self.tls, &self.http, &self.counters, connect_timeout),
+            read_timeout,
+            counters: self.counters.clone(),
+        }
+    }
+}
+
+/// hyper client with its own connection pool, configured from the TLS and HTTP settings
+fn hyper_client<B>(
+    tls: &ClientConfig,
+    http: &HttpArgs,
+    counters: &Arc<ConnectionCounters>,
+    connect_timeout: Option<Duration>,
+) -> Client<HttpsConnector<SocketConnector>, B>
+where
+    B: hyper::body::Body + Send + 'static,
+    B::Data: Send,
+{
+    let tcp = |local_address: Option<IpAddr>| {
+        let mut tcp = TcpConnector::new();
+        tcp.enforce_http(false);
+        tcp.set_nodelay(!http.no_tcp_nodelay);
+        tcp.set_keepalive(http.tcp_keepalive);
+        tcp.set_connect_timeout(connect_timeout);
+        tcp.set_local_address(local_address);
+        tcp
+    };
+    let sources = if http.local_address.is_empty() {
+        vec![tcp(None)]
+    } else {
+        http.local_address.iter().map(|&addr| tcp(Some(addr))).collect()
+    };
+    let tcp = SocketConnector {
+        sources,
+        resolve: Arc::new(http.resolve.clone()),
+        next: Arc::new(AtomicUsize::new(0)),
+        counters: counters.clone(),
+    };
+    let https = HttpsConnectorBuilder::new().with_tls_config(tls.clone()).https_or_http();
+    let https = match http.http_version {
+        HttpVersion::Http1 => https.enable_http1().wrap_connector(tcp),
+        HttpVersion::Http2 => https.enable_http2().wrap_connector(tcp),
+    };
+    let mut builder = Client::builder(TokioExecutor::new());
+    builder
+        .pool_timer(TokioTimer::new())
+        .pool_idle_timeout(http.idle_timeout)
+        .http2_only(http.http_version == HttpVersion::Http2);
+    if let Some(max) = http.max_idle_per_host {
+        builder.pool_max_idle_per_host(max);
+    }
+    builder.build(https)
+}
+
+/// HTTP client outside the SDK: no signing, retries or timeouts beyond connecting.
+/// It has its own connection pool but counts connections like the SDK's client.
+#[derive(Clone, Debug)]
+pub(crate) struct PlainClient {
+    client: Client<HttpsConnector<SocketConnector>, Full<Bytes>>,
+    counters: Arc<ConnectionCounters>,
+}
+
+impl PlainClient {
+    pub(crate) fn new(tls: &TlsArgs, http: &HttpArgs, connect_timeout: Option<Duration>, counters: Arc<ConnectionCounters>) -> Result<Self> {
+        Ok(PlainClient {
+            client: hyper_client(&tls.client_config()?, http, &counters, connect_timeout),
+            counters,
+        })
+    }
+
+    pub(crate) async fn send(&self, request: Request<Full<Bytes>>) -> Result<Response<Incoming>> {
+        self.counters.requests.fetch_add(1, Ordering::Relaxed);
+        Ok(self.client.request(request).await?)
+    }
+}
+
+/// TCP connector below TLS: applies --resolve overrides and --local-address
+/// binding, and counts the connections it establishes. TLS and the request
+/// itself still see the original hostname, so SNI, certificate checks, the
+/// Host header and the signature are unaffected by an override.
+#[derive(Clone, Debug)]
+struct SocketConnector {
+    /// One connector per source address, used in turn for new connections
+    sources: Vec<TcpConnector>,
+    resolve: Arc<Vec<ResolveOverride>>,
+    next: Arc<AtomicUsize>,
+    counters: Arc<ConnectionCounters>,
+}
+
+type Connecting<T, E> = Pin<Box<dyn Future<Output = Result<T, E>> + Send>>;
+
+impl SocketConnector {
+    /// The URI to connect to: the original one, or an override address for its host and port
+    fn target(&self, uri: Uri, turn: usize) -> Uri {
+        let Some(host) = uri.host() else { return uri };
+        let port = uri.port_u16().unwrap_or(if uri.scheme_str() == Some("https") { 443 } else { 80 });
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        let Some(resolve) = self
+            .resolve
+            .iter()
+            .find(|resolve| resolve.port == port && resolve.host.eq_ignore_ascii_case(host))
+        else {
+            return uri;
+        };
+        let addr = resolve.addrs[turn % resolve.addrs.len()];
+        let mut parts = uri.into_parts();
+        parts.authority = Some(addr.to_string().parse().expect("socket address is a valid authority"));
+        Uri::from_parts(parts).expect("only the authority was replaced")
+    }
+}
+
+impl Service<Uri> for SocketConnector {
+    type Response = <TcpConnector as Service<Uri>>::Response;
+    type Error = <TcpConnector as Service<Uri>>::Error;
+    type Future = Connecting<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self, _cx: &mut TaskContext<'_>) -> Poll<Result<(), Self::Error>> {
+        // TCP connectors have no backpressure and are always ready
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, uri: Uri) -> Self::Future {
+        let turn = self.next.fetch_add(1, Ordering::Relaxed);
+        let uri = self.target(uri, turn);
+        let source = turn % self.sources.len();
+        let connecting = self.sources[source].call(uri);
+        let counters = self.counters.clone();
+        Box::pin(async move {
+            let connection = connecting.await?;
+            counters.opened.fetch_add(1, Ordering::Relaxed);
+            Ok(connection)
+        })
+    }
+}
+
+impl HttpClient for HyperClient {
+    fn http_connector(&self, settings: &HttpConnectorSettings, _components: &RuntimeComponents) -> SharedHttpConnector {
+        let key = (settings.connect_timeout(), settings.read_timeout());
+        let mut connectors = self.connectors.lock().unwrap();
+        connectors
+            .entry(key)
+            .or_insert_with(|| SharedHttpConnector::new(self.connector(key.0, key.1)))
+            .clone()
+    }
+}
+
+#[derive(Debug)]
+struct HyperConnector {
+    client: Client<HttpsConnector<SocketConnector>, SdkBody>,
+    /// Limit on waiting for the response head once the request is sent, and then
+    /// for each frame of its body
+    read_timeout: Option<Duration>,
+    counters: Arc<ConnectionCounters>,
+}
+
+impl HttpConnector for HyperConnector {
+    fn call(&self, request: HttpRequest) -> HttpConnectorFuture {
+        let request = match request.try_into_http1x() {
+            Ok(request) => request,
+            Err(e) => return HttpConnectorFuture::ready(Err(ConnectorError::user(e.into()))),
+        };
+        let request = match request.extensions().get::<BodySigning>().cloned() {
+            Some(signing) => request.map(|body| SdkBody::from_body_1_x(SigningTimedBody::new(body, signing))),
+            None => request,
+        };
+        self.counters.requests.fetch_add(1, Ordering::Relaxed);
+        let response = self.client.request(request);
+        let read_timeout = self.read_timeout;
+        HttpConnectorFuture::new(async move {
+            let response = match read_timeout {
+                Some(limit) => tokio::time::timeout(limit, response)
+                    .await
+                    .map_err(|e| ConnectorError::timeout(e.into()))?,
+                None => response.await,
+            };
+            let response = response.map_err(classify_error)?;
+            let response = match read_timeout {
+                Some(limit) => response.map(|body| SdkBody::from_body_1_x(IdleTimeoutBody::new(body, limit))),
+                None => response.map(SdkBody::from_body_1_x),
+            };
+            HttpResponse::try_from(response).map_err(|e| ConnectorError::other(e.into(), None))
+        })
+    }
+}
+
+/// Request body as the SDK hands it over, timing the work its layers do per frame
+/// (aws-chunked framing, checksums and chunk signatures) minus generating the data,
+/// and charging it to the operation's signing time once the body is dropped
+struct SigningTimedBody<B> {
+    inner: B,
+    signing: BodySigning,
+    elapsed: Duration,
+}
+
+impl<B> SigningTimedBody<B> {
+    fn new(inner: B, signing: BodySigning) -> Self {
+        SigningTimedBody {
+            inner,
+            signing,
+            elapsed: Duration::ZERO,
+        }
+    }
+}
+
+impl<B> Body for SigningTimedBody<B>
+where
+    B: Body<Data = Bytes> + Unpin,
+{
+    type Data = Bytes;
+    type Error = B::Error;
+
+    fn poll_frame(mut self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<Option<Result<Frame<Bytes>, Self::Error>>> {
+        let this = &mut *self;
+        let generated = generation_time();
+        let started = Instant::now();
+        let frame = Pin::new(&mut this.inner).poll_frame(cx);
+        this.elapsed += started.elapsed().saturating_sub(generation_time() - generated);
+        frame
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+impl<B> Drop for SigningTimedBody<B> {
+    fn drop(&mut self) {
+        if !self.elapsed.is_zero() {
+            self.signing.charge(self.elapsed);
+        }
+    }
+}
+
+/// Response body that fails once no frame has arrived for `limit`, so a body that
+/// stalls after the headers times out too
+struct IdleTimeoutBody<B> {
+    inner: B,
+    limit: Duration,
+    idle: Pin<Box<Sleep>>,
+}
+
+impl<B> IdleTimeoutBody<B> {
+    fn new(inner: B, limit: Duration) -> Self {
+        IdleTimeoutBody {
+            inner,
+            limit,
+            idle: Box::pin(tokio::time::sleep(limit)),
+        }
+    }
+}
+
+impl<B> Body for IdleTimeoutBody<B>
+where
+    B: Body<Data = Bytes> + Unpin,
+    B::Error: Into<Box<dyn Error + Send + Sync>>,
+{
+    type Data = Bytes;
+    type Error = Box<dyn Error + Send + Sync>;
+
+    fn poll_frame(mut self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<Option<Result<Frame<Bytes>, Self::Error>>> {
+        let this = &mut *self;
+        match Pin::new(&mut this.inner).poll_frame(cx) {
+            Poll::Ready(frame) => {
+                let next = tokio::time::Instant::now() + this.limit;
+                this.idle.as_mut().reset(next);
+                Poll::Ready(frame.map(|frame| frame.map_err(Into::into)))
+            }
+            Poll::Pending => match this.idle.as_mut().poll(cx) {
+                Poll::Ready(()) => {
+                    let message = format!("no response data within the {:?} read timeout", this.limit);
+                    Poll::Ready(Some(Err(std::io::Error::new(std::io::ErrorKind::TimedOut, message).into())))
+                }
+                Poll::Pending => Poll::Pending,
+            },
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+/// Map hyper failures to the error kinds the SDK's retry classifier understands
+fn classify_error(err: hyper_util::client::legacy::Error) -> ConnectorError {
+    let mut io_error = None;
+    let mut dropped_connection = false;
+    let mut source: Option<&(dyn Error + 'static)> = err.source();
+    while let Some(e) = source {
+        if let Some(e) = e.downcast_ref::<std::io::Error>() {
+            io_error = Some(e.kind());
+        }
+        if let Some(e) = e.downcast_ref::<hyper::Error>() {
+            dropped_connection |= e.is_closed() || e.is_canceled() || e.is_incomplete_message();
+        }
+        source = e.source();
+    }
+    match io_error {
+        Some(std::io::ErrorKind::TimedOut) => ConnectorError::timeout(err.into()),
+        Some(_) => ConnectorError::io(err.into()),
+        None if err.is_connect() || dropped_connection => ConnectorError::io(err.into()),
+        None => ConnectorError::other(err.into(), None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tls_args(ca_bundle: &str) -> TlsArgs {
+        TlsArgs {
+            ca_bundle: Some(PathBuf::from(ca_bundle)),
+            client_cert: None,
+            client_key: None,
+            insecure_skip_verify: false,
+        }
+    }
+
+    #[tokio::test]
+    async fn read_timeout_bounds_each_body_frame() {
+        use futures::StreamExt;
+        use http_body_util::{BodyExt, StreamBody};
+
+        // One frame, then the body stalls
+        let frames = futures::stream::iter([Ok::<_, std::io::Error>(Frame::data(Bytes::from_static(b"head")))])
+            .chain(futures::stream::pending());
+        let mut body = IdleTimeoutBody::new(StreamBody::new(frames), Duration::from_millis(200));
+        let frame = body.frame().await.unwrap().unwrap();
+        assert_eq!(frame.into_data().unwrap(), Bytes::from_static(b"head"));
+
+        let started = tokio::time::Instant::now();
+        let err = body.frame().await.unwrap().unwrap_err();
+        assert!(started.elapsed() >= Duration::from_millis(200), "{:?}", started.elapsed());
+        assert!(err.to_string().contains("read timeout"), "{}", err);
+    }
+
+    #[test]
+    fn parses_resolve_overrides() {
+        let resolve: ResolveOverride = "S3.example.com:443:10.0.0.5,[::1]".parse().unwrap();
+        assert_eq!((resolve.host.as_str(), resolve.port), ("s3.example.com", 443));
+        assert_eq!(resolve.addrs, ["10.0.0.5:443".parse().unwrap(), "[::1]:443".parse().unwrap()]);
+        assert_eq!(resolve.to_string(), "s3.example.com:443 -> 10.0.0.5,::1");
+
+        for invalid in ["s3.example.com:10.0.0.5", ":443:10.0.0.5", "s3.example.com:https:10.0.0.5", "s3.example.com:443:gateway"] {
+            assert!(invalid.parse::<ResolveOverride>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn rejects_unusable_ca_bundles() {
+        let err = tls_args("/nonexistent/ca.pem").client_config().unwrap_err();
+        assert!(format!("{:#}", err).contains("/nonexistent/ca.pem"), "{:#}", err);
+
+        let empty = std::env::temp_dir().join(format!("s3-load-gen-empty-{}.pem", std::process::id()));
+        std::fs::write(&empty, "").unwrap();
+        let err = tls_args(empty.to_str().unwrap()).client_config().unwrap_err();
+        std::fs::remove_file(&empty).unwrap();
+        assert!(err.to_string().contains("No certificates found"), "{:#}", err);
+    }
+}
@@ -0,0 +1,251 @@
+//! How PUT bodies are produced and signed. By default object data is generated
+//! up front and sent from memory, so the SDK hashes the whole payload for the
+//! SigV4 signature. The streaming modes generate data piece by piece while the
+//! body is sent, the way a log shipper uploads, and sign it either as
+//! `UNSIGNED-PAYLOAD` or with aws-chunked encoding and a trailing checksum.
+
+use super::throttle::{StreamThrottle, Throttle};
+use anyhow::Result;
+use aws_sdk_s3::config::{Builder as ConfigBuilder, RequestChecksumCalculation};
+use aws_sdk_s3::primitives::ByteStream;
+use aws_smithy_types::body::SdkBody;
+use bytes::{Bytes, BytesMut};
+use clap::{Args, ValueEnum};
+use hyper::body::{Body, Frame, SizeHint};
+use rand::RngCore;
+use std::cell::Cell;
+use std::convert::Infallible;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{ready, Context as TaskContext, Poll};
+use std::time::{Duration, Instant};
+use tokio::time::Sleep;
+
+thread_local! {
+    /// Time this thread has spent generating streamed body data. The SDK's body layers
+    /// poll GeneratedBody synchronously, so the HTTP client subtracts the growth of
+    /// this from its timing of a body to isolate chunk signing and checksums.
+    static GENERATING: Cell<Duration> = const { Cell::new(Duration::ZERO) };
+}
+
+/// Total time the current thread has spent generating streamed body data
+pub(crate) fn generation_time() -> Duration {
+    GENERATING.with(Cell::get)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub(crate) enum UploadMode {
+    /// Data generated up front and sent from memory; the payload's SHA-256 is signed
+    Buffered,
+    /// Data generated while it is sent, signed as UNSIGNED-PAYLOAD
+    Unsigned,
+    /// Data generated while it is sent with aws-chunked encoding and a CRC32 trailer;
+    /// the SDK signs each chunk on plain-HTTP endpoints and leaves chunks unsigned over TLS
+    AwsChunked,
+}
+
+/// PUT body production and payload signing
+#[derive(Args, Clone, Debug)]
+pub(crate) struct UploadArgs {
+    /// How PUT bodies are produced and signed
+    #[arg(long, value_enum, default_value = "buffered")]
+    upload_mode: UploadMode,
+    /// Send streamed bodies without a Content-Length (Transfer-Encoding: chunked); only with --upload-mode unsigned.
+    /// S3 itself rejects such PUTs, other servers may not
+    #[arg(long)]
+    unknown_length: bool,
+    /// Size of the pieces streamed bodies are generated in
+    #[arg(long, default_value = "65536")]
+    stream_chunk_size: usize,
+}
+
+impl UploadArgs {
+    pub(crate) fn validate(&self) -> Result<()> {
+        if self.unknown_length && self.upload_mode != UploadMode::Unsigned {
+            anyhow::bail!("--unknown-length requires --upload-mode unsigned: aws-chunked needs the decoded length up front");
+        }
+        if self.stream_chunk_size == 0 {
+            anyhow::bail!("--stream-chunk-size must be positive");
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for UploadArgs {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.upload_mode {
+            UploadMode::Buffered => return write!(f, "buffered, signed SHA-256 payload"),
+            UploadMode::Unsigned => write!(f, "streamed, UNSIGNED-PAYLOAD")?,
+            UploadMode::AwsChunked => write!(f, "streamed, aws-chunked with CRC32 trailer (chunks signed over plain HTTP only)")?,
+        }
+        write!(f, ", generated in {} byte pieces", self.stream_chunk_size)?;
+        if self.unknown_length {
+            write!(f, ", no Content-Length")?;
+        }
+        Ok(())
+    }
+}
+
+/// Object data of one PUT: bytes generated up front, or a length to generate while sending
+pub(crate) enum Payload {
+    Buffered(Bytes),
+    Generated(usize),
+}
+
+impl Payload {
+    pub(crate) fn len(&self) -> usize {
+        match self {
+            Payload::Buffered(data) => data.len(),
+            Payload::Generated(size) => *size,
+        }
+    }
+
+    /// Consecutive pieces of at most `part_size` bytes, for the parts of a multipart upload
+    pub(crate) fn split(&self, part_size: usize) -> Vec<Payload> {
+        match self {
+            Payload::Buffered(data) => (0..data.len())
+                .step_by(part_size)
+                .map(|start| Payload::Buffered(data.slice(start..(start + part_size).min(data.len()))))
+                .collect(),
+            Payload::Generated(size) => (0..*size)
+                .step_by(part_size)
+                .map(|start| Payload::Generated(part_size.min(size - start)))
+                .collect(),
+        }
+    }
+}
+
+/// Builds PUT bodies for one run; cheap to clone into operations
+#[derive(Clone, Debug)]
+pub(crate) struct Uploader {
+    mode: UploadMode,
+    unknown_length: bool,
+    chunk_size: usize,
+    throttle: Throttle,
+}
+
+impl Default for Uploader {
+    fn default() -> Self {
+        Uploader {
+            mode: UploadMode::Buffered,
+            unknown_length: false,
+            chunk_size: 65536,
+            throttle: Throttle::default(),
+        }
+    }
+}
+
+impl Uploader {
+    pub(crate) fn new(args: &UploadArgs, throttle: Throttle) -> Self {
+        Uploader {
+            mode: args.upload_mode,
+            unknown_length: args.unknown_length,
+            chunk_size: args.stream_chunk_size,
+            throttle,
+        }
+    }
+
+    /// Data for an object of `size` bytes; buffered data is generated here, before the request starts
+    pub(crate) fn payload(&self, size: usize) -> Payload {
+        match self.mode {
+            UploadMode::Buffered => {
+                let mut data = vec![0u8; size];
+                rand::thread_rng().fill_bytes(&mut data);
+                Payload::Buffered(Bytes::from(data))
+            }
+            UploadMode::Unsigned | UploadMode::AwsChunked => Payload::Generated(size),
+        }
+    }
+
+    /// Request body for `payload`; every retry attempt of a generated body generates fresh data
+    pub(crate) fn body(&self, payload: Payload) -> ByteStream {
+        match payload {
+            Payload::Buffered(data) => self.throttle.upload_body(data),
+            Payload::Generated(size) => {
+                let uploader = self.clone();
+                ByteStream::new(SdkBody::retryable(move || {
+                    SdkBody::from_body_1_x(GeneratedBody {
+                        remaining: size,
+                        chunk_size: uploader.chunk_size,
+                        exact_length: !uploader.unknown_length,
+                        throttle: uploader.throttle.stream(),
+                        delay: None,
+                    })
+                }))
+            }
+        }
+    }
+
+    /// Config override that keeps the SDK from wrapping unsigned bodies in aws-chunked
+    /// encoding, which it does for streaming bodies whenever it adds a checksum
+    pub(crate) fn config_override(&self) -> Option<ConfigBuilder> {
+        match self.mode {
+            UploadMode::Unsigned => Some(ConfigBuilder::default().request_checksum_calculation(RequestChecksumCalculation::WhenRequired)),
+            UploadMode::Buffered | UploadMode::AwsChunked => None,
+        }
+    }
+}
+
+/// Random upload body produced `chunk_size` bytes at a time, paced by the bandwidth limits
+struct GeneratedBody {
+    remaining: usize,
+    chunk_size: usize,
+    exact_length: bool,
+    throttle: StreamThrottle,
+    delay: Option<Pin<Box<Sleep>>>,
+}
+
+impl Body for GeneratedBody {
+    type Data = Bytes;
+    type Error = Infallible;
+
+    fn poll_frame(mut self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<Option<Result<Frame<Bytes>, Infallible>>> {
+        if let Some(delay) = &mut self.delay {
+            ready!(delay.as_mut().poll(cx));
+            self.delay = None;
+        }
+        if self.remaining == 0 {
+            return Poll::Ready(None);
+        }
+        let length = self.remaining.min(self.chunk_size);
+        let started = Instant::now();
+        let mut chunk = BytesMut::zeroed(length);
+        rand::thread_rng().fill_bytes(&mut chunk);
+        GENERATING.with(|generating| generating.set(generating.get() + started.elapsed()));
+        self.remaining -= length;
+        let wait = self.throttle.reserve(length);
+        if !wait.is_zero() {
+            self.delay = Some(Box::pin(tokio::time::sleep(wait)));
+        }
+        Poll::Ready(Some(Ok(Frame::data(chunk.freeze()))))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.remaining == 0
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        if self.exact_length {
+            SizeHint::with_exact(self.remaining as u64)
+        } else {
+            SizeHint::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_payloads_into_parts() {
+        let lengths = |parts: Vec<Payload>| parts.iter().map(Payload::len).collect::<Vec<_>>();
+        assert_eq!(lengths(Payload::Generated(10).split(4)), [4, 4, 2]);
+        assert_eq!(lengths(Payload::Generated(8).split(4)), [4, 4]);
+        let data = Bytes::from_static(b"0123456789");
+        let parts = Payload::Buffered(data).split(4);
+        assert!(matches!(&parts[2], Payload::Buffered(last) if last.as_ref() == b"89"));
+        assert_eq!(lengths(parts), [4, 4, 2]);
+    }
+}
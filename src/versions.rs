@@ -0,0 +1,435 @@
+//! Versioned-bucket workload. Keys under a prefix are overwritten repeatedly to
+//! build version stacks; then specific versions are read back by version ID,
+//! the stacks are listed with ListObjectVersions, every key is deleted without
+//! a version ID (leaving a delete marker), and finally every version and marker
+//! is deleted by ID. Each phase is measured and reported on its own.
+
+use super::{connect, drive, generate_random_data, ConnectionArgs, OpUnit, RunArgs, Stats};
+use crate::endpoints::EndpointPool;
+use anyhow::{Context, Result};
+use aws_sdk_s3::types::{BucketVersioningStatus, VersioningConfiguration};
+use aws_sdk_s3::Client as S3Client;
+use bytes::{Bytes, BytesMut};
+use clap::Args;
+use rand::Rng;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// S3's limit on entries per ListObjectVersions page
+const MAX_PAGE_SIZE: i32 = 1000;
+
+#[derive(Args)]
+pub(crate) struct VersionsArgs {
+    #[command(flatten)]
+    conn: ConnectionArgs,
+    #[command(flatten)]
+    run: RunArgs,
+    #[arg(long, default_value = "10")]
+    concurrent: usize,
+    /// Keys whose version stacks are built
+    #[arg(long, default_value = "100")]
+    keys: usize,
+    /// Versions written per key
+    #[arg(long, default_value = "10")]
+    versions: usize,
+    /// Size of each version; its first bytes identify the version
+    #[arg(long, default_value = "1024")]
+    object_size: usize,
+    /// Entries per ListObjectVersions page (at most 1000)
+    #[arg(long, default_value = "1000")]
+    page_size: i32,
+    /// Turn versioning on for the bucket instead of failing when it is off
+    #[arg(long)]
+    enable_versioning: bool,
+    #[arg(long, default_value = "versioned/")]
+    prefix: String,
+}
+
+/// What one full ListObjectVersions scan of the prefix returned
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Listing {
+    versions: usize,
+    delete_markers: usize,
+    /// Keys whose latest entry is a delete marker
+    deleted_keys: usize,
+    pages: usize,
+}
+
+/// Measured phases of a run, in order
+pub(crate) struct Phases(pub(crate) Vec<(&'static str, Stats)>);
+
+impl Phases {
+    fn print_summary(&self) {
+        println!("\n=== VERSIONS Summary ===");
+        println!("{:<16} {:>8} {:>8} {:>10} {:>10} {:>10}", "Phase", "Ops", "Errors", "Ops/sec", "p50 ms", "p99 ms");
+        for (label, stats) in &self.0 {
+            println!(
+                "{:<16} {:>8} {:>8} {:>10.2} {:>10.2} {:>10.2}",
+                label,
+                stats.operations,
+                stats.errors,
+                stats.ops_per_sec(),
+                stats.percentile_ms(0.5),
+                stats.percentile_ms(0.99)
+            );
+        }
+    }
+}
+
+pub(crate) struct VersionsWorkload {
+    endpoints: Arc<EndpointPool>,
+    bucket: String,
+    run: RunArgs,
+    keys: usize,
+    versions: usize,
+    object_size: usize,
+    page_size: i32,
+    prefix: String,
+    /// Random data every version is derived from
+    pattern: Bytes,
+    /// Version IDs by key and version number; `None` where the PUT failed
+    stacks: Arc<Mutex<Vec<Vec<Option<String>>>>>,
+    /// Delete marker IDs by key
+    markers: Arc<Mutex<Vec<Option<String>>>>,
+    pub(crate) check_failures: Arc<AtomicU64>,
+}
+
+impl VersionsWorkload {
+    pub(crate) async fn prepare(args: VersionsArgs) -> Result<Self> {
+        if args.keys == 0 || args.versions == 0 {
+            anyhow::bail!("--keys and --versions must be positive");
+        }
+        if !(1..=MAX_PAGE_SIZE).contains(&args.page_size) {
+            anyhow::bail!("--page-size must be between 1 and {}", MAX_PAGE_SIZE);
+        }
+        if args.object_size < 64 {
+            anyhow::bail!("--object-size must be at least 64 bytes");
+        }
+        if args.run.total_bytes.is_some() {
+            anyhow::bail!("--total-bytes is not supported for versions, whose LIST phase counts listed versions; use --ops or --duration-secs");
+        }
+        let endpoints = connect(&args.conn).await?;
+        let bucket = args.conn.bucket;
+
+        let client = endpoints.primary();
+        let versioning = client.get_bucket_versioning().bucket(&bucket).send().await.context("Failed to get bucket versioning")?;
+        if versioning.status() != Some(&BucketVersioningStatus::Enabled) {
+            if !args.enable_versioning {
+                anyhow::bail!("Versioning is not enabled on bucket '{}'; pass --enable-versioning to turn it on", bucket);
+            }
+            println!("Enabling versioning on bucket '{}'...", bucket);
+            client
+                .put_bucket_versioning()
+                .bucket(&bucket)
+                .versioning_configuration(VersioningConfiguration::builder().status(BucketVersioningStatus::Enabled).build())
+                .send()
+                .await
+                .context("Failed to enable bucket versioning")?;
+        }
+        // The listing checks expect exactly the versions this run writes
+        let leftover = scan(client, &bucket, &args.prefix, args.page_size).await?;
+        if leftover.versions + leftover.delete_markers > 0 {
+            anyhow::bail!(
+                "Prefix '{}' already holds {} versions and {} delete markers; use an empty --prefix",
+                args.prefix,
+                leftover.versions,
+                leftover.delete_markers
+            );
+        }
+
+        Ok(VersionsWorkload {
+            endpoints,
+            bucket,
+            run: args.run,
+            keys: args.keys,
+            versions: args.versions,
+            object_size: args.object_size,
+            page_size: args.page_size,
+            prefix: args.prefix,
+            pattern: Bytes::from(generate_random_data(args.object_size)),
+            stacks: Arc::new(Mutex::new(vec![vec![None; args.versions]; args.keys])),
+            markers: Arc::new(Mutex::new(vec![None; args.keys])),
+            check_failures: Arc::default(),
+        })
+    }
+
+    fn print_config(&self) {
+        println!("Version stacks: {} keys x {} versions of {} bytes under '{}'", self.keys, self.versions, self.object_size, self.prefix);
+        println!("ListObjectVersions page size: {}", self.page_size);
+    }
+
+    /// Run every phase; the GET and LIST phases stop on the run's stop condition,
+    /// the others touch each key or version once
+    pub(crate) async fn execute(&self, concurrent: usize) -> Result<Phases> {
+        let mut phases = Vec::new();
+        phases.push(("PUT-VERSION", self.put_versions(concurrent).await?));
+        phases.push(("GET-VERSION", self.get_versions(concurrent).await?));
+        phases.push(("LIST-VERSIONS", self.list_versions(concurrent).await?));
+        phases.push(("DELETE-MARKER", self.delete_keys(concurrent).await?));
+        phases.push(("DELETE-VERSION", self.delete_versions(concurrent).await?));
+        Ok(Phases(phases))
+    }
+
+    /// Stop condition of a phase that issues exactly `ops` operations
+    fn exactly(&self, ops: usize) -> RunArgs {
+        RunArgs {
+            duration_secs: None,
+            ops: Some(ops as u64),
+            total_bytes: None,
+            warmup: None,
+            cooldown: None,
+        }
+    }
+
+    fn key(&self, index: usize) -> String {
+        format!("{}{:06}", self.prefix, index)
+    }
+
+    /// Data of one version: the shared pattern starting with the key and version number
+    fn version_data(&self, key: &str, version: usize) -> Bytes {
+        let stamp = format!("{} v{}\n", key, version);
+        let mut data = BytesMut::from(&self.pattern[..]);
+        let length = stamp.len().min(data.len());
+        data[..length].copy_from_slice(&stamp.as_bytes()[..length]);
+        data.freeze()
+    }
+
+    /// Fail the operation and count the failure when an invariant does not hold
+    fn check(failures: &AtomicU64, holds: bool, what: impl FnOnce() -> String) -> Result<()> {
+        if holds {
+            return Ok(());
+        }
+        let message = what();
+        Self::count_failure(failures, &message);
+        anyhow::bail!("Check failed: {}", message)
+    }
+
+    fn count_failure(failures: &AtomicU64, message: &str) {
+        failures.fetch_add(1, Ordering::Relaxed);
+        println!("[VERSIONS] Check failed: {}", message);
+    }
+
+    /// Overwrite every key `versions` times, cycling through the keys. Writes to
+    /// one key overlap when --concurrent exceeds --keys, so a stack's versions
+    /// may be stored in a different order than they are numbered; no check
+    /// depends on that order.
+    async fn put_versions(&self, concurrent: usize) -> Result<Stats> {
+        let total = self.keys * self.versions;
+        drive("PUT-VERSION", concurrent, self.exactly(total), Duration::from_millis(10), OpUnit::Bytes, &self.endpoints, |operation_count, client| {
+            let (index, version) = (operation_count as usize % self.keys, operation_count as usize / self.keys);
+            let key = self.key(index);
+            let data = self.version_data(&key, version);
+            let bucket = self.bucket.clone();
+            let stacks = self.stacks.clone();
+
+            println!("[BENCH] Spawning PUT-VERSION task {} for key: {} (version {})", operation_count, key, version);
+            let op = async move {
+                let size = data.len();
+                let output = client.put_object().bucket(bucket).key(&key).body(data.into()).send().await.context("Failed to put object")?;
+                let version_id = output.version_id().context("PutObject returned no version ID")?;
+                stacks.lock().unwrap()[index][version] = Some(version_id.to_string());
+                Ok(size)
+            };
+            (self.object_size as u64, op)
+        })
+        .await
+    }
+
+    /// Versions written so far as (key index, version number, version ID)
+    fn written(&self) -> Vec<(usize, usize, String)> {
+        let stacks = self.stacks.lock().unwrap();
+        let mut written = Vec::new();
+        for (index, stack) in stacks.iter().enumerate() {
+            for (version, id) in stack.iter().enumerate() {
+                if let Some(id) = id {
+                    written.push((index, version, id.clone()));
+                }
+            }
+        }
+        written
+    }
+
+    /// GET random versions by ID and check each returns the data written as that version
+    async fn get_versions(&self, concurrent: usize) -> Result<Stats> {
+        let written = Arc::new(self.written());
+        if written.is_empty() {
+            anyhow::bail!("No versions were written; check the PUT-VERSION errors above");
+        }
+        drive("GET-VERSION", concurrent, self.run, Duration::from_millis(10), OpUnit::Bytes, &self.endpoints, |operation_count, client| {
+            let (index, version, version_id) = written[rand::thread_rng().gen_range(0..written.len())].clone();
+            let key = self.key(index);
+            let expected = self.version_data(&key, version);
+            let bucket = self.bucket.clone();
+            let failures = self.check_failures.clone();
+
+            println!("[BENCH] Spawning GET-VERSION task {} for key: {} (version {})", operation_count, key, version);
+            let op = async move {
+                let output = client
+                    .get_object()
+                    .bucket(bucket)
+                    .key(&key)
+                    .version_id(&version_id)
+                    .send()
+                    .await
+                    .context("Failed to get object version")?;
+                let returned = output.version_id().map(str::to_string);
+                let data = output.body.collect().await.context("Failed to read body")?.into_bytes();
+                Self::check(&failures, returned.as_deref() == Some(version_id.as_str()), || {
+                    format!("GET {} version {} answered with version {:?}", key, version_id, returned)
+                })?;
+                Self::check(&failures, data == expected, || format!("GET {} version {} returned other data", key, version_id))?;
+                Ok(data.len())
+            };
+            (self.object_size as u64, op)
+        })
+        .await
+    }
+
+    /// Scan the prefix's versions page by page, checking every written version is listed
+    async fn list_versions(&self, concurrent: usize) -> Result<Stats> {
+        let expected = self.written().len();
+        drive("LIST-VERSIONS", concurrent, self.run, Duration::from_millis(10), OpUnit::Objects, &self.endpoints, |operation_count, client| {
+            let (bucket, prefix, page_size) = (self.bucket.clone(), self.prefix.clone(), self.page_size);
+            let failures = self.check_failures.clone();
+
+            println!("[BENCH] Spawning LIST-VERSIONS task {} for prefix: {}", operation_count, prefix);
+            let op = async move {
+                let listing = scan(&client, &bucket, &prefix, page_size).await?;
+                Self::check(&failures, listing.versions == expected && listing.delete_markers == 0, || {
+                    format!("ListObjectVersions shows {:?}, expected {} versions", listing, expected)
+                })?;
+                Ok(listing.versions)
+            };
+            (expected as u64, op)
+        })
+        .await
+    }
+
+    /// Delete every key without a version ID, which leaves a delete marker on top of its stack
+    async fn delete_keys(&self, concurrent: usize) -> Result<Stats> {
+        let stats = drive("DELETE-MARKER", concurrent, self.exactly(self.keys), Duration::from_millis(10), OpUnit::Objects, &self.endpoints, |operation_count, client| {
+            let index = operation_count as usize;
+            let key = self.key(index);
+            let bucket = self.bucket.clone();
+            let markers = self.markers.clone();
+            let failures = self.check_failures.clone();
+
+            println!("[BENCH] Spawning DELETE-MARKER task {} for key: {}", operation_count, key);
+            let op = async move {
+                let output = client.delete_object().bucket(bucket).key(&key).send().await.context("Failed to delete object")?;
+                Self::check(&failures, output.delete_marker() == Some(true), || format!("DELETE {} did not create a delete marker", key))?;
+                let marker = output.version_id().context("DeleteObject returned no delete marker version ID")?;
+                markers.lock().unwrap()[index] = Some(marker.to_string());
+                Ok(1)
+            };
+            (1, op)
+        })
+        .await?;
+
+        let listing = scan(self.endpoints.primary(), &self.bucket, &self.prefix, self.page_size).await?;
+        let markers = self.markers.lock().unwrap().iter().flatten().count();
+        // A failure here is not any one operation's; count it and carry on with the cleanup
+        if listing.delete_markers != markers || listing.deleted_keys != markers {
+            let message = format!("after deleting keys ListObjectVersions shows {:?}, expected {} delete markers", listing, markers);
+            Self::count_failure(&self.check_failures, &message);
+        }
+        Ok(stats)
+    }
+
+    /// Delete every version and delete marker by ID, leaving the prefix empty
+    async fn delete_versions(&self, concurrent: usize) -> Result<Stats> {
+        let mut targets: Vec<(usize, String)> = self.written().into_iter().map(|(index, _, id)| (index, id)).collect();
+        targets.extend(self.markers.lock().unwrap().iter().enumerate().filter_map(|(index, id)| Some((index, id.clone()?))));
+        let targets = Arc::new(targets);
+
+        let stats = drive("DELETE-VERSION", concurrent, self.exactly(targets.len()), Duration::from_millis(10), OpUnit::Objects, &self.endpoints, |operation_count, client| {
+            let (index, version_id) = targets[operation_count as usize].clone();
+            let key = self.key(index);
+            let bucket = self.bucket.clone();
+            let failures = self.check_failures.clone();
+
+            println!("[BENCH] Spawning DELETE-VERSION task {} for key: {} (version {})", operation_count, key, version_id);
+            let op = async move {
+                let output = client
+                    .delete_object()
+                    .bucket(bucket)
+                    .key(&key)
+                    .version_id(&version_id)
+                    .send()
+                    .await
+                    .context("Failed to delete object version")?;
+                Self::check(&failures, output.version_id() == Some(version_id.as_str()), || {
+                    format!("DELETE {} version {} answered with version {:?}", key, version_id, output.version_id())
+                })?;
+                Ok(1)
+            };
+            (1, op)
+        })
+        .await?;
+
+        let listing = scan(self.endpoints.primary(), &self.bucket, &self.prefix, self.page_size).await?;
+        if listing.versions + listing.delete_markers > 0 {
+            let message = format!("after deleting every version ListObjectVersions still shows {:?}", listing);
+            Self::count_failure(&self.check_failures, &message);
+        }
+        Ok(stats)
+    }
+
+    fn print_summary(&self) {
+        let markers = self.markers.lock().unwrap().iter().flatten().count();
+        println!("Versions written: {}, delete markers: {}", self.written().len(), markers);
+        println!("Check failures: {}", self.check_failures.load(Ordering::Relaxed));
+    }
+}
+
+/// List every version and delete marker under `prefix`, following pagination
+async fn scan(client: &S3Client, bucket: &str, prefix: &str, page_size: i32) -> Result<Listing> {
+    let mut listing = Listing::default();
+    let mut markers: (Option<String>, Option<String>) = (None, None);
+    loop {
+        let page = client
+            .list_object_versions()
+            .bucket(bucket)
+            .prefix(prefix)
+            .max_keys(page_size)
+            .set_key_marker(markers.0.take())
+            .set_version_id_marker(markers.1.take())
+            .send()
+            .await
+            .context("Failed to list object versions")?;
+        listing.pages += 1;
+        listing.versions += page.versions().len();
+        listing.delete_markers += page.delete_markers().len();
+        listing.deleted_keys += page.delete_markers().iter().filter(|marker| marker.is_latest() == Some(true)).count();
+        if page.is_truncated() != Some(true) {
+            return Ok(listing);
+        }
+        markers = (page.next_key_marker().map(str::to_string), page.next_version_id_marker().map(str::to_string));
+    }
+}
+
+pub(crate) async fn run_versions_benchmark(args: VersionsArgs) -> Result<()> {
+    let concurrent = args.concurrent;
+
+    println!("Starting VERSIONS benchmark...");
+    println!("Endpoint: {}", args.conn.describe_endpoints());
+    println!("Client: {}", args.conn.client);
+    println!("HTTP: {}", args.conn.http);
+    println!("Credentials: {}", args.conn.credentials);
+    println!("Bucket: {}", args.conn.bucket);
+    println!("Stop GET and LIST phases after: {}", args.run.resolve());
+    println!("Concurrent operations: {}", concurrent);
+
+    let workload = VersionsWorkload::prepare(args).await?;
+    workload.print_config();
+
+    let phases = workload.execute(concurrent).await?;
+    for (label, stats) in &phases.0 {
+        stats.print(label);
+    }
+    phases.print_summary();
+    workload.print_summary();
+
+    Ok(())
+}